@@ -1,13 +1,262 @@
 use clap::Parser;
+use std::time::Duration;
+
+use crate::events::LogFormat;
+use crate::headers::Header;
+use crate::progress::parse_size;
+use crate::rewrite::RewriteRule;
+use crate::selection::{parse_time, Segments};
 
 #[derive(Debug, Parser)]
 pub struct Args {
-    #[clap(long, short)]
-    pub base_url: String,
+    /// URL that relative URIs in the manifest are resolved against. Defaults
+    /// to where --manifest-url ends up after redirects
+    #[clap(long, short, required_unless_present = "manifest_url")]
+    pub base_url: Option<String>,
 
-    #[clap(long, short)]
-    pub manifest_path: String,
+    #[clap(
+        long,
+        short,
+        required_unless_present_any = ["mirror", "playlists_only", "manifest_url"]
+    )]
+    pub manifest_path: Option<String>,
+
+    /// Fetch the manifest from this URL instead of reading it from
+    /// --manifest-path
+    #[clap(long, conflicts_with = "manifest_path")]
+    pub manifest_url: Option<String>,
 
     #[clap(long, short)]
     pub output_dir: String,
+
+    /// Print the number of files and bytes downloaded, the download rate
+    /// and, once every file is known, an estimate of the time left to
+    /// stderr every few seconds
+    #[clap(long)]
+    pub progress: bool,
+
+    /// With 'json', also write a JSON object to stdout (or --events-file)
+    /// for every download started, completed or failed and every playlist
+    /// refresh, for other tools to follow
+    #[clap(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// File to write JSON events to instead of stdout
+    #[clap(long)]
+    pub events_file: Option<String>,
+
+    /// Number of files to download at once
+    #[clap(long, default_value_t = 4)]
+    pub workers: usize,
+
+    /// Download at most this many files from any one host at once
+    #[clap(long)]
+    pub max_connections_per_host: Option<usize>,
+
+    /// Send a 'Name: value' header with every request, e.g.
+    /// "Authorization: Bearer <token>" or "Referer: <url>". May be given more
+    /// than once
+    #[clap(long = "header", short = 'H', value_name = "HEADER")]
+    pub headers: Vec<Header>,
+
+    /// Send a 'Name: value' header with key requests only, replacing any
+    /// --header of the same name. May be given more than once
+    #[clap(long = "key-header", value_name = "HEADER")]
+    pub key_headers: Vec<Header>,
+
+    /// User-Agent header to send with every request
+    #[clap(long)]
+    pub user_agent: Option<String>,
+
+    /// Send every request through this proxy, e.g. http://proxy:3128 or
+    /// socks5h://localhost:1080. Without it, the HTTP_PROXY, HTTPS_PROXY and
+    /// ALL_PROXY environment variables are honoured
+    #[clap(long)]
+    pub proxy: Option<String>,
+
+    /// Credentials for --proxy, as 'user:password'
+    #[clap(long, requires = "proxy")]
+    pub proxy_auth: Option<String>,
+
+    /// Rewrite outgoing request URLs with a 'regex=>replacement' rule. May be
+    /// given more than once; rules are applied in order
+    #[clap(long)]
+    pub rewrite: Vec<RewriteRule>,
+
+    /// Shell command run before each request to sign it. It receives the
+    /// method and URL as arguments and prints `Name: value` headers to add,
+    /// or a replacement URL
+    #[clap(long)]
+    pub sign_command: Option<String>,
+
+    /// File to load cookies from at startup and save them back to on exit
+    #[clap(long)]
+    pub cookie_jar: Option<String>,
+
+    /// Directory of previously downloaded files to share between runs, e.g.
+    /// when downloading several variants that use the same audio or init
+    /// segments into different output directories
+    #[clap(long)]
+    pub cache_dir: Option<String>,
+
+    /// Keep only one copy of files with identical content, hard linking the
+    /// rest to it and listing them in aliases.txt in the output directory
+    #[clap(long)]
+    pub dedup: bool,
+
+    /// Ignore query strings when checking whether a URL was already queued,
+    /// e.g. when every refresh signs the same key URL with a new token
+    #[clap(long)]
+    pub ignore_query: bool,
+
+    /// Ignore this query parameter when checking whether a URL was already
+    /// queued. May be given more than once
+    #[clap(long, conflicts_with = "ignore_query")]
+    pub ignore_query_param: Vec<String>,
+
+    /// Write the SHA-256 digest of every downloaded file to SHA256SUMS in the
+    /// output directory, for checking with `sha256sum -c`
+    #[clap(long)]
+    pub hash_manifest: bool,
+
+    /// Shell command to pipe each media segment through before it is stored.
+    /// The segment is written to its stdin and its stdout is saved instead;
+    /// the segment URL is available as $SEGMENT_URL. May be given more than
+    /// once to chain commands
+    #[clap(long)]
+    pub process_command: Vec<String>,
+
+    /// Before downloading, estimate the total size with HEAD requests and
+    /// stop if the output directory lacks the space. Checks every file, or
+    /// only the given number of evenly spaced files
+    #[clap(long, num_args = 0..=1, value_name = "SAMPLE")]
+    pub preflight: Option<Option<usize>>,
+
+    /// Write the media to stdout as one in-order stream instead of saving
+    /// files, e.g. to pipe into ffmpeg or a player
+    #[clap(long)]
+    pub stdout: bool,
+
+    /// Skip files already in the output directory, and fetch any that the
+    /// last run queued but didn't download, so an interrupted download can
+    /// be picked up where it left off. Live playlists, including mirrored
+    /// ones, continue from the last segment the run saw.
+    #[clap(long)]
+    pub resume: bool,
+
+    /// With --resume, only skip files whose size matches the Content-Length
+    /// of a HEAD request. Not suitable with --process-command, which changes
+    /// file sizes
+    #[clap(long, requires = "resume")]
+    pub verify_resume: bool,
+
+    /// How many times to try each download before giving up on it. Only
+    /// server errors, timeouts and rate limiting are retried
+    #[clap(long, default_value_t = 4)]
+    pub max_attempts: u32,
+
+    /// Give up after this long (e.g. "45m" or "1h 30m"), abandoning any
+    /// downloads still in progress and reporting what was left undone
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub deadline: Option<Duration>,
+
+    /// How much to download (e.g. "00:05:00" or "2h"). Of a VOD playlist,
+    /// the segments covering this much of its timeline from --start. A live
+    /// playlist from --manifest-url stops being followed after this long;
+    /// without it, live playlists are followed until they end
+    #[clap(long, value_parser = parse_time)]
+    pub duration: Option<Duration>,
+
+    /// Where in a VOD playlist's timeline to start downloading (e.g.
+    /// "00:10:00" or "10m"), going by the segments' durations. Downloads the
+    /// segment playing at that time onwards
+    #[clap(long, value_parser = parse_time, conflicts_with_all = ["start_seq", "segments"])]
+    pub start: Option<Duration>,
+
+    /// Stop recording a live playlist after this long (e.g. "2h"). Files
+    /// already queued are still downloaded, and a --local-playlist is ended
+    /// as for any finished recording
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub record_duration: Option<Duration>,
+
+    /// Stop once this much has been downloaded (e.g. "10G" or "500MiB").
+    /// Files already downloading when the limit is crossed are finished, so
+    /// the total can go over by up to one file per worker
+    #[clap(long, value_parser = parse_size)]
+    pub max_size: Option<u64>,
+
+    /// If the playlist at --base-url is not there yet (404 or 403), keep
+    /// checking for up to this long (e.g. "30m") and start once it appears
+    #[clap(long, value_parser = humantime::parse_duration)]
+    pub wait_for_stream: Option<Duration>,
+
+    /// How often to check for the playlist with --wait-for-stream
+    #[clap(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    pub wait_interval: Duration,
+
+    /// Media sequence number of the first segment to download
+    #[clap(long)]
+    pub start_seq: Option<u64>,
+
+    /// Media sequence number of the last segment to download
+    #[clap(long)]
+    pub end_seq: Option<u64>,
+
+    /// Media sequence numbers of the first and last segments to download,
+    /// e.g. "100-250". Either end may be left off
+    #[clap(long, conflicts_with_all = ["start_seq", "end_seq"])]
+    pub segments: Option<Segments>,
+
+    /// Continuously mirror the playlist at --base-url, along with every
+    /// playlist and segment it references, under their original paths
+    #[clap(long)]
+    pub mirror: bool,
+
+    /// Save the playlist at --base-url and every playlist it references,
+    /// recursively, without downloading any media
+    #[clap(long)]
+    pub playlists_only: bool,
+
+    /// Seconds to wait between playlist refreshes in mirror mode
+    #[clap(long, default_value_t = 5)]
+    pub mirror_interval: u64,
+
+    /// In mirror mode, halve the refresh interval (down to one second)
+    /// whenever segments are missed between refreshes
+    #[clap(long)]
+    pub tighten_interval: bool,
+
+    /// When the manifest is a master playlist, download its highest bandwidth
+    /// variant, along with that variant's audio and subtitle renditions in
+    /// directories of their own
+    #[clap(long)]
+    pub select_variant: bool,
+
+    /// When the manifest is a master playlist, also capture its I-frame
+    /// playlists and their byte range segments into iframes/
+    #[clap(long)]
+    pub iframes: bool,
+
+    /// Prepend the EXT-X-MAP init segment to each downloaded fMP4 fragment,
+    /// writing playable .mp4 files to joined/ in the output directory
+    #[clap(long)]
+    pub join_init: bool,
+
+    /// Also write every media segment, in playlist order, to this one file,
+    /// e.g. out.ts. Segments are appended as soon as every earlier one has
+    /// downloaded, so the file grows during a live recording
+    #[clap(long)]
+    pub output_file: Option<String>,
+
+    /// Write a playlist of the downloaded files to index.m3u8 in the output
+    /// directory, for playing the download back offline. With
+    /// --select-variant, master.m3u8 and a playlist per rendition are
+    /// written too
+    #[clap(long)]
+    pub local_playlist: bool,
+
+    /// Also write an ffmpeg concat demuxer list of the downloaded segments
+    /// to concat.txt in the output directory
+    #[clap(long)]
+    pub concat_list: bool,
 }
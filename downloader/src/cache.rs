@@ -0,0 +1,54 @@
+use sha2::{Digest, Sha256};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::fs::link_or_copy;
+use crate::work_queue::WorkItem;
+
+/// A directory of downloaded files keyed by the hash of their URL and byte
+/// range. Variants downloaded into separate output directories often share
+/// audio renditions and init segments; with a shared cache each of those is
+/// fetched once and then linked into every output directory that needs it.
+pub struct SegmentCache {
+    dir: PathBuf,
+}
+
+impl SegmentCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        let dir = dir.as_ref().to_owned();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    fn entry(&self, work_item: &WorkItem) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(work_item.remote_url.as_str().as_bytes());
+        if let Some(range) = work_item.range {
+            hasher.update(range.header_value().as_bytes());
+        }
+        self.dir.join(hex::encode(hasher.finalize()))
+    }
+
+    /// Place the cached copy of a work item's file at `dest`. Returns false
+    /// if it hasn't been cached yet.
+    pub fn restore(&self, work_item: &WorkItem, dest: &Path) -> io::Result<bool> {
+        let entry = self.entry(work_item);
+        if !entry.exists() {
+            return Ok(false);
+        }
+
+        link_or_copy(&entry, dest)?;
+        log::debug!("restored {} from cache", work_item.remote_url);
+        Ok(true)
+    }
+
+    /// Add a freshly downloaded copy of a work item's file to the cache
+    pub fn insert(&self, work_item: &WorkItem, src: &Path) -> io::Result<()> {
+        let entry = self.entry(work_item);
+        if entry.exists() {
+            return Ok(());
+        }
+
+        link_or_copy(src, &entry)
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+pub const CONCAT_LIST_NAME: &str = "concat.txt";
+
+/// Writer for an ffmpeg concat demuxer script. Segment paths are written
+/// relative to the list itself, so it can be used directly with
+/// `ffmpeg -f concat -i concat.txt -c copy out.mp4`.
+///
+/// Every entry carries its EXTINF duration. ffmpeg lays files out back to back
+/// using these durations, rebasing each file's timestamps to start where the
+/// previous file ended, so timestamps that reset at an EXT-X-DISCONTINUITY
+/// don't break the output. Each run of segments between discontinuities is
+/// marked as a chapter (ffmpeg 5.0 or later), so boundaries such as ad breaks
+/// can still be found after joining.
+pub struct ConcatList {
+    out: BufWriter<File>,
+    /// Time from the start of the list to the end of the last segment, while
+    /// every segment has had a duration
+    elapsed: Option<f64>,
+    /// Start of the current run of segments, and how many came before it
+    section_start: f64,
+    sections: usize,
+}
+
+impl ConcatList {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "ffconcat version 1.0")?;
+        Ok(Self {
+            out,
+            elapsed: Some(0.0),
+            section_start: 0.0,
+            sections: 0,
+        })
+    }
+
+    pub fn add_segment(
+        &mut self,
+        path: &Path,
+        duration: Option<f64>,
+        discontinuity: bool,
+    ) -> io::Result<()> {
+        if discontinuity && self.elapsed.is_some_and(|elapsed| elapsed > 0.0) {
+            self.end_section()?;
+        }
+
+        writeln!(self.out, "file '{}'", escape(path))?;
+        if let Some(duration) = duration {
+            writeln!(self.out, "duration {}", duration)?;
+        }
+        self.elapsed = self.elapsed.zip(duration).map(|(e, d)| e + d);
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        // A list without discontinuities is a single chapter, left unmarked
+        if self.sections > 0 {
+            self.end_section()?;
+        }
+        self.out.flush()
+    }
+
+    /// Mark the segments since the last discontinuity as a chapter
+    fn end_section(&mut self) -> io::Result<()> {
+        let Some(elapsed) = self.elapsed else {
+            return Ok(());
+        };
+        writeln!(
+            self.out,
+            "chapter {} {} {}",
+            self.sections, self.section_start, elapsed
+        )?;
+        self.section_start = elapsed;
+        self.sections += 1;
+        Ok(())
+    }
+}
+
+/// Quote a path for use inside single quotes in a concat script
+fn escape(path: &Path) -> String {
+    path.to_string_lossy().replace('\'', r"'\''")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_list(segments: &[(&str, Option<f64>, bool)]) -> String {
+        let path = std::env::temp_dir().join(format!("concat-{}.txt", std::process::id()));
+        let mut list = ConcatList::create(&path).unwrap();
+        for (segment, duration, discontinuity) in segments {
+            list.add_segment(Path::new(segment), *duration, *discontinuity)
+                .unwrap();
+        }
+        list.finish().unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        text
+    }
+
+    #[test]
+    fn marks_discontinuities_as_chapters() {
+        let text = write_list(&[
+            ("a.ts", Some(6.0), false),
+            ("it's.ts", Some(4.5), false),
+            ("ad.ts", Some(5.0), true),
+            ("b.ts", Some(6.0), true),
+        ]);
+        assert_eq!(
+            text,
+            concat!(
+                "ffconcat version 1.0\n",
+                "file 'a.ts'\nduration 6\n",
+                "file 'it'\\''s.ts'\nduration 4.5\n",
+                "chapter 0 0 10.5\n",
+                "file 'ad.ts'\nduration 5\n",
+                "chapter 1 10.5 15.5\n",
+                "file 'b.ts'\nduration 6\n",
+                "chapter 2 15.5 21.5\n",
+            )
+        );
+    }
+
+    #[test]
+    fn leaves_continuous_lists_unmarked() {
+        let text = write_list(&[("a.ts", Some(6.0), true), ("b.ts", None, false)]);
+        assert_eq!(
+            text,
+            "ffconcat version 1.0\nfile 'a.ts'\nduration 6\nfile 'b.ts'\n"
+        );
+    }
+}
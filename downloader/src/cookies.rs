@@ -0,0 +1,77 @@
+use cookie_store::CookieStore;
+use reqwest::header::HeaderValue;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use url::Url;
+
+/// Cookies shared by every request, loaded from and saved back to a file so
+/// that sessions (e.g. CDN affinity cookies) carry over between runs
+pub struct CookieJar {
+    path: PathBuf,
+    store: Mutex<CookieStore>,
+}
+
+impl CookieJar {
+    /// Load the jar at `path`, starting empty if it doesn't exist yet
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref().to_owned();
+        let store = match File::open(&path) {
+            Ok(file) => CookieStore::load_json(BufReader::new(file))
+                .map_err(|e| format!("failed to load {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => CookieStore::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        log::debug!("loaded {} cookies", store.iter_unexpired().count());
+        let store = Mutex::new(store);
+
+        Ok(Self { path, store })
+    }
+
+    /// Write all unexpired cookies back to the jar. Session cookies are kept
+    /// too, since resuming the session is the point of the jar.
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let store = self.store.lock().unwrap();
+        let mut writer = BufWriter::new(File::create(&self.path)?);
+
+        for cookie in store.iter_unexpired() {
+            writeln!(writer, "{}", serde_json::to_string(cookie)?)?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl reqwest::cookie::CookieStore for CookieJar {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let cookies = cookie_headers
+            .filter_map(|header| header.to_str().ok())
+            .filter_map(|header| cookie::Cookie::parse(header.to_owned()).ok());
+
+        self.store
+            .lock()
+            .unwrap()
+            .store_response_cookies(cookies, url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        let header = self
+            .store
+            .lock()
+            .unwrap()
+            .get_request_values(url)
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+
+        if header.is_empty() {
+            None
+        } else {
+            HeaderValue::from_str(&header).ok()
+        }
+    }
+}
@@ -0,0 +1,88 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::fs::{link_or_copy, write_atomic};
+
+pub const ALIAS_LIST_NAME: &str = "aliases.txt";
+
+/// Stores files by content so that identical files downloaded from different
+/// URLs (ad slates, filler) take up space only once. Later copies are hard
+/// links to the first, and each is recorded in an alias list as
+/// `alias -> original`. The list is replaced whole on every change, so it is
+/// never left half written.
+pub struct Deduplicator {
+    output_dir: PathBuf,
+    seen: Mutex<HashMap<Vec<u8>, PathBuf>>,
+    aliases: Mutex<String>,
+}
+
+impl Deduplicator {
+    pub fn create<P: AsRef<Path>>(output_dir: P) -> io::Result<Self> {
+        let output_dir = output_dir.as_ref().to_owned();
+        std::fs::create_dir_all(&output_dir)?;
+        write_atomic(&output_dir.join(ALIAS_LIST_NAME), b"")?;
+
+        Ok(Self {
+            output_dir,
+            seen: Mutex::new(HashMap::new()),
+            aliases: Mutex::new(String::new()),
+        })
+    }
+
+    /// Store `data` at `path` (relative to the output directory), linking to
+    /// an earlier file if one had the same content
+    pub fn store(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let hash = Sha256::digest(data).to_vec();
+        let dest = self.output_dir.join(path);
+        let original = self.seen.lock().unwrap().get(&hash).cloned();
+
+        match original {
+            Some(original) if original != path => {
+                link_or_copy(&self.output_dir.join(&original), &dest)?;
+                log::debug!(
+                    "{} is a duplicate of {}",
+                    path.display(),
+                    original.display()
+                );
+
+                let mut aliases = self.aliases.lock().unwrap();
+                let alias = format!("{} -> {}\n", path.display(), original.display());
+                aliases.push_str(&alias);
+                write_atomic(&self.output_dir.join(ALIAS_LIST_NAME), aliases.as_bytes())
+            }
+            _ => {
+                write_atomic(&dest, data)?;
+                self.seen
+                    .lock()
+                    .unwrap()
+                    .entry(hash)
+                    .or_insert_with(|| path.to_owned());
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links_duplicates_and_lists_them() {
+        let dir = std::env::temp_dir().join(format!("dedup-{}", std::process::id()));
+        let dedup = Deduplicator::create(&dir).unwrap();
+        dedup.store(Path::new("a.ts"), b"slate").unwrap();
+        dedup.store(Path::new("b.ts"), b"media").unwrap();
+        dedup.store(Path::new("c.ts"), b"slate").unwrap();
+
+        assert_eq!(std::fs::read(dir.join("c.ts")).unwrap(), b"slate");
+        let aliases = std::fs::read_to_string(dir.join(ALIAS_LIST_NAME)).unwrap();
+        assert_eq!(aliases, "c.ts -> a.ts\n");
+        assert!(!dir.join("a.ts.part").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
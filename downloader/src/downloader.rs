@@ -1,68 +1,450 @@
-use crossbeam_deque::Worker;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::io;
+use std::path::Path;
 use std::sync::Arc;
-use std::time::Duration;
+use tokio::time::Instant;
 
-use crate::work_queue::WorkItem;
+use crate::cache::SegmentCache;
+use crate::dedup::Deduplicator;
+use crate::events::{Event, EventLog};
+use crate::hashes::HashManifest;
+use crate::http::{HttpClient, HttpError};
+use crate::join::OrderedOutput;
+use crate::journal::Journal;
+use crate::processor::{process_all, SegmentProcessor};
+use crate::progress::Progress;
+use crate::resume::{self, Resume};
+use crate::retry::RetryPolicy;
+use crate::work_queue::{FileType, WorkItem, WorkQueue};
 
-const RETRY_WAIT_MS: u64 = 500;
+/// Work items that were not downloaded
+#[derive(Debug, Default)]
+pub struct Unfinished {
+    /// Items in flight when the deadline passed, or taken once the size limit
+    /// was reached
+    pub abandoned: Vec<WorkItem>,
+    /// Items that failed on every attempt, with the last error
+    pub failed: Vec<(WorkItem, String)>,
+}
 
 pub struct DownloadWorker {
+    cache: Option<Arc<SegmentCache>>,
+    client: HttpClient,
+    deadline: Option<Instant>,
+    dedup: Option<Arc<Deduplicator>>,
+    events: Option<Arc<EventLog>>,
+    hashes: Option<Arc<HashManifest>>,
+    journal: Option<Arc<Journal>>,
+    max_size: Option<u64>,
+    output: Option<Arc<OrderedOutput>>,
     output_dir: String,
+    processors: Arc<Vec<Box<dyn SegmentProcessor>>>,
+    progress: Option<Arc<Progress>>,
+    resume: Option<Resume>,
+    retry: RetryPolicy,
     worker_count: usize,
 }
 
 impl DownloadWorker {
-    pub fn new(output_dir: String, worker_count: usize) -> Self {
+    pub fn new(client: HttpClient, output_dir: String, worker_count: usize) -> Self {
         Self {
+            cache: None,
+            client,
+            deadline: None,
+            dedup: None,
+            events: None,
+            hashes: None,
+            journal: None,
+            max_size: None,
+            output: None,
             output_dir,
+            processors: Arc::new(Vec::new()),
+            progress: None,
+            resume: None,
+            retry: RetryPolicy::default(),
             worker_count,
         }
     }
 
-    pub async fn run(&mut self, worker: &Worker<WorkItem>, stop: Arc<AtomicBool>) {
+    /// Share downloaded files with other runs through `cache`
+    pub fn with_cache(mut self, cache: SegmentCache) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Run each downloaded file through `processors`, in order, before it is
+    /// stored
+    pub fn with_processors(mut self, processors: Vec<Box<dyn SegmentProcessor>>) -> Self {
+        self.processors = Arc::new(processors);
+        self
+    }
+
+    /// Store files through `dedup` so identical content is kept only once
+    pub fn with_dedup(mut self, dedup: Arc<Deduplicator>) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    /// Record the digest of every file stored in `hashes`
+    pub fn with_hash_manifest(mut self, hashes: Arc<HashManifest>) -> Self {
+        self.hashes = Some(hashes);
+        self
+    }
+
+    /// Report downloads starting, finishing and failing to `events`
+    pub fn with_events(mut self, events: Arc<EventLog>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Record every file stored in `journal`
+    pub fn with_journal(mut self, journal: Arc<Journal>) -> Self {
+        self.journal = Some(journal);
+        self
+    }
+
+    /// Append media segments to `output` as they are stored
+    pub fn with_output(mut self, output: Arc<OrderedOutput>) -> Self {
+        self.output = Some(output);
+        self
+    }
+
+    /// Stop downloading at `deadline`, abandoning any requests still in
+    /// flight. Files are only written once complete, so nothing partial is
+    /// left behind.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Stop taking files from the queue once `progress` has counted
+    /// `max_size` bytes downloaded, and close the queue. Files already
+    /// downloading are finished.
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    /// Count every file stored in `progress`
+    pub fn with_progress(mut self, progress: Arc<Progress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Skip files that an earlier run already downloaded into the output
+    /// directory
+    pub fn with_resume(mut self, resume: Resume) -> Self {
+        self.resume = Some(resume);
+        self
+    }
+
+    /// Retry failed downloads according to `retry`
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Download queued work items until the queue is closed and empty.
+    /// Returns the items that were in flight when the deadline passed, and
+    /// those that could not be downloaded; any not yet started are left in
+    /// the queue.
+    pub async fn run(&mut self, queue: Arc<WorkQueue>) -> Unfinished {
         let mut worker_handles = Vec::with_capacity(self.worker_count);
 
         for _ in 0..self.worker_count {
-            let stealer = worker.stealer();
-            let stop = stop.clone();
+            let queue = queue.clone();
             let output_dir = self.output_dir.clone();
+            let client = self.client.clone();
+            let processors = self.processors.clone();
+            let dedup = self.dedup.clone();
+            let deadline = self.deadline;
+            let max_size = self.max_size;
+            let retry = self.retry;
+            let resume = self.resume;
+            let cache = self.cache.clone();
+            let progress = self.progress.clone();
+            let records = Records {
+                cache: self.cache.clone(),
+                events: self.events.clone(),
+                hashes: self.hashes.clone(),
+                journal: self.journal.clone(),
+                output: self.output.clone(),
+                progress: self.progress.clone(),
+            };
 
             let task = tokio::spawn(async move {
+                let expired = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+                let full = || {
+                    let progress = progress.as_ref();
+                    max_size
+                        .zip(progress)
+                        .is_some_and(|(max, progress)| progress.bytes() >= max)
+                };
+                let mut unfinished = Unfinished::default();
+
                 loop {
-                    match stealer.steal() {
-                        crossbeam_deque::Steal::Empty => {
-                            tokio::time::sleep(Duration::from_millis(RETRY_WAIT_MS)).await;
-                            if stop.load(Ordering::Relaxed) {
-                                break;
+                    let work_item = match deadline {
+                        Some(deadline) => {
+                            match tokio::time::timeout_at(deadline, queue.next()).await {
+                                Ok(work_item) => work_item,
+                                Err(_) => break,
                             }
                         }
-                        crossbeam_deque::Steal::Retry => {
-                            log::warn!("failed to read from the download queue. retrying...");
-                            tokio::time::sleep(Duration::from_millis(RETRY_WAIT_MS)).await;
-                        }
-                        crossbeam_deque::Steal::Success(work_item) => {
-                            let res = reqwest::get(work_item.remote_url.as_str()).await.unwrap();
+                        None => queue.next().await,
+                    };
+                    let Some(work_item) = work_item else {
+                        break;
+                    };
+                    if expired() || full() {
+                        unfinished.abandoned.push(work_item);
+                        break;
+                    }
 
-                            if !res.status().is_success() {
-                                panic!("oh noes {} -> {:?}", res.url(), res.status());
+                    let relative_path = crate::fs::relative_path(&work_item);
+                    let path = Path::new(&output_dir).join(&relative_path);
+
+                    let restored = crate::fs::mkdirp(output_dir.as_str(), &work_item).and_then(
+                        |()| match cache.as_ref() {
+                            Some(cache) => cache.restore(&work_item, &path),
+                            None => Ok(false),
+                        },
+                    );
+                    let in_place = match (restored, resume) {
+                        (Ok(true), _) => true,
+                        (Ok(false), Some(resume)) => {
+                            let complete =
+                                resume::is_complete(&client, &work_item, &path, resume).await;
+                            if complete {
+                                log::debug!("{} is already downloaded", path.display());
                             }
+                            complete
+                        }
+                        (Ok(false), None) => false,
+                        (Err(e), _) => {
+                            let error = format!("{}: {}", path.display(), e);
+                            records.give_up(work_item, error, &mut unfinished);
+                            continue;
+                        }
+                    };
+                    if in_place {
+                        if let Err(e) = records.existing(&work_item, &relative_path, &path) {
+                            let error = format!("{}: {}", path.display(), e);
+                            records.give_up(work_item, error, &mut unfinished);
+                        }
+                        continue;
+                    }
 
-                            let body = res.bytes().await.unwrap();
-                            log::debug!("{:?}", body);
+                    let started = Instant::now();
+                    if let Some(events) = records.events.as_ref() {
+                        if !matches!(work_item.file_type, FileType::Key) {
+                            events.emit(Event::SegmentStarted {
+                                url: work_item.remote_url.as_str(),
+                                file_type: work_item.file_type,
+                            });
+                        }
+                    }
 
-                            crate::fs::mkdirp(output_dir.as_str(), &work_item).unwrap();
-                            std::fs::write(work_item.local_path, body).unwrap();
+                    let body = match deadline {
+                        Some(deadline) => {
+                            let download = fetch(&client, &work_item, retry);
+                            match tokio::time::timeout_at(deadline, download).await {
+                                Ok(body) => body,
+                                Err(_) => {
+                                    log::debug!("abandoned {}", work_item.remote_url);
+                                    unfinished.abandoned.push(work_item);
+                                    break;
+                                }
+                            }
+                        }
+                        None => fetch(&client, &work_item, retry).await,
+                    };
+                    let mut body = match body {
+                        Ok(body) => body,
+                        Err(e) => {
+                            records.give_up(work_item, e.to_string(), &mut unfinished);
+                            continue;
                         }
+                    };
+
+                    let downloaded = body.len() as u64;
+
+                    if !processors.is_empty() {
+                        // Processors may block, so keep them off the
+                        // runtime thread
+                        let processors = processors.clone();
+                        let item = work_item.clone();
+                        let processed = tokio::task::spawn_blocking(move || {
+                            process_all(&processors, &item, body)
+                        })
+                        .await;
+                        body = match processed {
+                            Ok(Ok(body)) => body,
+                            Ok(Err(e)) => {
+                                records.give_up(work_item, e.to_string(), &mut unfinished);
+                                continue;
+                            }
+                            Err(e) => {
+                                let error = format!("processing failed: {}", e);
+                                records.give_up(work_item, error, &mut unfinished);
+                                continue;
+                            }
+                        };
+                    }
+
+                    let stored = match dedup.as_ref() {
+                        Some(dedup) => dedup.store(&relative_path, &body),
+                        None => crate::fs::write_atomic(&path, &body),
+                    }
+                    .and_then(|()| {
+                        records.stored(&work_item, &relative_path, &path, &body, downloaded)
+                    });
+                    if let Err(e) = stored {
+                        let error = format!("{}: {}", path.display(), e);
+                        records.give_up(work_item, error, &mut unfinished);
+                        continue;
+                    }
+                    if full() {
+                        log::info!("size limit reached; no longer downloading");
+                        queue.close();
+                    }
+
+                    if let Some(events) = records.events.as_ref() {
+                        let elapsed = started.elapsed();
+                        events.emit(Event::stored(
+                            &work_item,
+                            &relative_path,
+                            downloaded,
+                            elapsed,
+                        ));
                     }
                 }
+
+                unfinished
             });
 
             worker_handles.push(task);
         }
 
+        let mut unfinished = Unfinished::default();
         for handle in worker_handles {
-            handle.await.unwrap();
+            match handle.await {
+                Ok(task) => {
+                    unfinished.abandoned.extend(task.abandoned);
+                    unfinished.failed.extend(task.failed);
+                }
+                Err(e) => log::error!("a download worker stopped: {}", e),
+            }
+        }
+        unfinished
+    }
+}
+
+/// Everything told about a file once it is in the output directory, or
+/// once it is given up on
+struct Records {
+    cache: Option<Arc<SegmentCache>>,
+    events: Option<Arc<EventLog>>,
+    hashes: Option<Arc<HashManifest>>,
+    journal: Option<Arc<Journal>>,
+    output: Option<Arc<OrderedOutput>>,
+    progress: Option<Arc<Progress>>,
+}
+
+impl Records {
+    /// Record a file that was already in place, either restored from the
+    /// cache or left by an earlier run
+    fn existing(&self, work_item: &WorkItem, relative_path: &Path, path: &Path) -> io::Result<()> {
+        if let Some(hashes) = self.hashes.as_ref() {
+            hashes.add(relative_path, &std::fs::read(path)?)?;
+        }
+        self.completed(work_item, relative_path, 0)
+    }
+
+    /// Record a file that was just stored at `path`, `downloaded` bytes of
+    /// it fetched
+    fn stored(
+        &self,
+        work_item: &WorkItem,
+        relative_path: &Path,
+        path: &Path,
+        body: &[u8],
+        downloaded: u64,
+    ) -> io::Result<()> {
+        if let Some(hashes) = self.hashes.as_ref() {
+            hashes.add(relative_path, body)?;
+        }
+        if let Some(cache) = self.cache.as_ref() {
+            cache.insert(work_item, path)?;
+        }
+        self.completed(work_item, relative_path, downloaded)
+    }
+
+    fn completed(
+        &self,
+        work_item: &WorkItem,
+        relative_path: &Path,
+        downloaded: u64,
+    ) -> io::Result<()> {
+        if let Some(progress) = self.progress.as_ref() {
+            progress.completed(downloaded);
+        }
+        if let Some(journal) = self.journal.as_ref() {
+            journal.completed(work_item);
+        }
+        if let Some(output) = self.output.as_ref() {
+            output.finished(relative_path, true)?;
+        }
+        Ok(())
+    }
+
+    /// Record a work item that could not be downloaded, processed or stored
+    fn give_up(&self, work_item: WorkItem, error: String, unfinished: &mut Unfinished) {
+        log::error!("giving up on {}: {}", work_item.remote_url, error);
+        if let Some(output) = self.output.as_ref() {
+            let path = crate::fs::relative_path(&work_item);
+            if let Err(e) = output.finished(&path, false) {
+                log::error!("could not write to the output file: {}", e);
+            }
+        }
+        if let Some(events) = self.events.as_ref() {
+            events.emit(Event::Error {
+                url: work_item.remote_url.as_str(),
+                message: &error,
+            });
+        }
+        unfinished.failed.push((work_item, error));
+    }
+}
+
+/// Download a work item, retrying failures as `retry` allows
+async fn fetch(
+    client: &HttpClient,
+    work_item: &WorkItem,
+    retry: RetryPolicy,
+) -> Result<Vec<u8>, HttpError> {
+    let mut attempt = 1;
+    loop {
+        let body = match work_item.file_type {
+            FileType::Key => client.fetch_key(&work_item.remote_url).await,
+            _ => {
+                client
+                    .fetch_range(&work_item.remote_url, work_item.range)
+                    .await
+            }
+        };
+        match body {
+            Ok(body) => return Ok(body.to_vec()),
+            Err(e) if attempt < retry.max_attempts && retry.should_retry(&e) => {
+                let delay = retry.delay(attempt);
+                log::warn!(
+                    "{} failed ({}); retrying in {:?}",
+                    work_item.remote_url,
+                    e,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
         }
     }
 }
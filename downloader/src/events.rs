@@ -0,0 +1,107 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::work_queue::{FileType, WorkItem};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Log messages on stderr only
+    #[default]
+    Text,
+    /// Also emit one JSON object per event, for other tools to follow
+    Json,
+}
+
+/// Something that happened during a download, as written by an [`EventLog`]
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    SegmentStarted {
+        url: &'a str,
+        file_type: FileType,
+    },
+    SegmentCompleted {
+        url: &'a str,
+        file_type: FileType,
+        path: &'a Path,
+        bytes: u64,
+        duration_ms: u128,
+    },
+    KeyFetched {
+        url: &'a str,
+        path: &'a Path,
+        bytes: u64,
+    },
+    PlaylistRefreshed {
+        url: &'a str,
+        ended: bool,
+    },
+    Error {
+        url: &'a str,
+        message: &'a str,
+    },
+}
+
+impl<'a> Event<'a> {
+    /// The event for a file having been stored, `bytes` of it downloaded
+    /// over `elapsed`
+    pub fn stored(work_item: &'a WorkItem, path: &'a Path, bytes: u64, elapsed: Duration) -> Self {
+        let url = work_item.remote_url.as_str();
+        match work_item.file_type {
+            FileType::Key => Self::KeyFetched { url, path, bytes },
+            file_type => Self::SegmentCompleted {
+                url,
+                file_type,
+                path,
+                bytes,
+                duration_ms: elapsed.as_millis(),
+            },
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Timestamped<'a> {
+    time: String,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Machine-readable record of a download, one JSON object per line
+pub struct EventLog {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl EventLog {
+    pub fn stdout() -> Self {
+        Self {
+            out: Mutex::new(Box::new(io::stdout())),
+        }
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            out: Mutex::new(Box::new(File::create(path)?)),
+        })
+    }
+
+    pub fn emit(&self, event: Event) {
+        let time = humantime::format_rfc3339_millis(SystemTime::now()).to_string();
+        let mut line = serde_json::to_string(&Timestamped {
+            time,
+            event: &event,
+        })
+        .unwrap();
+        line.push('\n');
+
+        let mut out = self.out.lock().unwrap();
+        if let Err(e) = out.write_all(line.as_bytes()).and_then(|_| out.flush()) {
+            log::warn!("failed to write an event: {}", e);
+        }
+    }
+}
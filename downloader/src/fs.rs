@@ -1,6 +1,6 @@
 use lazy_static::lazy_static;
 use std::collections::HashSet;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use url::{ParseError, Url};
 
@@ -31,10 +31,8 @@ pub fn mkdirp(output_dir: &str, work_item: &WorkItem) -> std::io::Result<()> {
         static ref MKDIR_CACHE: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
     }
 
-    let local_base = local_base_dir(work_item);
-    let path = Path::new(output_dir)
-        .join(local_base)
-        .join(work_item.local_path.as_path().parent().unwrap());
+    let file_path = Path::new(output_dir).join(relative_path(work_item));
+    let path = file_path.parent().unwrap();
 
     debug_assert!(path.starts_with(output_dir));
 
@@ -42,15 +40,57 @@ pub fn mkdirp(output_dir: &str, work_item: &WorkItem) -> std::io::Result<()> {
 
     if !cache.contains(path.to_str().unwrap()) {
         log::debug!("mkdirp {:?}", path);
-        std::fs::create_dir_all(path.clone())?;
+        std::fs::create_dir_all(path)?;
         cache.insert(path.to_str().unwrap().to_owned());
     }
 
     Ok(())
 }
 
+/// Location of a work item's file, relative to the output directory
+pub fn relative_path(work_item: &WorkItem) -> PathBuf {
+    let path = Path::new(local_base_dir(work_item)).join(&work_item.local_path);
+    match work_item.range {
+        Some(range) => range_path(&path, range.offset, range.length),
+        None => path,
+    }
+}
+
+/// Local file name for a byte range of a remote resource, so several ranges
+/// of one file can be stored side by side
+pub fn range_path(path: &Path, offset: u64, length: u64) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}-{}", offset, length));
+    path.with_file_name(name)
+}
+
+/// Write a file under a temporary name and then move it into place, so an
+/// interrupted run never leaves a partial file behind
+pub fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut partial = path.as_os_str().to_os_string();
+    partial.push(".part");
+    std::fs::write(&partial, data)?;
+    std::fs::rename(&partial, path)
+}
+
+/// Hard link `src` to `dest`, falling back to a copy when the two are on
+/// different filesystems
+pub fn link_or_copy(src: &Path, dest: &Path) -> std::io::Result<()> {
+    if dest.exists() {
+        std::fs::remove_file(dest)?;
+    }
+
+    if std::fs::hard_link(src, dest).is_err() {
+        std::fs::copy(src, dest)?;
+    }
+
+    Ok(())
+}
+
 fn local_base_dir(work_item: &WorkItem) -> &'static str {
     match work_item.file_type {
+        FileType::IFrame => "iframes",
+        FileType::InitSegment => "init",
         FileType::Key => "keys",
         FileType::MediaSegment => "segments",
     }
@@ -0,0 +1,31 @@
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+pub const HASH_MANIFEST_NAME: &str = "SHA256SUMS";
+
+/// A list of SHA-256 digests for every downloaded file, in the format read by
+/// `sha256sum -c`. Paths are relative to the output directory, so the archive
+/// can be checked from there after being moved.
+pub struct HashManifest {
+    out: Mutex<BufWriter<File>>,
+}
+
+impl HashManifest {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let out = Mutex::new(BufWriter::new(File::create(path)?));
+        Ok(Self { out })
+    }
+
+    pub fn add(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let hash = hex::encode(Sha256::digest(data));
+        let mut out = self.out.lock().unwrap();
+        writeln!(out, "{}  {}", hash, path.display())
+    }
+
+    pub fn finish(&self) -> io::Result<()> {
+        self.out.lock().unwrap().flush()
+    }
+}
@@ -0,0 +1,67 @@
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::str::FromStr;
+
+/// A `Name: value` request header given on the command line
+#[derive(Clone, Debug)]
+pub struct Header {
+    name: HeaderName,
+    value: HeaderValue,
+}
+
+impl FromStr for Header {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected 'Name: value', got '{}'", s))?;
+        let name = HeaderName::from_str(name.trim()).map_err(|e| e.to_string())?;
+        let value = HeaderValue::from_str(value.trim()).map_err(|e| e.to_string())?;
+
+        Ok(Self { name, value })
+    }
+}
+
+/// Collect headers into a map. A header given more than once is sent with
+/// each of its values.
+pub fn header_map(headers: &[Header]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for header in headers {
+        map.append(header.name.clone(), header.value.clone());
+    }
+    map
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_headers() {
+        let header: Header = "Referer: https://example.com/ ".parse().unwrap();
+        assert_eq!(header.name, "referer");
+        assert_eq!(header.value, "https://example.com/");
+
+        let header: Header = "X-Token:a:b".parse().unwrap();
+        assert_eq!(header.value, "a:b");
+    }
+
+    #[test]
+    fn rejects_bad_headers() {
+        assert!("Referer".parse::<Header>().is_err());
+        assert!(": value".parse::<Header>().is_err());
+        assert!("Bad Name: value".parse::<Header>().is_err());
+        assert!("Name: line\nbreak".parse::<Header>().is_err());
+    }
+
+    #[test]
+    fn keeps_repeated_headers() {
+        let headers: Vec<Header> = ["Cookie: a=1", "Cookie: b=2", "Origin: x"]
+            .iter()
+            .map(|header| header.parse().unwrap())
+            .collect();
+        let map = header_map(&headers);
+        assert_eq!(map.get_all("cookie").iter().count(), 2);
+        assert_eq!(map.len(), 3);
+    }
+}
@@ -0,0 +1,201 @@
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, CONTENT_LENGTH, RANGE};
+use reqwest::{Client, Proxy, RequestBuilder, Response, StatusCode};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+use url::Url;
+
+use crate::cookies::CookieJar;
+use crate::rewrite::{rewrite_url, RewriteRule};
+use crate::sign::{RequestSigner, SignError};
+use crate::work_queue::ByteRange;
+
+#[derive(Debug)]
+pub enum HttpError {
+    Request(reqwest::Error),
+    Sign(SignError),
+}
+
+impl Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Request(e) => e.fmt(f),
+            Self::Sign(e) => write!(f, "failed to sign request: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+impl From<reqwest::Error> for HttpError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Request(e)
+    }
+}
+
+/// Shared HTTP client used for every outgoing request, so that options which
+/// apply to all requests only need to be configured in one place
+#[derive(Clone)]
+pub struct HttpClient {
+    client: Client,
+    rewrites: Arc<Vec<RewriteRule>>,
+    signer: Option<Arc<dyn RequestSigner>>,
+    host_limits: Option<Arc<HostLimits>>,
+    headers: Arc<HeaderMap>,
+    key_headers: Arc<HeaderMap>,
+}
+
+/// Caps how many downloads run against each host at once
+struct HostLimits {
+    per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    fn semaphore(&self, url: &Url) -> Arc<Semaphore> {
+        let host = url.host_str().unwrap_or_default().to_owned();
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone()
+    }
+}
+
+impl HttpClient {
+    /// Without a `proxy`, the proxies in the HTTP_PROXY, HTTPS_PROXY and
+    /// ALL_PROXY environment variables are used
+    pub fn new(
+        rewrites: Vec<RewriteRule>,
+        cookie_jar: Option<Arc<CookieJar>>,
+        proxy: Option<Proxy>,
+    ) -> Self {
+        let mut builder = Client::builder();
+        if let Some(jar) = cookie_jar {
+            builder = builder.cookie_provider(jar);
+        }
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(proxy);
+        }
+
+        Self {
+            client: builder.build().unwrap(),
+            rewrites: Arc::new(rewrites),
+            signer: None,
+            host_limits: None,
+            headers: Arc::new(HeaderMap::new()),
+            key_headers: Arc::new(HeaderMap::new()),
+        }
+    }
+
+    pub fn with_signer(mut self, signer: impl RequestSigner + 'static) -> Self {
+        self.signer = Some(Arc::new(signer));
+        self
+    }
+
+    /// Download from at most `max` URLs of any one host at a time
+    pub fn with_max_connections_per_host(mut self, max: usize) -> Self {
+        self.host_limits = Some(Arc::new(HostLimits {
+            per_host: max.max(1),
+            hosts: Mutex::new(HashMap::new()),
+        }));
+        self
+    }
+
+    /// Send `headers` with every request
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = Arc::new(headers);
+        self
+    }
+
+    /// Send `headers` with key requests, in place of any of the same name
+    /// given to [`HttpClient::with_headers`]
+    pub fn with_key_headers(mut self, headers: HeaderMap) -> Self {
+        self.key_headers = Arc::new(headers);
+        self
+    }
+
+    pub fn get(&self, url: &Url) -> RequestBuilder {
+        self.client
+            .get(rewrite_url(&self.rewrites, url))
+            .headers((*self.headers).clone())
+    }
+
+    pub fn head(&self, url: &Url) -> RequestBuilder {
+        self.client
+            .head(rewrite_url(&self.rewrites, url))
+            .headers((*self.headers).clone())
+    }
+
+    pub async fn fetch(&self, url: &Url) -> Result<Response, HttpError> {
+        self.send(self.get(url)).await
+    }
+
+    /// Download a resource, or only `range` of it
+    pub async fn fetch_range(
+        &self,
+        url: &Url,
+        range: Option<ByteRange>,
+    ) -> Result<Bytes, HttpError> {
+        // Held until the whole body has been read
+        let _permit = match self.host_limits.as_ref() {
+            Some(limits) => {
+                let host = rewrite_url(&self.rewrites, url);
+                Some(limits.semaphore(&host).acquire_owned().await.unwrap())
+            }
+            None => None,
+        };
+
+        let mut request = self.get(url);
+        if let Some(range) = range {
+            request = request.header(RANGE, range.header_value());
+        }
+
+        let res = self.send(request).await?.error_for_status()?;
+        let partial = res.status() == StatusCode::PARTIAL_CONTENT;
+        let body = res.bytes().await?;
+
+        // Origins that ignore Range send the whole resource back
+        match range {
+            Some(range) if !partial => Ok(range.slice(body)),
+            _ => Ok(body),
+        }
+    }
+
+    /// Download a decryption key
+    pub async fn fetch_key(&self, url: &Url) -> Result<Bytes, HttpError> {
+        let request = self.get(url).headers((*self.key_headers).clone());
+        let res = self.send(request).await?.error_for_status()?;
+        Ok(res.bytes().await?)
+    }
+
+    /// Size of a resource according to a HEAD request, if the server says
+    pub async fn content_length(&self, url: &Url) -> Result<Option<u64>, HttpError> {
+        let res = self.send(self.head(url)).await?.error_for_status()?;
+        Ok(res
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok()))
+    }
+
+    /// Sign (if configured) and send a request built with [`HttpClient::get`] or
+    /// [`HttpClient::head`]
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, HttpError> {
+        let mut request = request.build()?;
+
+        if let Some(signer) = self.signer.clone() {
+            // Signers may block (e.g. by running a command), so keep them off
+            // the runtime thread
+            request =
+                tokio::task::spawn_blocking(move || signer.sign(&mut request).map(|_| request))
+                    .await
+                    .map_err(|e| HttpError::Sign(e.into()))?
+                    .map_err(HttpError::Sign)?;
+        }
+
+        Ok(self.client.execute(request).await?)
+    }
+}
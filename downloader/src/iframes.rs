@@ -0,0 +1,90 @@
+use hls::{Line, Tag};
+use std::error::Error;
+use std::path::Path;
+use url::Url;
+
+use crate::fs;
+use crate::hashes::HashManifest;
+use crate::http::HttpClient;
+use crate::work_queue::{ByteRange, FileType, RangeTracker};
+
+/// Download every I-frame playlist referenced by a master playlist, along with
+/// the byte ranges of media that make up its I-frames. Everything is stored
+/// under `iframes/` in the output directory so trick play assets can be
+/// generated offline.
+pub async fn capture(
+    client: &HttpClient,
+    base_url: &Url,
+    master: &str,
+    output_dir: &str,
+    hashes: Option<&HashManifest>,
+) -> Result<(), Box<dyn Error>> {
+    let mut playlists = Vec::new();
+    hls::for_each(master, |line: Line| {
+        if let Line::Tag(Tag::IFrameStreamInf(attrs)) = line {
+            playlists.push(attrs.uri);
+        }
+    })?;
+
+    for uri in playlists {
+        let playlist = fs::parse_path_from_url(base_url, &uri, FileType::IFrame)?;
+        log::info!("capturing I-frame playlist {}", playlist.remote_url);
+
+        let text = client
+            .fetch(&playlist.remote_url)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        save(
+            output_dir,
+            &fs::relative_path(&playlist),
+            text.as_bytes(),
+            hashes,
+        )?;
+
+        for (segment, range) in segments(&playlist.remote_url, &text)? {
+            let work_item =
+                fs::parse_path_from_url(&playlist.remote_url, &segment, FileType::IFrame)?
+                    .with_range(range);
+            let body = client.fetch_range(&work_item.remote_url, range).await?;
+            save(output_dir, &fs::relative_path(&work_item), &body, hashes)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Segment URIs of an I-frame playlist paired with their byte ranges
+fn segments(url: &Url, playlist: &str) -> Result<Vec<(String, Option<ByteRange>)>, hls::Error> {
+    let mut segments = Vec::new();
+    let mut range = None;
+    let mut ranges = RangeTracker::default();
+
+    hls::for_each(playlist, |line: Line| match line {
+        Line::Tag(Tag::Byterange(r)) => range = Some(r),
+        Line::Uri(uri) => {
+            let resolved = range.take().map(|range| ranges.resolve(&uri, range));
+            segments.push((uri, resolved));
+        }
+        _ => {}
+    })?;
+
+    log::debug!("{} I-frame segments in {}", segments.len(), url);
+    Ok(segments)
+}
+
+fn save(
+    output_dir: &str,
+    path: &Path,
+    data: &[u8],
+    hashes: Option<&HashManifest>,
+) -> std::io::Result<()> {
+    if let Some(hashes) = hashes {
+        hashes.add(path, data)?;
+    }
+
+    let path = Path::new(output_dir).join(path);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, data)
+}
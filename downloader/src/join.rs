@@ -0,0 +1,214 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+pub const JOINED_DIR: &str = "joined";
+
+/// A downloaded fMP4 fragment along with the EXT-X-MAP init segment it
+/// depends on. Both paths are relative to the output directory.
+#[derive(Debug)]
+pub struct Fragment {
+    pub segment: PathBuf,
+    pub init: PathBuf,
+}
+
+/// Write an independently playable `.mp4` for every fragment by prepending
+/// its init segment. Output files are placed under `joined/`, mirroring the
+/// layout of the downloaded segments.
+pub fn join_init_segments(output_dir: &str, fragments: &[Fragment]) -> io::Result<()> {
+    let output_dir = Path::new(output_dir);
+
+    for fragment in fragments {
+        let init = std::fs::read(output_dir.join(&fragment.init))?;
+        let media = std::fs::read(output_dir.join(&fragment.segment))?;
+
+        let path = joined_path(output_dir, &fragment.segment);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+
+        log::debug!("joining {:?} + {:?}", fragment.init, fragment.segment);
+        let mut out = File::create(path)?;
+        out.write_all(&init)?;
+        out.write_all(&media)?;
+    }
+
+    Ok(())
+}
+
+/// A single output file that segments are appended to in playlist order,
+/// whatever order they download in. Each segment is written as soon as it and
+/// every segment listed before it have finished, so a live recording grows as
+/// it goes and an interrupted run keeps what it had. A segment's init segment,
+/// if it has one, goes ahead of it whenever it differs from the last one
+/// written.
+pub struct OrderedOutput {
+    output_dir: PathBuf,
+    state: Mutex<OrderedState>,
+}
+
+struct OrderedState {
+    writer: BufWriter<File>,
+    /// Segments not yet written, in playlist order, with their init segments
+    pending: VecDeque<(PathBuf, Option<PathBuf>)>,
+    /// Every segment and init segment listed so far
+    listed: HashSet<PathBuf>,
+    /// Listed files that have finished, and whether they were stored
+    finished: HashMap<PathBuf, bool>,
+    last_init: Option<PathBuf>,
+    missing: usize,
+}
+
+impl OrderedOutput {
+    pub fn create(output_dir: &str, out: &Path) -> io::Result<Self> {
+        let state = OrderedState {
+            writer: BufWriter::new(File::create(out)?),
+            pending: VecDeque::new(),
+            listed: HashSet::new(),
+            finished: HashMap::new(),
+            last_init: None,
+            missing: 0,
+        };
+
+        Ok(Self {
+            output_dir: PathBuf::from(output_dir),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// List the next segment of the playlist. Paths are relative to the
+    /// output directory.
+    pub fn add(&self, segment: PathBuf, init: Option<PathBuf>) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.listed.insert(segment.clone());
+        state.listed.extend(init.clone());
+        state.pending.push_back((segment, init));
+        self.write_ready(&mut state)
+    }
+
+    /// A file finished downloading, or failed to if not `stored`
+    pub fn finished(&self, path: &Path, stored: bool) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if !state.listed.contains(path) {
+            return Ok(());
+        }
+        state.finished.insert(path.to_owned(), stored);
+        self.write_ready(&mut state)
+    }
+
+    /// Write out whatever is left, leaving out segments that weren't
+    /// downloaded. Returns how many were left out over the whole run.
+    pub fn finish(&self) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        while let Some((segment, init)) = state.pending.pop_front() {
+            let stored = self.output_dir.join(&segment).exists();
+            let init = init.filter(|init| self.output_dir.join(init).exists());
+            self.write_segment(&mut state, &segment, init, stored)?;
+        }
+        state.writer.flush()?;
+        Ok(state.missing)
+    }
+
+    /// Write segments from the front of the list for as long as they, and
+    /// the init segments they need, have finished
+    fn write_ready(&self, state: &mut OrderedState) -> io::Result<()> {
+        let mut wrote = false;
+        while let Some((segment, init)) = state.pending.front() {
+            let Some(&stored) = state.finished.get(segment) else {
+                break;
+            };
+            let init = match init {
+                Some(init) => match state.finished.get(init) {
+                    Some(&init_stored) => init_stored.then(|| init.clone()),
+                    None => break,
+                },
+                None => None,
+            };
+
+            let (segment, _) = state.pending.pop_front().unwrap();
+            self.write_segment(state, &segment, init, stored)?;
+            wrote = true;
+        }
+
+        if wrote {
+            state.writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn write_segment(
+        &self,
+        state: &mut OrderedState,
+        segment: &Path,
+        init: Option<PathBuf>,
+        stored: bool,
+    ) -> io::Result<()> {
+        if !stored {
+            log::warn!("{:?} was not downloaded; leaving it out", segment);
+            state.missing += 1;
+            return Ok(());
+        }
+
+        if let Some(init) = init.filter(|init| state.last_init.as_ref() != Some(init)) {
+            let mut file = File::open(self.output_dir.join(&init))?;
+            io::copy(&mut file, &mut state.writer)?;
+            state.last_init = Some(init);
+        }
+        let mut file = File::open(self.output_dir.join(segment))?;
+        io::copy(&mut file, &mut state.writer)?;
+        Ok(())
+    }
+}
+
+fn joined_path(output_dir: &Path, segment: &Path) -> PathBuf {
+    // Drop the leading "segments" directory
+    let segment = segment.iter().skip(1).collect::<PathBuf>();
+    output_dir
+        .join(JOINED_DIR)
+        .join(segment)
+        .with_extension("mp4")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn appends_segments_in_playlist_order() {
+        let dir = std::env::temp_dir().join(format!("join-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output_dir = dir.to_str().unwrap();
+        let out = dir.join("out.ts");
+        let store = |name: &str| {
+            std::fs::write(dir.join(name), name.trim_end_matches(".ts")).unwrap();
+            PathBuf::from(name)
+        };
+        let written = || std::fs::read_to_string(&out).unwrap();
+
+        let output = OrderedOutput::create(output_dir, &out).unwrap();
+        let init = Some(PathBuf::from("init.ts"));
+        for name in ["a.ts", "b.ts", "c.ts", "d.ts"] {
+            output.add(PathBuf::from(name), init.clone()).unwrap();
+        }
+
+        output.finished(&store("b.ts"), true).unwrap();
+        output.finished(&store("a.ts"), true).unwrap();
+        assert_eq!(written(), "", "waits for the init segment");
+        output.finished(&store("init.ts"), true).unwrap();
+        assert_eq!(written(), "initab");
+
+        output.finished(&store("d.ts"), true).unwrap();
+        assert_eq!(written(), "initab");
+        output.finished(Path::new("c.ts"), false).unwrap();
+        assert_eq!(written(), "initabd");
+
+        // A segment listed again is written again once it's there
+        output.add(PathBuf::from("a.ts"), init).unwrap();
+        output.add(PathBuf::from("e.ts"), None).unwrap();
+        assert_eq!(written(), "initabda");
+        assert_eq!(output.finish().unwrap(), 2);
+        assert_eq!(written(), "initabda");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
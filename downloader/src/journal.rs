@@ -0,0 +1,254 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use url::Url;
+
+use crate::work_queue::{ByteRange, FileType, WorkItem};
+
+pub const JOURNAL_NAME: &str = "journal.jsonl";
+
+/// A record of what a run has queued and downloaded, kept in the output
+/// directory so that `--resume` can pick up exactly where an interrupted run
+/// stopped. Files of a live playlist that were queued but not downloaded are
+/// fetched on resume even once they have left the playlist.
+///
+/// Each entry is one line of JSON, written as it happens, so a run that is
+/// killed loses at most the line it was writing. Besides each file queued and
+/// completed, the journal records how far through each media playlist the
+/// run got, so a resumed live recording carries on from there.
+pub struct Journal {
+    file: Mutex<File>,
+}
+
+/// What an earlier run left behind
+#[derive(Debug, Default)]
+pub struct Previous {
+    /// The media playlist it was downloading
+    pub playlist_url: Option<Url>,
+    /// The variant it picked with --select-variant
+    pub variant_url: Option<Url>,
+    /// Files it queued but didn't download, in the order they were queued
+    pub pending: Vec<WorkItem>,
+    /// The media sequence number of the next segment it would have taken
+    /// from each media playlist
+    pub positions: HashMap<Url, u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Entry {
+    Started {
+        playlist_url: String,
+        variant_url: Option<String>,
+    },
+    Queued(Item),
+    Completed(Item),
+    /// Every segment of the playlist before `next_sequence` has been queued
+    Reached {
+        playlist_url: String,
+        next_sequence: u64,
+    },
+}
+
+#[derive(Deserialize, Serialize)]
+struct Item {
+    url: String,
+    path: PathBuf,
+    file_type: FileType,
+    range: Option<ByteRange>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+}
+
+impl Item {
+    fn key(&self) -> (String, Option<(u64, u64)>) {
+        (self.url.clone(), self.range.map(|r| (r.offset, r.length)))
+    }
+}
+
+impl From<&WorkItem> for Item {
+    fn from(work_item: &WorkItem) -> Self {
+        Self {
+            url: work_item.remote_url.to_string(),
+            path: work_item.local_path.clone(),
+            file_type: work_item.file_type,
+            range: work_item.range,
+            sequence: work_item.sequence,
+        }
+    }
+}
+
+impl Journal {
+    /// Start a journal in `output_dir`, adding to any existing one when
+    /// resuming and replacing it otherwise
+    pub fn create(output_dir: &str, resume: bool) -> std::io::Result<Self> {
+        std::fs::create_dir_all(output_dir)?;
+        let path = Path::new(output_dir).join(JOURNAL_NAME);
+        // A run killed while writing leaves its last line cut short
+        let cut_short = resume
+            && std::fs::read(&path).is_ok_and(|data| data.last().is_some_and(|b| *b != b'\n'));
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resume)
+            .truncate(!resume)
+            .open(&path)?;
+        if cut_short {
+            file.write_all(b"\n")?;
+        }
+        let file = Mutex::new(file);
+
+        Ok(Self { file })
+    }
+
+    /// Read back the journal of an earlier run in `output_dir`, if there is
+    /// one
+    pub fn previous(output_dir: &str) -> Result<Previous, Box<dyn Error>> {
+        match File::open(Path::new(output_dir).join(JOURNAL_NAME)) {
+            Ok(file) => read_journal(file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Previous::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn started(&self, playlist_url: &Url, variant_url: Option<&Url>) {
+        self.write(&Entry::Started {
+            playlist_url: playlist_url.to_string(),
+            variant_url: variant_url.map(Url::to_string),
+        });
+    }
+
+    pub fn queued(&self, work_item: &WorkItem) {
+        self.write(&Entry::Queued(work_item.into()));
+    }
+
+    pub fn completed(&self, work_item: &WorkItem) {
+        self.write(&Entry::Completed(work_item.into()));
+    }
+
+    pub fn reached(&self, playlist_url: &Url, next_sequence: u64) {
+        self.write(&Entry::Reached {
+            playlist_url: playlist_url.to_string(),
+            next_sequence,
+        });
+    }
+
+    /// Failing to keep the journal only costs a later resume some work, so
+    /// errors are logged rather than stopping the download
+    fn write(&self, entry: &Entry) {
+        let mut line = serde_json::to_string(entry).unwrap();
+        line.push('\n');
+        if let Err(e) = self.file.lock().unwrap().write_all(line.as_bytes()) {
+            log::warn!("failed to write to the journal: {}", e);
+        }
+    }
+}
+
+fn read_journal(file: File) -> Result<Previous, Box<dyn Error>> {
+    let mut previous = Previous::default();
+    let mut queued = Vec::new();
+    let mut done = HashSet::new();
+
+    for line in BufReader::new(file).lines() {
+        // The last line is cut short if the run was killed while writing it
+        let Ok(entry) = serde_json::from_str(&line?) else {
+            continue;
+        };
+        match entry {
+            Entry::Started {
+                playlist_url,
+                variant_url,
+            } => {
+                previous.playlist_url = Some(Url::parse(&playlist_url)?);
+                previous.variant_url = variant_url.map(|url| Url::parse(&url)).transpose()?;
+            }
+            Entry::Queued(item) => queued.push(item),
+            Entry::Completed(item) => {
+                done.insert(item.key());
+            }
+            Entry::Reached {
+                playlist_url,
+                next_sequence,
+            } => {
+                let position = previous
+                    .positions
+                    .entry(Url::parse(&playlist_url)?)
+                    .or_default();
+                *position = next_sequence.max(*position);
+            }
+        }
+    }
+
+    for item in queued {
+        if done.insert(item.key()) {
+            let work_item = WorkItem::new(item.path, Url::parse(&item.url)?, item.file_type);
+            let work_item = work_item.with_range(item.range);
+            previous
+                .pending
+                .push(work_item.with_sequence(item.sequence));
+        }
+    }
+
+    Ok(previous)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn reads_back_an_interrupted_run() {
+        let dir = std::env::temp_dir().join(format!("journal-{}", std::process::id()));
+        let output_dir = dir.to_str().unwrap();
+        let playlist = Url::parse("https://example.com/live/prog.m3u8").unwrap();
+        let segment = |n: u64| {
+            let path = PathBuf::from(format!("{}.ts", n));
+            let url = playlist.join(path.to_str().unwrap()).unwrap();
+            WorkItem::new(path, url, FileType::MediaSegment).with_sequence(Some(n))
+        };
+
+        let journal = Journal::create(output_dir, false).unwrap();
+        journal.started(&playlist, None);
+        for n in 10..13 {
+            journal.queued(&segment(n));
+        }
+        journal.reached(&playlist, 13);
+        journal.completed(&segment(10));
+        journal.completed(&segment(12));
+        drop(journal);
+
+        // Killed while recording that 11 was done
+        let path = dir.join(JOURNAL_NAME);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"completed":{"url":"https://example.com/li"#)
+            .unwrap();
+        drop(file);
+
+        let previous = Journal::previous(output_dir).unwrap();
+        assert_eq!(previous.playlist_url.as_ref(), Some(&playlist));
+        assert_eq!(previous.variant_url, None);
+        let [pending] = &previous.pending[..] else {
+            panic!("expected one pending file, got {:?}", previous.pending);
+        };
+        assert_eq!(pending.remote_url, segment(11).remote_url);
+        assert_eq!(pending.sequence, Some(11));
+        assert_eq!(previous.positions.get(&playlist), Some(&13));
+
+        // A resumed run starts on a fresh line
+        let journal = Journal::create(output_dir, true).unwrap();
+        journal.completed(&segment(11));
+        journal.reached(&playlist, 15);
+        drop(journal);
+
+        let previous = Journal::previous(output_dir).unwrap();
+        assert!(previous.pending.is_empty());
+        assert_eq!(previous.positions.get(&playlist), Some(&15));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+use std::error::Error;
+use std::time::Duration;
+use tokio::time::Instant;
+use url::Url;
+
+use crate::events::{Event, EventLog};
+use crate::http::HttpClient;
+use crate::journal::Journal;
+use crate::manifest_watcher::{FileAdd, ManifestWatcher};
+use crate::progress::Progress;
+
+/// When to stop following a live playlist, short of it ending
+#[derive(Clone, Copy, Default)]
+pub struct Limits<'a> {
+    /// Stop if this would pass before the next refresh
+    pub stop_at: Option<Instant>,
+    /// Stop once `progress` has counted this many bytes downloaded
+    pub max_size: Option<(u64, &'a Progress)>,
+}
+
+/// Keep re-fetching a live media playlist, every half target duration, and
+/// feed each version to `watcher` until the playlist ends or a limit is
+/// reached. Master playlists, which have no target duration, are not
+/// refreshed. Each refresh is reported to `events`, and how far it got is
+/// recorded in `journal`.
+pub async fn follow<F>(
+    client: &HttpClient,
+    url: &Url,
+    watcher: &mut ManifestWatcher<F>,
+    limits: Limits<'_>,
+    journal: Option<&Journal>,
+    events: Option<&EventLog>,
+) -> Result<(), Box<dyn Error>>
+where
+    F: FnMut(FileAdd),
+{
+    while let Some(target_duration) = watcher.target_duration() {
+        if watcher.ended() {
+            log::info!("{} has ended", url);
+            break;
+        }
+
+        let interval = Duration::from_secs(target_duration) / 2;
+        if limits
+            .stop_at
+            .is_some_and(|stop| Instant::now() + interval > stop)
+        {
+            log::info!("recording time is up; no longer following {}", url);
+            break;
+        }
+        if let Some((max, progress)) = limits.max_size {
+            if progress.bytes() >= max {
+                log::info!("size limit reached; no longer following {}", url);
+                break;
+            }
+        }
+
+        tokio::time::sleep(interval).await;
+        let text = client.fetch(url).await?.error_for_status()?.text().await?;
+        watcher.update(&text)?;
+        if let Some(journal) = journal {
+            journal.reached(url, watcher.next_sequence());
+        }
+        if let Some(events) = events {
+            events.emit(Event::PlaylistRefreshed {
+                url: url.as_str(),
+                ended: watcher.ended(),
+            });
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use hls::{EncryptionMethod, KeyAttributes, Line, MapAttributes, PlaylistType, Tag};
+use std::error::Error;
+use std::path::Path;
+use url::Url;
+
+use crate::fs;
+use crate::manifest_watcher::FileAdd;
+use crate::renditions::Rendition;
+use crate::work_queue::{FileType, WorkItem};
+
+pub const LOCAL_PLAYLIST_NAME: &str = "index.m3u8";
+pub const LOCAL_MASTER_NAME: &str = "master.m3u8";
+
+/// A media playlist of the downloaded files, for playing a download back
+/// without the origin. It is built from the segments as they are seen, so a
+/// live playlist ends up listing everything captured rather than just its
+/// last window. URIs are relative to the output directory, and AES-128 keys
+/// point at the downloaded key files. DRM keys are kept as they were.
+#[derive(Default)]
+pub struct LocalPlaylist {
+    lines: Vec<Line>,
+    first_sequence: Option<u64>,
+    target_duration: u64,
+    keys: Vec<KeyAttributes>,
+    pending_map: Option<String>,
+    uses_map: bool,
+    gap: bool,
+}
+
+impl LocalPlaylist {
+    /// Record a playlist event. `work_item` gives the file a URI is stored
+    /// as.
+    pub fn add<W>(&mut self, event: &FileAdd, work_item: W)
+    where
+        W: Fn(&str, FileType) -> WorkItem,
+    {
+        match event {
+            FileAdd::Segment {
+                uri,
+                sequence,
+                duration,
+                discontinuity,
+                range,
+                keys,
+            } => {
+                self.first_sequence.get_or_insert(*sequence);
+
+                if *keys != self.keys {
+                    self.add_keys(keys, &work_item);
+                    self.keys = keys.clone();
+                }
+                if let Some(uri) = self.pending_map.take() {
+                    self.uses_map = true;
+                    self.lines.push(Line::Tag(Tag::Map(MapAttributes {
+                        uri,
+                        byterange: None,
+                    })));
+                }
+                // Timestamps jump over missing segments just as they do at
+                // discontinuities
+                if *discontinuity || std::mem::take(&mut self.gap) {
+                    self.lines.push(Line::Tag(Tag::Discontinuity));
+                }
+
+                let duration = duration.unwrap_or_default();
+                self.target_duration = self.target_duration.max(duration.round() as u64);
+                self.lines.push(Line::Tag(Tag::Inf(duration, None)));
+                let segment = work_item(uri, FileType::MediaSegment).with_range(*range);
+                self.lines.push(Line::Uri(local_uri(&segment)));
+            }
+            FileAdd::InitSegment { uri, range } => {
+                let init = work_item(uri, FileType::InitSegment).with_range(*range);
+                self.pending_map = Some(local_uri(&init));
+            }
+            FileAdd::SegmentsMissed { .. } | FileAdd::Gap { .. } => self.gap = true,
+            FileAdd::Key(_) | FileAdd::SegmentTooLong { .. } => {}
+        }
+    }
+
+    /// Write the playlist, ended, to `path`
+    pub fn finish(self, path: &Path) -> Result<(), Box<dyn Error>> {
+        // Fractional durations need version 3, and EXT-X-MAP in a playlist
+        // that isn't I-frames only needs version 6
+        let version = if self.uses_map { 6 } else { 3 };
+        let mut lines = vec![
+            Line::Tag(Tag::M3u),
+            Line::Tag(Tag::Version(version)),
+            Line::Tag(Tag::Targetduration(self.target_duration)),
+            Line::Tag(Tag::MediaSequence(self.first_sequence.unwrap_or(0))),
+            Line::Tag(Tag::PlaylistType(PlaylistType::Vod)),
+        ];
+        lines.extend(self.lines);
+        lines.push(Line::Tag(Tag::Endlist));
+
+        fs::write_atomic(path, hls::to_string(&lines)?.as_bytes())?;
+        Ok(())
+    }
+
+    fn add_keys<W>(&mut self, keys: &[KeyAttributes], work_item: W)
+    where
+        W: Fn(&str, FileType) -> WorkItem,
+    {
+        if keys.is_empty() {
+            self.lines.push(Line::Tag(Tag::Key(KeyAttributes {
+                method: EncryptionMethod::None,
+                uri: None,
+                iv: None,
+                keyformat: None,
+                keyformatversions: None,
+            })));
+        }
+
+        for key in keys {
+            let mut key = key.clone();
+            if key.is_identity() {
+                key.uri = key
+                    .uri
+                    .map(|uri| local_uri(&work_item(&uri, FileType::Key)));
+            }
+            self.lines.push(Line::Tag(Tag::Key(key)));
+        }
+    }
+}
+
+/// Rewrite a master playlist to offer only the downloaded variant and its
+/// renditions, pointing at their local playlists. Renditions are written
+/// next to the variant's playlist as `<dir>.m3u8`.
+pub fn rewrite_master(
+    master: &str,
+    master_url: &Url,
+    variant_url: &Url,
+    renditions: &[Rendition],
+) -> Result<String, Box<dyn Error>> {
+    let mut lines = Vec::new();
+    let mut stream_inf = None;
+
+    for line in hls::from_str::<Vec<Line>>(master)? {
+        match line {
+            Line::Tag(Tag::Media(mut attrs)) => {
+                // Renditions without a URI are carried in the variant itself
+                if let Some(uri) = attrs.uri.take() {
+                    let url = master_url.join(&uri)?;
+                    match renditions.iter().find(|r| r.url == url) {
+                        Some(rendition) => attrs.uri = Some(rendition_playlist(rendition)),
+                        None => continue,
+                    }
+                }
+                lines.push(Line::Tag(Tag::Media(attrs)));
+            }
+            Line::Tag(Tag::StreamInf(attrs)) => stream_inf = Some(attrs),
+            Line::Uri(uri) => {
+                if let Some(attrs) = stream_inf.take() {
+                    if master_url.join(&uri)? == *variant_url {
+                        lines.push(Line::Tag(Tag::StreamInf(attrs)));
+                        lines.push(Line::Uri(LOCAL_PLAYLIST_NAME.to_owned()));
+                    }
+                }
+            }
+            Line::Tag(Tag::IFrameStreamInf(_)) => {}
+            line => lines.push(line),
+        }
+    }
+
+    Ok(hls::to_string(&lines)?)
+}
+
+/// File name of a rendition's local playlist
+pub fn rendition_playlist(rendition: &Rendition) -> String {
+    format!("{}.m3u8", rendition.dir)
+}
+
+fn local_uri(work_item: &WorkItem) -> String {
+    fs::relative_path(work_item).to_string_lossy().into_owned()
+}
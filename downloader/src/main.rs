@@ -1,59 +1,742 @@
 mod args;
+mod cache;
+mod concat;
+mod cookies;
+mod dedup;
 mod downloader;
+mod events;
 mod fs;
+mod hashes;
+mod headers;
+mod http;
+mod iframes;
+mod join;
+mod journal;
+mod live;
+mod local_playlist;
 mod manifest_watcher;
+mod mirror;
+mod preflight;
+mod processor;
+mod progress;
+mod renditions;
+mod resume;
+mod retry;
+mod rewrite;
+mod selection;
+mod sign;
+mod stream;
+mod wait;
 mod work_queue;
 
 use clap::Parser;
-use crossbeam_deque::Worker;
 use downloader::DownloadWorker;
+use hls::Playlist;
 use std::{
-    path::Path,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
+    cell::{Cell, RefCell},
+    fmt::Display,
+    future::Future,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+    time::Duration,
 };
+use tokio::time::Instant;
 use url::Url;
 
 use args::Args;
-use hls::Line;
+use bytes::Bytes;
+use cache::SegmentCache;
+use concat::{ConcatList, CONCAT_LIST_NAME};
+use cookies::CookieJar;
+use dedup::Deduplicator;
+use events::{EventLog, LogFormat};
+use futures::{Stream, StreamExt};
+use hashes::{HashManifest, HASH_MANIFEST_NAME};
+use http::{HttpClient, HttpError};
+use join::{Fragment, OrderedOutput};
+use journal::Journal;
+use local_playlist::{LocalPlaylist, LOCAL_MASTER_NAME, LOCAL_PLAYLIST_NAME};
 use manifest_watcher::{FileAdd, ManifestWatcher};
-use work_queue::FileType;
+use mirror::Mirror;
+use processor::{CommandProcessor, SegmentProcessor};
+use progress::Progress;
+use reqwest::header::USER_AGENT;
+use resume::Resume;
+use retry::RetryPolicy;
+use sign::CommandSigner;
+use work_queue::{FileType, Priority, QueryMatch, WorkItem, WorkQueue};
 
-const WORKER_COUNT: usize = 4;
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Exit code when some files could not be downloaded, or the deadline
+/// passed first
+const EXIT_PARTIAL: i32 = 2;
+/// Exit code when the run stops on an error
+const EXIT_FATAL: i32 = 1;
+
+/// The --cookie-jar, so cookies received so far are kept when the run stops
+/// on an error
+static COOKIE_JAR: OnceLock<Arc<CookieJar>> = OnceLock::new();
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
     env_logger::init();
 
+    // Failures of a single file are given up on by the workers, so a panic
+    // is a bug or a problem with the run as a whole. Give it its own exit
+    // code rather than the default for a panic.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        exit_fatal();
+    }));
+
     let args = Args::parse();
-    let base_url = Url::parse(args.base_url.as_str()).unwrap();
-    let manifest = read_manifest(args.manifest_path);
-    let worker = Worker::new_fifo();
-    let mut downloader = DownloadWorker::new(args.output_dir, WORKER_COUNT);
-    let is_done = Arc::new(AtomicBool::new(false));
-    let downloads_complete = downloader.run(&worker, is_done.clone());
-
-    let mut watcher = ManifestWatcher::new(|message| match message {
-        FileAdd::Segment(s) => {
-            let work_item =
-                fs::parse_path_from_url(&base_url, s.as_str(), FileType::MediaSegment).unwrap();
-            worker.push(work_item);
-        }
-        FileAdd::Key(s) => {
-            let work_item = fs::parse_path_from_url(&base_url, s.as_str(), FileType::Key).unwrap();
-            worker.push(work_item);
+    let base_url = args.base_url.as_ref().or(args.manifest_url.as_ref());
+    let mut base_url = Url::parse(base_url.unwrap()).unwrap();
+    let deadline = args.deadline.map(|d| Instant::now() + d);
+    let workers = args.workers.max(1);
+    let cookie_jar = args
+        .cookie_jar
+        .as_ref()
+        .map(|path| Arc::new(CookieJar::load(path).unwrap()));
+    if let Some(jar) = cookie_jar.as_ref() {
+        let _ = COOKIE_JAR.set(jar.clone());
+    }
+    let proxy = args.proxy.as_ref().map(|url| {
+        let proxy = reqwest::Proxy::all(url).unwrap();
+        match args.proxy_auth.as_ref() {
+            Some(auth) => {
+                let (user, password) = auth.split_once(':').unwrap_or((auth, ""));
+                proxy.basic_auth(user, password)
+            }
+            None => proxy,
+        }
+    });
+    let mut client = HttpClient::new(args.rewrite.clone(), cookie_jar.clone(), proxy);
+    if let Some(command) = args.sign_command.clone() {
+        client = client.with_signer(CommandSigner::new(command));
+    }
+    let mut headers = headers::header_map(&args.headers);
+    if let Some(user_agent) = args.user_agent.as_ref() {
+        headers.insert(USER_AGENT, user_agent.parse().unwrap());
+    }
+    client = client
+        .with_headers(headers)
+        .with_key_headers(headers::header_map(&args.key_headers));
+    if let Some(max) = args.max_connections_per_host {
+        client = client.with_max_connections_per_host(max);
+    }
+
+    if let Some(timeout) = args.wait_for_stream {
+        let ready = wait::wait_for_playlist(&client, &base_url, args.wait_interval, timeout);
+        if !ready.await.unwrap() {
+            fatal(format!("{} did not appear within {:?}", base_url, timeout));
+        }
+    }
+
+    if args.playlists_only {
+        let mirror = Mirror::new(client, &args.output_dir, Duration::ZERO);
+        if let Some(res) = until_deadline(deadline, mirror.snapshot_playlists(&base_url)).await {
+            res.unwrap();
+        }
+        save_cookies(cookie_jar.as_deref());
+        return;
+    }
+
+    if args.mirror {
+        let interval = Duration::from_secs(args.mirror_interval);
+        let positions = if args.resume {
+            Journal::previous(&args.output_dir).unwrap().positions
+        } else {
+            Default::default()
+        };
+        let journal = Journal::create(&args.output_dir, args.resume).unwrap();
+        let mut mirror = Mirror::new(client, &args.output_dir, interval)
+            .tighten_interval(args.tighten_interval)
+            .with_journal(journal, positions);
+        if let Some(res) = until_deadline(deadline, mirror.run(&base_url)).await {
+            res.unwrap();
         }
+        save_cookies(cookie_jar.as_deref());
+        return;
+    }
+
+    let mut playlist_url = None;
+    let mut manifest = match args.manifest_url.as_ref() {
+        Some(url) => {
+            let (manifest, final_url) = fetch_manifest(&client, &Url::parse(url).unwrap())
+                .await
+                .unwrap();
+            if args.base_url.is_none() {
+                base_url = final_url.clone();
+            }
+            playlist_url = Some(final_url);
+            manifest
+        }
+        None => read_manifest(args.manifest_path.as_ref().unwrap()),
+    };
+
+    let previous = if args.resume {
+        Journal::previous(&args.output_dir).unwrap()
+    } else {
+        Default::default()
+    };
+
+    // A selected variant takes the master playlist's place, and its
+    // renditions are downloaded alongside it. A resumed run sticks with the
+    // variant it picked before.
+    let (master, master_url) = (manifest.clone(), base_url.clone());
+    let mut renditions = Vec::new();
+    let mut selected_variant = None;
+    if args.select_variant {
+        let playlist = Playlist::parse(&manifest)
+            .unwrap_or_else(|e| fatal(format!("could not parse {}: {}", base_url, e)));
+        if let Playlist::Master(playlist) = playlist {
+            let preferred = previous.variant_url.as_ref();
+            let (variant, selected) =
+                match renditions::select_variant(&playlist, &base_url, preferred) {
+                    Ok(Some(selected)) => selected,
+                    Ok(None) => fatal("the master playlist has no variants"),
+                    Err(e) => fatal(format!("could not select a variant: {}", e)),
+                };
+            log::info!("downloading variant {}", variant);
+            let (text, final_url) = fetch_manifest(&client, &variant).await.unwrap();
+            manifest = text;
+            base_url = final_url.clone();
+            playlist_url = Some(final_url);
+            renditions = selected;
+            selected_variant = Some(variant);
+        }
+    }
+
+    // Segments can be picked by sequence number, or from a VOD playlist by
+    // time. Times are applied to each rendition's own playlist too, as their
+    // segments needn't line up with the variant's.
+    let by_time = args.start.is_some() || args.duration.is_some();
+    let vod_selection = |text: &str| {
+        let start = args.start.unwrap_or_default();
+        selection::time_range(text, start, args.duration)
+    };
+    let range = match args.segments {
+        Some(segments) => segments.0,
+        None if args.start.is_some() => vod_selection(&manifest)
+            .unwrap_or_else(|e| fatal(format!("could not apply --start: {}", e))),
+        // --duration alone also limits how long a live playlist is followed
+        None if by_time => vod_selection(&manifest).unwrap_or((args.start_seq, args.end_seq)),
+        None => (args.start_seq, args.end_seq),
+    };
+
+    if args.stdout {
+        let segments = stream::segment_stream(&client, &base_url, &manifest, range, workers);
+        let copy = write_stream(segments.unwrap());
+        if let Some(res) = until_deadline(deadline, copy).await {
+            res.unwrap();
+        }
+        save_cookies(cookie_jar.as_deref());
+        return;
+    }
+
+    let mut concat_list = args.concat_list.then(|| {
+        std::fs::create_dir_all(&args.output_dir).unwrap();
+        ConcatList::create(Path::new(&args.output_dir).join(CONCAT_LIST_NAME)).unwrap()
     });
+    let hashes = args.hash_manifest.then(|| {
+        std::fs::create_dir_all(&args.output_dir).unwrap();
+        let path = Path::new(&args.output_dir).join(HASH_MANIFEST_NAME);
+        Arc::new(HashManifest::create(path).unwrap())
+    });
+    let dedup = args
+        .dedup
+        .then(|| Arc::new(Deduplicator::create(&args.output_dir).unwrap()));
+    let journal = Arc::new(Journal::create(&args.output_dir, args.resume).unwrap());
+    let media_url = playlist_url.as_ref().unwrap_or(&base_url);
+    if let Some(url) = previous
+        .playlist_url
+        .as_ref()
+        .filter(|url| *url != media_url)
+    {
+        log::warn!("resuming a download of {} from {}", url, media_url);
+    }
+    journal.started(media_url, selected_variant.as_ref());
+    let mut local_playlist = args.local_playlist.then(LocalPlaylist::default);
+    let rendition_playlists: Vec<_> = renditions
+        .iter()
+        .map(|_| RefCell::new(LocalPlaylist::default()))
+        .collect();
+    let mut gap = false;
+    let mut current_init: Option<PathBuf> = None;
+    let mut fragments = Vec::new();
+    let query_match = if args.ignore_query {
+        QueryMatch::Ignore
+    } else if !args.ignore_query_param.is_empty() {
+        QueryMatch::IgnoreParams(args.ignore_query_param.clone())
+    } else {
+        QueryMatch::Whole
+    };
+    let queue = Arc::new(WorkQueue::new().with_query_match(query_match));
+    let mut downloader = DownloadWorker::new(client.clone(), args.output_dir.clone(), workers)
+        .with_retry_policy(RetryPolicy::new(args.max_attempts));
+    if let Some(dir) = args.cache_dir.as_ref() {
+        downloader = downloader.with_cache(SegmentCache::new(dir).unwrap());
+    }
+    if !args.process_command.is_empty() {
+        let processors = args
+            .process_command
+            .iter()
+            .map(|command| {
+                Box::new(CommandProcessor::new(command.clone())) as Box<dyn SegmentProcessor>
+            })
+            .collect();
+        downloader = downloader.with_processors(processors);
+    }
+    if let Some(dedup) = dedup.as_ref() {
+        downloader = downloader.with_dedup(dedup.clone());
+    }
+    if let Some(hashes) = hashes.as_ref() {
+        downloader = downloader.with_hash_manifest(hashes.clone());
+    }
+    if let Some(deadline) = deadline {
+        downloader = downloader.with_deadline(deadline);
+    }
+    if let Some(max_size) = args.max_size {
+        downloader = downloader.with_max_size(max_size);
+    }
+    downloader = downloader.with_journal(journal.clone());
+    let output = args.output_file.as_ref().map(|path| {
+        let output = OrderedOutput::create(&args.output_dir, Path::new(path)).unwrap();
+        Arc::new(output)
+    });
+    if let Some(output) = output.as_ref() {
+        downloader = downloader.with_output(output.clone());
+    }
+    let events = (args.log_format == LogFormat::Json).then(|| {
+        let events = match args.events_file.as_ref() {
+            Some(path) => EventLog::create(path).unwrap(),
+            None => EventLog::stdout(),
+        };
+        Arc::new(events)
+    });
+    if let Some(events) = events.as_ref() {
+        downloader = downloader.with_events(events.clone());
+    }
+    if args.resume {
+        let resume = if args.verify_resume {
+            Resume::VerifySize
+        } else {
+            Resume::Exists
+        };
+        downloader = downloader.with_resume(resume);
+    }
+    let progress = Progress::new();
+    downloader = downloader.with_progress(progress.clone());
+    let downloads_complete = downloader.run(queue.clone());
+
+    let queued = RefCell::new(Vec::new());
+    // Segments found once the playlists have first been loaded are at the
+    // live edge
+    let live_edge = Cell::new(false);
+    let full = || args.max_size.is_some_and(|max| progress.bytes() >= max);
+    let enqueue = |work_item: WorkItem| {
+        if full() {
+            log::debug!("size limit reached; not queuing {}", work_item.remote_url);
+            return;
+        }
+        let priority = Priority::of(&work_item, live_edge.get());
+        if !queue.add(work_item.clone(), priority) {
+            log::debug!("{} is already queued", work_item.remote_url);
+            return;
+        }
+
+        journal.queued(&work_item);
+        if args.preflight.is_some() {
+            queued.borrow_mut().push(work_item);
+        }
+        progress.queued();
+    };
+
+    // Files the last run didn't get to may have left a live playlist since
+    if !previous.pending.is_empty() {
+        log::info!(
+            "resuming {} files left by the last run",
+            previous.pending.len()
+        );
+    }
+    for work_item in previous.pending {
+        enqueue(work_item);
+    }
+    // Live playlists carry on from the last segment the last run saw
+    let resume_from = |url: &Url, start: Option<u64>| match previous.positions.get(url) {
+        Some(&next) => Some(start.map_or(next, |start| start.max(next))),
+        None => start,
+    };
+
+    let mut watcher = ManifestWatcher::new(|message| {
+        if let Some(playlist) = local_playlist.as_mut() {
+            playlist.add(&message, |uri, file_type| {
+                fs::parse_path_from_url(&base_url, uri, file_type).unwrap()
+            });
+        }
+
+        match message {
+            FileAdd::Segment {
+                uri,
+                sequence,
+                duration,
+                discontinuity,
+                range,
+                keys,
+            } => {
+                for key in keys.iter().filter(|key| !key.is_identity()) {
+                    log::debug!(
+                        "{} is also encrypted with a {} key",
+                        uri,
+                        key.keyformat.as_deref().unwrap_or_default()
+                    );
+                }
+
+                let work_item =
+                    fs::parse_path_from_url(&base_url, uri.as_str(), FileType::MediaSegment)
+                        .unwrap()
+                        .with_range(range)
+                        .with_sequence(Some(sequence));
+                if let Some(list) = concat_list.as_mut() {
+                    // Timestamps jump over missing segments just as they do at
+                    // discontinuities
+                    let discontinuity = discontinuity || std::mem::take(&mut gap);
+                    list.add_segment(&fs::relative_path(&work_item), duration, discontinuity)
+                        .unwrap();
+                }
+                if let Some(output) = output.as_ref() {
+                    let init = current_init.clone();
+                    output.add(fs::relative_path(&work_item), init).unwrap();
+                }
+                if let Some(init) = current_init.as_ref().filter(|_| args.join_init) {
+                    fragments.push(Fragment {
+                        segment: fs::relative_path(&work_item),
+                        init: init.clone(),
+                    });
+                }
+                enqueue(work_item);
+            }
+            FileAdd::InitSegment { uri, range } => {
+                let work_item =
+                    fs::parse_path_from_url(&base_url, uri.as_str(), FileType::InitSegment)
+                        .unwrap()
+                        .with_range(range);
+                current_init = Some(fs::relative_path(&work_item));
+                enqueue(work_item);
+            }
+            FileAdd::Key(s) => {
+                let work_item =
+                    fs::parse_path_from_url(&base_url, s.as_str(), FileType::Key).unwrap();
+                enqueue(work_item);
+            }
+            FileAdd::SegmentsMissed { from, to } => {
+                log::warn!("segments {} to {} are no longer available", from, to);
+                gap = true;
+            }
+            FileAdd::Gap { uri, duration } => {
+                log::debug!(
+                    "skipping gap segment {} ({}s)",
+                    uri,
+                    duration.unwrap_or_default()
+                );
+                gap = true;
+            }
+            FileAdd::SegmentTooLong {
+                uri,
+                duration,
+                target_duration,
+            } => {
+                log::warn!(
+                    "{} is {}s, longer than the target duration of {}s",
+                    uri,
+                    duration,
+                    target_duration
+                );
+            }
+        }
+    })
+    .sequence_range(resume_from(media_url, range.0), range.1);
+
+    watcher.update(manifest.as_str()).unwrap();
+    journal.reached(media_url, watcher.next_sequence());
+
+    let mut rendition_watchers = Vec::new();
+    for (rendition, playlist) in renditions.iter().zip(&rendition_playlists) {
+        log::info!(
+            "downloading rendition {} from {}",
+            rendition.dir,
+            rendition.url
+        );
+        let (text, _) = fetch_manifest(&client, &rendition.url).await.unwrap();
+        let mut watcher = ManifestWatcher::new(|event| {
+            if args.local_playlist {
+                playlist.borrow_mut().add(&event, |uri, file_type| {
+                    renditions::local_work_item(rendition, uri, file_type)
+                });
+            }
+            if let Some(work_item) = renditions::work_item(rendition, event) {
+                enqueue(work_item);
+            }
+        });
+        let (start, end) = if by_time {
+            vod_selection(&text).unwrap_or_default()
+        } else {
+            (None, None)
+        };
+        watcher = watcher.sequence_range(resume_from(&rendition.url, start), end);
+        watcher.update(&text).unwrap();
+        journal.reached(&rendition.url, watcher.next_sequence());
+        rendition_watchers.push((rendition, watcher));
+    }
+
+    live_edge.set(true);
+
+    if let Some(sample) = args.preflight {
+        preflight(&client, &queued.take(), sample, &args.output_dir).await;
+    }
 
-    watcher.update(manifest);
+    let ended = watcher.ended() && rendition_watchers.iter().all(|(_, w)| w.ended());
+    progress.set_listing_complete(ended);
+    let reporter = args
+        .progress
+        .then(|| tokio::spawn(progress.clone().report(PROGRESS_INTERVAL)));
+
+    // Live playlists are refreshed while the segments seen so far download
+    let stop_at = [args.duration, args.record_duration]
+        .into_iter()
+        .flatten()
+        .map(|d| Instant::now() + d)
+        .chain(deadline)
+        .min();
+    let limits = live::Limits {
+        stop_at,
+        max_size: args.max_size.map(|max| (max, &*progress)),
+    };
+    let refresh = async {
+        let variant = async {
+            match playlist_url.as_ref() {
+                Some(url) => {
+                    let journal = Some(&*journal);
+                    live::follow(
+                        &client,
+                        url,
+                        &mut watcher,
+                        limits,
+                        journal,
+                        events.as_deref(),
+                    )
+                    .await
+                }
+                None => Ok(()),
+            }
+        };
+        let renditions = rendition_watchers.iter_mut().map(|(rendition, watcher)| {
+            let journal = Some(&*journal);
+            live::follow(
+                &client,
+                &rendition.url,
+                watcher,
+                limits,
+                journal,
+                events.as_deref(),
+            )
+        });
+        let (variant, renditions) = tokio::join!(variant, futures::future::join_all(renditions));
+        variant.unwrap();
+        for rendition in renditions {
+            rendition.unwrap();
+        }
+        progress.set_listing_complete(true);
+        queue.close();
+    };
+    let ((), unfinished) = tokio::join!(refresh, downloads_complete);
+    if let Some(reporter) = reporter {
+        reporter.abort();
+    }
+    let mut undone = unfinished.abandoned;
+    undone.extend(std::iter::from_fn(|| queue.take()));
+    let failed = unfinished.failed;
+    let out_of_time = deadline.is_some_and(|d| Instant::now() >= d);
+    let stopped_by = if out_of_time {
+        "the deadline passed"
+    } else if full() {
+        "the size limit was reached"
+    } else {
+        "the run stopped"
+    };
+
+    if let Some(list) = concat_list {
+        list.finish().unwrap();
+    }
+
+    if args.iframes && !out_of_time {
+        iframes::capture(
+            &client,
+            &master_url,
+            &master,
+            &args.output_dir,
+            hashes.as_deref(),
+        )
+        .await
+        .unwrap();
+    }
+
+    if args.join_init && undone.is_empty() && failed.is_empty() {
+        join::join_init_segments(&args.output_dir, &fragments).unwrap();
+    }
+
+    if let (Some(output), Some(output_file)) = (output, args.output_file.as_ref()) {
+        match output.finish().unwrap() {
+            0 => log::info!("wrote {}", output_file),
+            n => log::warn!("wrote {} without {} missing segments", output_file, n),
+        }
+    }
+
+    if let Some(playlist) = local_playlist {
+        let dir = Path::new(&args.output_dir);
+        playlist.finish(&dir.join(LOCAL_PLAYLIST_NAME)).unwrap();
+        for (rendition, playlist) in renditions.iter().zip(rendition_playlists) {
+            let path = dir.join(local_playlist::rendition_playlist(rendition));
+            playlist.into_inner().finish(&path).unwrap();
+        }
+        if let Some(variant) = selected_variant.as_ref() {
+            let text =
+                local_playlist::rewrite_master(&master, &master_url, variant, &renditions).unwrap();
+            fs::write_atomic(&dir.join(LOCAL_MASTER_NAME), text.as_bytes()).unwrap();
+        }
+    }
+
+    if let Some(hashes) = hashes {
+        hashes.finish().unwrap();
+    }
+
+    save_cookies(cookie_jar.as_deref());
+
+    report(&progress, &undone, stopped_by, &failed);
+    if !undone.is_empty() || !failed.is_empty() {
+        std::process::exit(EXIT_PARTIAL);
+    }
+}
+
+/// Print what was downloaded, and what wasn't, at the end of a run. Files
+/// still queued were left because of `stopped_by`.
+fn report(
+    progress: &Progress,
+    undone: &[WorkItem],
+    stopped_by: &str,
+    failed: &[(WorkItem, String)],
+) {
+    progress.summary();
+
+    if !undone.is_empty() {
+        log::error!(
+            "{} files were not downloaded because {}:",
+            undone.len(),
+            stopped_by
+        );
+        for work_item in undone {
+            log::error!("  {}", work_item.remote_url);
+        }
+    }
+
+    if !failed.is_empty() {
+        log::error!("{} files could not be downloaded:", failed.len());
+        for (work_item, error) in failed {
+            log::error!("  {}: {}", work_item.remote_url, error);
+        }
+    }
+}
+
+/// Estimate the size of the download and exit if it won't fit
+async fn preflight(
+    client: &HttpClient,
+    queued: &[WorkItem],
+    sample: Option<usize>,
+    output_dir: &str,
+) {
+    let estimate = preflight::estimate(client, queued, sample).await.unwrap();
+    log::info!(
+        "about {} bytes to download in {} files ({} sampled)",
+        estimate.bytes,
+        estimate.files,
+        estimate.sampled
+    );
+
+    std::fs::create_dir_all(output_dir).unwrap();
+    let available = preflight::available_space(Path::new(output_dir)).unwrap();
+    if estimate.bytes > available {
+        fatal(format!(
+            "not enough space in {}: about {} bytes needed but only {} available",
+            output_dir, estimate.bytes, available
+        ));
+    }
+}
+
+/// Copy each segment to stdout as it arrives
+async fn write_stream<S>(segments: S) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: Stream<Item = Result<Bytes, stream::StreamError>>,
+{
+    let mut segments = std::pin::pin!(segments);
+    let mut stdout = std::io::stdout().lock();
+
+    while let Some(segment) = segments.next().await {
+        stdout.write_all(&segment?)?;
+    }
+
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Run `f` until `deadline`, if there is one. Returns None if it ran out of
+/// time.
+async fn until_deadline<F: Future>(deadline: Option<Instant>, f: F) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => {
+            let res = tokio::time::timeout_at(deadline, f).await.ok();
+            if res.is_none() {
+                log::warn!("deadline reached; stopping");
+            }
+            res
+        }
+        None => Some(f.await),
+    }
+}
+
+/// Stop the run on an error the user needs to fix
+fn fatal<M: Display>(message: M) -> ! {
+    log::error!("{}", message);
+    exit_fatal();
+}
+
+/// Exit with `EXIT_FATAL`, saving any cookies first
+fn exit_fatal() -> ! {
+    if let Some(jar) = COOKIE_JAR.get() {
+        if let Err(e) = jar.save() {
+            log::error!("could not save cookies: {}", e);
+        }
+    }
+    std::process::exit(EXIT_FATAL);
+}
+
+fn save_cookies(jar: Option<&CookieJar>) {
+    if let Some(jar) = jar {
+        jar.save().unwrap();
+    }
+}
 
-    is_done.store(true, Ordering::Relaxed);
-    downloads_complete.await;
+/// Download a manifest, along with the URL it was served from after any
+/// redirects
+async fn fetch_manifest(client: &HttpClient, url: &Url) -> Result<(String, Url), HttpError> {
+    let res = client.fetch(url).await?.error_for_status()?;
+    let url = res.url().clone();
+    Ok((res.text().await?, url))
 }
 
-fn read_manifest<P: AsRef<Path>>(path: P) -> Vec<Line> {
-    let input = std::fs::read_to_string(path).unwrap();
-    hls::from_str(input.as_str()).unwrap()
+fn read_manifest<P: AsRef<Path>>(path: P) -> String {
+    std::fs::read_to_string(path).unwrap()
 }
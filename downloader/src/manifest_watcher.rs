@@ -1,18 +1,59 @@
-use hls::{Line, Tag};
+use hls::validate::exceeds_target_duration;
+use hls::{EncryptionMethod, KeyAttributes, Line, Tag};
+
+use crate::work_queue::{ByteRange, RangeTracker};
 
 #[derive(Debug)]
 pub enum FileAdd {
-    Segment(String),
+    Segment {
+        uri: String,
+        /// Media sequence number
+        sequence: u64,
+        duration: Option<f64>,
+        discontinuity: bool,
+        /// The part of `uri` holding the segment, from EXT-X-BYTERANGE
+        range: Option<ByteRange>,
+        /// Every key in effect for the segment, one per KEYFORMAT
+        keys: Vec<KeyAttributes>,
+    },
+    InitSegment {
+        uri: String,
+        /// The part of `uri` holding the init segment, from the BYTERANGE of
+        /// EXT-X-MAP
+        range: Option<ByteRange>,
+    },
     Key(String),
+    /// Segments with media sequence numbers `from..=to` were removed from
+    /// the playlist before they were seen
+    SegmentsMissed {
+        from: u64,
+        to: u64,
+    },
+    /// A segment marked with EXT-X-GAP, which has no media to download
+    Gap {
+        uri: String,
+        duration: Option<f64>,
+    },
+    /// A new segment is longer than the playlist's target duration allows
+    SegmentTooLong {
+        uri: String,
+        duration: f64,
+        target_duration: u64,
+    },
 }
 
+/// Tracks which segments of a (possibly live) playlist have already been
+/// reported. Only the media sequence number of the next unseen segment is
+/// kept between refreshes, so memory use does not grow with the playlist.
 pub struct ManifestWatcher<F>
 where
     F: FnMut(FileAdd),
 {
-    first_segment: usize,
-    segment_count: usize,
-    lines: Vec<Line>,
+    next_sequence: u64,
+    end_sequence: Option<u64>,
+    report_gaps: bool,
+    ended: bool,
+    target_duration: Option<u64>,
     data_added: F,
 }
 
@@ -21,41 +62,277 @@ where
     F: FnMut(FileAdd),
 {
     pub fn new(data_added: F) -> Self {
-        let first_segment = 0;
-        let lines = Vec::new();
-        let segment_count = 0;
+        let next_sequence = 0;
+        let end_sequence = None;
 
         Self {
-            first_segment,
-            lines,
+            next_sequence,
+            end_sequence,
+            report_gaps: false,
+            ended: false,
+            target_duration: None,
             data_added,
-            segment_count,
         }
     }
 
-    pub fn update(&mut self, new_manifest: Vec<Line>) {
-        let mut i = 0;
+    /// Only report segments whose media sequence number lies within
+    /// `start..=end`
+    pub fn sequence_range(mut self, start: Option<u64>, end: Option<u64>) -> Self {
+        self.next_sequence = start.unwrap_or(0);
+        self.end_sequence = end;
+        self.report_gaps = start.is_some();
+        self
+    }
+
+    /// Whether the last update had an EXT-X-ENDLIST tag
+    pub fn ended(&self) -> bool {
+        self.ended
+    }
+
+    /// Media sequence number of the next segment to report
+    pub fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// EXT-X-TARGETDURATION of the last update
+    pub fn target_duration(&self) -> Option<u64> {
+        self.target_duration
+    }
+
+    pub fn update(&mut self, manifest: &str) -> Result<(), hls::Error> {
+        let mut sequence = 0;
+        let mut target_duration = None;
+        let mut seen_segment = false;
+        let mut ended = false;
+        let mut duration = None;
+        let mut discontinuity = false;
+        let mut gap = false;
+        let mut range = None;
+        let mut ranges = RangeTracker::default();
+        let mut keys: Vec<KeyAttributes> = Vec::new();
+        let mut pending_keys = Vec::new();
+        let mut pending_map = None;
 
-        for line in &new_manifest {
-            match line {
-                Line::Tag(Tag::Key(attrs)) => {
-                    (self.data_added)(FileAdd::Key(
-                        attrs.uri.as_ref().map(|s| s.clone()).unwrap_or_default(),
-                    ));
+        hls::for_each(manifest, |line: Line| match line {
+            Line::Tag(Tag::MediaSequence(s)) => {
+                sequence = s;
+            }
+            Line::Tag(Tag::Skip(attrs)) => {
+                // A delta update leaves out segments already seen in full
+                sequence += attrs.skipped_segments;
+            }
+            Line::Tag(Tag::Targetduration(t)) => {
+                target_duration = Some(t);
+            }
+            Line::Tag(Tag::Inf(d, _)) => {
+                duration = Some(d);
+            }
+            Line::Tag(Tag::Discontinuity) => {
+                discontinuity = true;
+            }
+            Line::Tag(Tag::Gap) => {
+                gap = true;
+            }
+            Line::Tag(Tag::Byterange(r)) => {
+                range = Some(r);
+            }
+            Line::Tag(Tag::Endlist) => {
+                ended = true;
+            }
+            Line::Tag(Tag::Key(attrs)) => {
+                // A key replaces any earlier key of the same format, while
+                // METHOD=NONE turns encryption off altogether
+                if attrs.method == EncryptionMethod::None {
+                    keys.clear();
+                } else {
+                    keys.retain(|k| k.keyformat != attrs.keyformat);
+                    keys.push(attrs.clone());
                 }
-                Line::Uri(u) => {
-                    i += 1;
-                    if i > self.segment_count {
-                        self.segment_count += 1;
-                        (self.data_added)(FileAdd::Segment(u.to_owned()));
+
+                // Keys are reported just before the first new segment they
+                // apply to, so skipped segments don't pull in unused keys.
+                // Only identity keys can be downloaded; DRM keys are left to
+                // the consumer of the segment events.
+                pending_keys.retain(|k: &KeyAttributes| k.keyformat != attrs.keyformat);
+                if attrs.is_identity() && attrs.uri.is_some() {
+                    pending_keys.push(attrs);
+                }
+            }
+            Line::Tag(Tag::Map(attrs)) => {
+                pending_map = Some(attrs);
+            }
+            Line::Uri(uri) => {
+                seen_segment = true;
+                // Offsets are resolved for every segment, seen or not, as
+                // they can continue from the previous segment's range
+                let range = range.take().map(|r| ranges.resolve(&uri, r));
+                let in_range = self.end_sequence.is_none_or(|end| sequence <= end);
+                if sequence >= self.next_sequence && in_range {
+                    // Until the first update there is nothing to have missed,
+                    // unless a specific start was asked for
+                    if sequence > self.next_sequence && self.report_gaps {
+                        (self.data_added)(FileAdd::SegmentsMissed {
+                            from: self.next_sequence,
+                            to: sequence - 1,
+                        });
+                    }
+                    self.next_sequence = sequence + 1;
+                    if gap {
+                        (self.data_added)(FileAdd::Gap {
+                            uri,
+                            duration: duration.take(),
+                        });
+                    } else {
+                        for key in pending_keys.drain(..) {
+                            (self.data_added)(FileAdd::Key(key.uri.unwrap()));
+                        }
+                        if let Some(map) = pending_map.take() {
+                            // Unlike EXT-X-BYTERANGE, a map's range without an
+                            // offset starts at the beginning of the resource
+                            let range = map.byterange.map(|r| ByteRange {
+                                length: r.length,
+                                offset: r.offset.unwrap_or(0),
+                            });
+                            (self.data_added)(FileAdd::InitSegment {
+                                uri: map.uri,
+                                range,
+                            });
+                        }
+                        if let (Some(d), Some(t)) = (duration, target_duration) {
+                            if exceeds_target_duration(d, t) {
+                                (self.data_added)(FileAdd::SegmentTooLong {
+                                    uri: uri.clone(),
+                                    duration: d,
+                                    target_duration: t,
+                                });
+                            }
+                        }
+                        (self.data_added)(FileAdd::Segment {
+                            uri,
+                            sequence,
+                            duration: duration.take(),
+                            discontinuity,
+                            range,
+                            keys: keys.clone(),
+                        });
                     }
                 }
-                Line::Tag(t) => {
-                    // log::debug!("other tag: {:?}", t);
+                duration = None;
+                discontinuity = false;
+                gap = false;
+                sequence += 1;
+            }
+            _ => {}
+        })?;
+
+        self.report_gaps |= seen_segment;
+        self.ended = ended;
+        self.target_duration = target_duration;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn playlist(first: u64, count: u64) -> String {
+        let mut text = format!(
+            "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:{}\n",
+            first
+        );
+        for sequence in first..first + count {
+            text.push_str(&format!("#EXTINF:6.0,\n{}.ts\n", sequence));
+        }
+        text
+    }
+
+    /// What each update reported: segments by media sequence number, and
+    /// missed ranges
+    fn watch(start: Option<u64>, end: Option<u64>, updates: &[String]) -> Vec<String> {
+        let mut seen = Vec::new();
+        {
+            let mut watcher = ManifestWatcher::new(|event| match event {
+                FileAdd::Segment { sequence, .. } => seen.push(sequence.to_string()),
+                FileAdd::SegmentsMissed { from, to } => {
+                    seen.push(format!("missed {}-{}", from, to))
                 }
+                _ => {}
+            })
+            .sequence_range(start, end);
+            for update in updates {
+                watcher.update(update).unwrap();
             }
         }
+        seen
+    }
+
+    #[test]
+    fn reports_each_segment_once() {
+        let seen = watch(None, None, &[playlist(10, 3), playlist(11, 3)]);
+        assert_eq!(seen, ["10", "11", "12", "13"]);
+    }
+
+    #[test]
+    fn reports_segments_that_slid_out_of_the_window() {
+        let seen = watch(None, None, &[playlist(10, 2), playlist(15, 1)]);
+        assert_eq!(seen, ["10", "11", "missed 12-14", "15"]);
+
+        // Nothing was missed before the first update, unless asked for
+        assert_eq!(watch(None, None, &[playlist(20, 1)]), ["20"]);
+        assert_eq!(
+            watch(Some(18), None, &[playlist(20, 1)]),
+            ["missed 18-19", "20"]
+        );
+    }
+
+    #[test]
+    fn keeps_to_the_sequence_range() {
+        let seen = watch(Some(11), Some(12), &[playlist(10, 2), playlist(10, 5)]);
+        assert_eq!(seen, ["11", "12"]);
+    }
+
+    #[test]
+    fn counts_skipped_segments() {
+        let delta = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:10\n#EXT-X-SKIP:SKIPPED-SEGMENTS=2\n#EXTINF:6.0,\n12.ts\n#EXT-X-ENDLIST\n";
+        let seen = watch(None, None, &[playlist(10, 2), delta.to_owned()]);
+        assert_eq!(seen, ["10", "11", "12"]);
+    }
+
+    #[test]
+    fn reports_the_byte_range_of_init_segments() {
+        let manifest = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXT-X-MAP:URI=\"main.mp4\",BYTERANGE=\"720@0\"\n",
+            "#EXT-X-BYTERANGE:1000@720\n",
+            "#EXTINF:6.0,\n",
+            "main.mp4\n",
+            "#EXT-X-MAP:URI=\"other.mp4\",BYTERANGE=\"500\"\n",
+            "#EXTINF:6.0,\n",
+            "other.mp4\n",
+            "#EXT-X-MAP:URI=\"init.mp4\"\n",
+            "#EXTINF:6.0,\n",
+            "c.mp4\n",
+        );
+
+        let mut inits = Vec::new();
+        {
+            let mut watcher = ManifestWatcher::new(|event| {
+                if let FileAdd::InitSegment { uri, range } = event {
+                    inits.push((uri, range.map(|r| (r.offset, r.length))));
+                }
+            });
+            watcher.update(manifest).unwrap();
+        }
 
-        self.lines = new_manifest
+        assert_eq!(
+            inits,
+            [
+                ("main.mp4".to_owned(), Some((0, 720))),
+                ("other.mp4".to_owned(), Some((0, 500))),
+                ("init.mp4".to_owned(), None),
+            ]
+        );
     }
 }
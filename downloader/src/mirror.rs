@@ -0,0 +1,241 @@
+use hls::{Line, Tag};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Duration;
+use url::Url;
+
+use crate::http::HttpClient;
+use crate::journal::Journal;
+use crate::manifest_watcher::{FileAdd, ManifestWatcher};
+
+type Watcher = ManifestWatcher<Box<dyn FnMut(FileAdd)>>;
+type Events = Rc<RefCell<Vec<FileAdd>>>;
+
+const MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Pull-through replica of an origin. The master playlist and every playlist
+/// it references are re-fetched on each pass and rewritten in place, and any
+/// segments, keys and init segments that have not been seen before are
+/// downloaded. Files are stored under their original URL paths, so the output
+/// directory can be served as-is. Mirroring stops once every media playlist
+/// has ended.
+pub struct Mirror {
+    client: HttpClient,
+    output_dir: PathBuf,
+    interval: Duration,
+    tighten_interval: bool,
+    journal: Option<Journal>,
+    /// Where each media playlist was left by an earlier run
+    positions: HashMap<Url, u64>,
+    watchers: HashMap<Url, (Watcher, Events)>,
+}
+
+impl Mirror {
+    pub fn new(client: HttpClient, output_dir: &str, interval: Duration) -> Self {
+        Self {
+            client,
+            output_dir: PathBuf::from(output_dir),
+            interval,
+            tighten_interval: false,
+            journal: None,
+            positions: HashMap::new(),
+            watchers: HashMap::new(),
+        }
+    }
+
+    /// Poll more often whenever segments roll off a playlist before they are
+    /// mirrored
+    pub fn tighten_interval(mut self, tighten: bool) -> Self {
+        self.tighten_interval = tighten;
+        self
+    }
+
+    /// Record how far through each media playlist the mirror gets in
+    /// `journal`, and carry on from `positions`, as read from the journal of
+    /// an earlier run
+    pub fn with_journal(mut self, journal: Journal, positions: HashMap<Url, u64>) -> Self {
+        self.journal = Some(journal);
+        self.positions = positions;
+        self
+    }
+
+    pub async fn run(&mut self, master_url: &Url) -> Result<(), Box<dyn Error>> {
+        loop {
+            let master = self.fetch_playlist(master_url).await?;
+
+            let all_ended = match referenced_playlists(&master)? {
+                Some(playlists) => {
+                    let mut all_ended = true;
+                    for uri in playlists {
+                        let url = master_url.join(&uri)?;
+                        let text = self.fetch_playlist(&url).await?;
+                        all_ended &= self.mirror_media_playlist(&url, &text).await?;
+                    }
+                    all_ended
+                }
+                // A media playlist was given rather than a master playlist
+                None => self.mirror_media_playlist(master_url, &master).await?,
+            };
+
+            if all_ended {
+                log::info!("all playlists have ended; mirror complete");
+                return Ok(());
+            }
+
+            tokio::time::sleep(self.interval).await;
+        }
+    }
+
+    /// Take a one-off copy of a playlist and every playlist it references,
+    /// recursively, without downloading any media
+    pub async fn snapshot_playlists(&self, url: &Url) -> Result<(), Box<dyn Error>> {
+        let mut pending = vec![url.clone()];
+        let mut seen = HashSet::new();
+
+        while let Some(url) = pending.pop() {
+            if !seen.insert(url.clone()) {
+                continue;
+            }
+
+            log::info!("saving playlist {}", url);
+            let text = self.fetch_playlist(&url).await?;
+            for uri in referenced_playlists(&text)?.unwrap_or_default() {
+                pending.push(url.join(&uri)?);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Download anything new in a media playlist. Returns true once the
+    /// playlist has an EXT-X-ENDLIST tag.
+    async fn mirror_media_playlist(
+        &mut self,
+        url: &Url,
+        text: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        let (watcher, events) = self.watchers.entry(url.clone()).or_insert_with(|| {
+            let events: Events = Rc::new(RefCell::new(Vec::new()));
+            let sink = events.clone();
+            let start = self.positions.get(url).copied();
+            let watcher: Watcher =
+                ManifestWatcher::new(Box::new(move |event| sink.borrow_mut().push(event)));
+            (watcher.sequence_range(start, None), events)
+        });
+
+        watcher.update(text)?;
+        if let Some(journal) = self.journal.as_ref() {
+            journal.reached(url, watcher.next_sequence());
+        }
+        let new_files: Vec<_> = events.borrow_mut().drain(..).collect();
+
+        for file in new_files {
+            let (uri, is_key) = match file {
+                // The mirrored playlist keeps its byte ranges, so files are
+                // fetched whole
+                FileAdd::Segment { uri, .. } | FileAdd::InitSegment { uri, .. } => (uri, false),
+                FileAdd::Key(uri) => (uri, true),
+                FileAdd::SegmentsMissed { from, to } => {
+                    log::warn!("segments {} to {} of {} were missed", from, to, url);
+                    if self.tighten_interval && self.interval > MIN_INTERVAL {
+                        self.interval = (self.interval / 2).max(MIN_INTERVAL);
+                        log::info!("polling every {:?} from now on", self.interval);
+                    }
+                    continue;
+                }
+                // Gap segments are listed but may not exist on the origin
+                FileAdd::Gap { .. } => continue,
+                FileAdd::SegmentTooLong {
+                    uri,
+                    duration,
+                    target_duration,
+                } => {
+                    log::warn!(
+                        "{} in {} is {}s, longer than the target duration of {}s",
+                        uri,
+                        url,
+                        duration,
+                        target_duration
+                    );
+                    continue;
+                }
+            };
+            let file_url = url.join(&uri)?;
+            let path = self.local_path(&file_url);
+
+            if path.exists() {
+                continue;
+            }
+
+            log::debug!("mirroring {}", file_url);
+            let body = if is_key {
+                self.client.fetch_key(&file_url).await?
+            } else {
+                self.client
+                    .fetch(&file_url)
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?
+            };
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, body)?;
+        }
+
+        let mut ended = false;
+        hls::for_each(text, |line: Line| {
+            ended |= matches!(line, Line::Tag(Tag::Endlist));
+        })?;
+
+        Ok(ended)
+    }
+
+    /// Download a playlist and atomically replace the local copy
+    async fn fetch_playlist(&self, url: &Url) -> Result<String, Box<dyn Error>> {
+        let text = self
+            .client
+            .fetch(url)
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let path = self.local_path(url);
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let tmp = path.with_extension("m3u8.tmp");
+        std::fs::write(&tmp, &text)?;
+        std::fs::rename(tmp, path)?;
+
+        Ok(text)
+    }
+
+    fn local_path(&self, url: &Url) -> PathBuf {
+        self.output_dir
+            .join(Path::new(url.path().trim_start_matches('/')))
+    }
+}
+
+/// URIs of the variant, rendition and I-frame playlists referenced by a
+/// master playlist, or None if `text` is a media playlist
+fn referenced_playlists(text: &str) -> Result<Option<Vec<String>>, hls::Error> {
+    let mut is_master = false;
+    let mut playlists = Vec::new();
+    hls::for_each(text, |line: Line| match line {
+        Line::Tag(Tag::Media(attrs)) => {
+            is_master = true;
+            playlists.extend(attrs.uri);
+        }
+        Line::Tag(Tag::IFrameStreamInf(attrs)) => {
+            is_master = true;
+            playlists.push(attrs.uri);
+        }
+        Line::Tag(Tag::StreamInf(_)) => is_master = true,
+        Line::Uri(uri) => playlists.push(uri),
+        _ => {}
+    })?;
+
+    Ok(is_master.then_some(playlists))
+}
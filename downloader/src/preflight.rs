@@ -0,0 +1,84 @@
+use std::path::Path;
+
+use crate::http::{HttpClient, HttpError};
+use crate::work_queue::WorkItem;
+
+/// Estimated size of a download, from HEAD requests for some or all of its
+/// files
+#[derive(Debug)]
+pub struct Estimate {
+    pub files: usize,
+    pub sampled: usize,
+    pub bytes: u64,
+}
+
+/// Estimate the total size of `items` with HEAD requests. With a sample size,
+/// only that many evenly spaced files are checked and the total is
+/// extrapolated from them.
+pub async fn estimate(
+    client: &HttpClient,
+    items: &[WorkItem],
+    sample: Option<usize>,
+) -> Result<Estimate, HttpError> {
+    let sample = sample.unwrap_or(items.len()).clamp(1, items.len().max(1));
+    let step = items.len() as f64 / sample as f64;
+    let mut sampled = 0;
+    let mut bytes = 0;
+
+    for i in 0..sample {
+        let Some(work_item) = items.get((i as f64 * step) as usize) else {
+            break;
+        };
+
+        // Byte ranges already say how big they are
+        let length = match work_item.range {
+            Some(range) => Some(range.length),
+            None => client.content_length(&work_item.remote_url).await?,
+        };
+
+        match length {
+            Some(length) => {
+                sampled += 1;
+                bytes += length;
+            }
+            None => log::debug!("no Content-Length for {}", work_item.remote_url),
+        }
+    }
+
+    if sampled > 0 {
+        bytes = (bytes as f64 * items.len() as f64 / sampled as f64) as u64;
+    }
+
+    Ok(Estimate {
+        files: items.len(),
+        sampled,
+        bytes,
+    })
+}
+
+/// Free space available to this user on the filesystem holding `path`
+#[cfg(unix)]
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+
+    // SAFETY: `path` is a valid C string and `stat` is only read if statvfs
+    // reports that it filled it in
+    let stat = unsafe {
+        if libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        stat.assume_init()
+    };
+
+    #[allow(clippy::unnecessary_cast)]
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn available_space(_path: &Path) -> std::io::Result<u64> {
+    Ok(u64::MAX)
+}
@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use crate::work_queue::{FileType, WorkItem};
+
+pub type ProcessError = Box<dyn Error + Send + Sync>;
+
+/// A transformation applied to each downloaded file before it is stored,
+/// e.g. decrypting, stripping ID3 tags or remuxing. Processors are chained,
+/// each receiving the output of the one before it.
+pub trait SegmentProcessor: Send + Sync {
+    fn process(&self, work_item: &WorkItem, data: Vec<u8>) -> Result<Vec<u8>, ProcessError>;
+}
+
+/// Run a chain of processors over `data`
+pub fn process_all(
+    processors: &[Box<dyn SegmentProcessor>],
+    work_item: &WorkItem,
+    data: Vec<u8>,
+) -> Result<Vec<u8>, ProcessError> {
+    processors
+        .iter()
+        .try_fold(data, |data, processor| processor.process(work_item, data))
+}
+
+/// Pipes media segments through an external command run with `sh -c`. The
+/// segment is written to the command's stdin and whatever it prints to stdout
+/// is stored in its place. Keys and init segments are passed through as is.
+pub struct CommandProcessor {
+    command: String,
+}
+
+impl CommandProcessor {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+}
+
+impl SegmentProcessor for CommandProcessor {
+    fn process(&self, work_item: &WorkItem, data: Vec<u8>) -> Result<Vec<u8>, ProcessError> {
+        if !matches!(work_item.file_type, FileType::MediaSegment) {
+            return Ok(data);
+        }
+
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .env("SEGMENT_URL", work_item.remote_url.as_str())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        // Feed stdin from another thread so a command that writes output
+        // before it has read all of its input can't deadlock
+        let mut stdin = child.stdin.take().unwrap();
+        let writer = std::thread::spawn(move || stdin.write_all(&data));
+        let output = child.wait_with_output()?;
+        writer.join().unwrap()?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "process command failed on {} ({})",
+                work_item.remote_url, output.status
+            )
+            .into());
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::PathBuf;
+    use url::Url;
+
+    struct Append(u8);
+
+    impl SegmentProcessor for Append {
+        fn process(&self, _: &WorkItem, mut data: Vec<u8>) -> Result<Vec<u8>, ProcessError> {
+            data.push(self.0);
+            Ok(data)
+        }
+    }
+
+    struct Fail;
+
+    impl SegmentProcessor for Fail {
+        fn process(&self, _: &WorkItem, _: Vec<u8>) -> Result<Vec<u8>, ProcessError> {
+            Err("stage failed".into())
+        }
+    }
+
+    fn segment() -> WorkItem {
+        let url = Url::parse("https://example.com/a.ts").unwrap();
+        WorkItem::new(PathBuf::from("a.ts"), url, FileType::MediaSegment)
+    }
+
+    #[test]
+    fn chains_processors_in_order() {
+        let processors: Vec<Box<dyn SegmentProcessor>> =
+            vec![Box::new(Append(1)), Box::new(Append(2))];
+        let out = process_all(&processors, &segment(), vec![0]).unwrap();
+        assert_eq!(out, [0, 1, 2]);
+    }
+
+    #[test]
+    fn stops_at_a_failing_stage() {
+        let processors: Vec<Box<dyn SegmentProcessor>> =
+            vec![Box::new(Append(1)), Box::new(Fail), Box::new(Append(2))];
+        let err = process_all(&processors, &segment(), vec![0]).unwrap_err();
+        assert_eq!(err.to_string(), "stage failed");
+    }
+
+    #[test]
+    fn reports_failing_commands() {
+        let processor = CommandProcessor::new("cat >/dev/null; exit 3".to_owned());
+        assert!(processor.process(&segment(), vec![0]).is_err());
+
+        let processor = CommandProcessor::new("tr a b".to_owned());
+        assert_eq!(
+            processor.process(&segment(), b"aa".to_vec()).unwrap(),
+            b"bb"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Counts of files queued and downloaded, shared between whatever queues
+/// files and the download workers
+pub struct Progress {
+    started: Instant,
+    queued: AtomicU64,
+    completed: AtomicU64,
+    bytes: AtomicU64,
+    /// Whether every file has been queued, as for a VOD playlist, so an ETA
+    /// can be given
+    listing_complete: AtomicBool,
+}
+
+impl Progress {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            started: Instant::now(),
+            queued: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            listing_complete: AtomicBool::new(false),
+        })
+    }
+
+    pub fn queued(&self) {
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A file was stored, `bytes` of which were downloaded rather than
+    /// already available locally
+    pub fn completed(&self, bytes: u64) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Bytes downloaded so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn set_listing_complete(&self, complete: bool) {
+        self.listing_complete.store(complete, Ordering::Relaxed);
+    }
+
+    /// Print a status line to stderr every `interval`, forever
+    pub async fn report(self: Arc<Self>, interval: Duration) {
+        let mut last_bytes = 0;
+        loop {
+            tokio::time::sleep(interval).await;
+            let bytes = self.bytes.load(Ordering::Relaxed);
+            let rate = (bytes - last_bytes) as f64 / interval.as_secs_f64();
+            last_bytes = bytes;
+            eprintln!("{}", self.status(rate, true));
+        }
+    }
+
+    /// Print the final totals to stderr
+    pub fn summary(&self) {
+        let elapsed = self.started.elapsed();
+        let rate = self.bytes.load(Ordering::Relaxed) as f64 / elapsed.as_secs_f64().max(1.0);
+        eprintln!(
+            "{} in {}",
+            self.status(rate, false),
+            humantime::format_duration(Duration::from_secs(elapsed.as_secs()))
+        );
+    }
+
+    fn status(&self, rate: f64, eta: bool) -> String {
+        let queued = self.queued.load(Ordering::Relaxed);
+        let completed = self.completed.load(Ordering::Relaxed);
+        let bytes = self.bytes.load(Ordering::Relaxed);
+        let mut status = format!(
+            "{}/{} files, {} downloaded, {}/s",
+            completed,
+            queued,
+            format_bytes(bytes as f64),
+            format_bytes(rate)
+        );
+
+        // Assumes the remaining files are about the size of those so far
+        if eta
+            && self.listing_complete.load(Ordering::Relaxed)
+            && completed > 0
+            && completed < queued
+            && rate > 0.0
+        {
+            let remaining = bytes as f64 / completed as f64 * (queued - completed) as f64;
+            let eta = Duration::from_secs((remaining / rate) as u64);
+            status.push_str(&format!(", ETA {}", humantime::format_duration(eta)));
+        }
+
+        status
+    }
+}
+
+/// A size like "10G", "500MiB" or "1.5 GB", in binary units, or a plain
+/// number of bytes
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("expected a size like 10G, got '{}'", s))?;
+
+    let unit = unit.trim();
+    let unit = unit.strip_suffix(['B', 'b']).unwrap_or(unit);
+    let unit = match unit.strip_suffix('i') {
+        Some(prefix) if !prefix.is_empty() => prefix,
+        _ => unit,
+    };
+    let scale = match unit.to_ascii_uppercase().as_str() {
+        "" => 1u64,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return Err(format!("unknown size unit in '{}'", s)),
+    };
+    Ok((number * scale as f64) as u64)
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("10G"), Ok(10 << 30));
+        assert_eq!(parse_size("500MiB"), Ok(500 << 20));
+        assert_eq!(parse_size("1.5 GB"), Ok(3 << 29));
+        assert_eq!(parse_size("2kb"), Ok(2048));
+        assert_eq!(parse_size("7B"), Ok(7));
+
+        assert!(parse_size("").is_err());
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("-5M").is_err());
+        assert!(parse_size("1.2.3M").is_err());
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("10iB").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use hls::{MasterPlaylist, MediaAttributes, MediaType};
+use std::path::Path;
+use url::{ParseError, Url};
+
+use crate::fs;
+use crate::manifest_watcher::FileAdd;
+use crate::work_queue::{FileType, WorkItem};
+
+/// A separate audio or subtitle playlist to download alongside a variant
+pub struct Rendition {
+    pub url: Url,
+    /// Directory the rendition's files are stored in, e.g. `audio-English`
+    pub dir: String,
+}
+
+/// Pick the highest bandwidth variant of a master playlist, or `preferred`
+/// if the playlist still has it, along with the audio and subtitle
+/// renditions of its groups that have their own playlists. None if the
+/// playlist has no variants.
+pub fn select_variant(
+    master: &MasterPlaylist,
+    base_url: &Url,
+    preferred: Option<&Url>,
+) -> Result<Option<(Url, Vec<Rendition>)>, ParseError> {
+    let preferred = master
+        .variants
+        .iter()
+        .find(|v| base_url.join(&v.uri).ok().as_ref() == preferred);
+    let variant =
+        match preferred.or_else(|| master.variants.iter().max_by_key(|v| v.attrs.bandwidth)) {
+            Some(variant) => variant,
+            None => return Ok(None),
+        };
+
+    let mut renditions = Vec::new();
+    let groups = variant
+        .audio_renditions(master)
+        .chain(variant.subtitle_renditions(master));
+    for media in groups {
+        if let Some(uri) = media.uri.as_ref() {
+            renditions.push(Rendition {
+                url: base_url.join(uri)?,
+                dir: rendition_dir(media),
+            });
+        }
+    }
+
+    Ok(Some((base_url.join(&variant.uri)?, renditions)))
+}
+
+/// The file to download for an event of a rendition's playlist, stored under
+/// the rendition's directory
+pub fn work_item(rendition: &Rendition, event: FileAdd) -> Option<WorkItem> {
+    let (uri, file_type, range, sequence) = match event {
+        FileAdd::Segment {
+            uri,
+            range,
+            sequence,
+            ..
+        } => (uri, FileType::MediaSegment, range, Some(sequence)),
+        FileAdd::InitSegment { uri, range } => (uri, FileType::InitSegment, range, None),
+        FileAdd::Key(uri) => (uri, FileType::Key, None, None),
+        FileAdd::SegmentsMissed { from, to } => {
+            log::warn!(
+                "segments {} to {} of {} were missed",
+                from,
+                to,
+                rendition.dir
+            );
+            return None;
+        }
+        FileAdd::Gap { .. } | FileAdd::SegmentTooLong { .. } => return None,
+    };
+
+    let work_item = local_work_item(rendition, &uri, file_type);
+    Some(work_item.with_range(range).with_sequence(sequence))
+}
+
+/// The file a URI of a rendition's playlist is stored as
+pub fn local_work_item(rendition: &Rendition, uri: &str, file_type: FileType) -> WorkItem {
+    let mut work_item = fs::parse_path_from_url(&rendition.url, uri, file_type).unwrap();
+    work_item.local_path = Path::new(&rendition.dir).join(&work_item.local_path);
+    work_item
+}
+
+fn rendition_dir(media: &MediaAttributes) -> String {
+    let kind = match media.media_type {
+        MediaType::Audio => "audio",
+        MediaType::Video => "video",
+        MediaType::Subtitles => "subtitles",
+        MediaType::ClosedCaptions => "closed-captions",
+    };
+    let name: String = media
+        .name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}-{}", kind, name)
+}
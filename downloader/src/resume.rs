@@ -0,0 +1,43 @@
+use std::path::Path;
+
+use crate::http::HttpClient;
+use crate::work_queue::WorkItem;
+
+/// How to tell whether a file left by an earlier run is complete
+#[derive(Clone, Copy, Debug)]
+pub enum Resume {
+    /// Any non-empty file is taken to be complete
+    Exists,
+    /// The file must be as big as the server says it is
+    VerifySize,
+}
+
+/// Whether `path` already holds the whole of a work item's file, so it
+/// needn't be downloaded again. Byte ranges are checked against their
+/// length either way.
+pub async fn is_complete(
+    client: &HttpClient,
+    work_item: &WorkItem,
+    path: &Path,
+    resume: Resume,
+) -> bool {
+    let size = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(_) => return false,
+    };
+
+    if let Some(range) = work_item.range {
+        return size == range.length;
+    }
+
+    match resume {
+        Resume::Exists => size > 0,
+        Resume::VerifySize => match client.content_length(&work_item.remote_url).await {
+            Ok(length) => length == Some(size),
+            Err(e) => {
+                log::debug!("couldn't check {}: {}", work_item.remote_url, e);
+                false
+            }
+        },
+    }
+}
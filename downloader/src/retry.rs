@@ -0,0 +1,98 @@
+use reqwest::StatusCode;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use crate::http::HttpError;
+
+/// How failed downloads are retried: after a delay that doubles with each
+/// attempt, with random jitter so workers don't all retry at once
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            ..Default::default()
+        }
+    }
+
+    /// How long to wait after attempt number `attempt`, counting from 1,
+    /// has failed. Somewhere between half and all of the backoff.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(1 << (attempt - 1).min(16))
+            .min(self.max_delay);
+        let half = backoff / 2;
+        let jitter = RandomState::new().build_hasher().finish() % (half.as_millis() as u64 + 1);
+        half + Duration::from_millis(jitter)
+    }
+
+    /// Whether a failure might go away on its own. Errors without a
+    /// response, such as dropped connections, might.
+    pub fn should_retry(&self, error: &HttpError) -> bool {
+        match error {
+            HttpError::Request(e) => e.status().is_none_or(transient),
+            HttpError::Sign(_) => false,
+        }
+    }
+}
+
+/// Whether a response status might change if the request is repeated.
+/// Client errors won't, apart from timeouts and rate limiting.
+fn transient(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classifies_statuses() {
+        assert!(transient(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(transient(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(transient(StatusCode::TOO_MANY_REQUESTS));
+        assert!(transient(StatusCode::REQUEST_TIMEOUT));
+
+        assert!(!transient(StatusCode::NOT_FOUND));
+        assert!(!transient(StatusCode::FORBIDDEN));
+        assert!(!transient(StatusCode::GONE));
+    }
+
+    #[test]
+    fn does_not_retry_signing_failures() {
+        let error = HttpError::Sign("signer exited with 1".into());
+        assert!(!RetryPolicy::default().should_retry(&error));
+    }
+
+    #[test]
+    fn backs_off_up_to_the_limit() {
+        let policy = RetryPolicy::default();
+        for (attempt, backoff) in [(1, 500), (2, 1000), (3, 2000), (20, 30_000)] {
+            let delay = policy.delay(attempt);
+            let backoff = Duration::from_millis(backoff);
+            assert!(delay >= backoff / 2 && delay <= backoff, "{:?}", delay);
+        }
+        assert_eq!(RetryPolicy::new(0).max_attempts, 1);
+    }
+}
@@ -0,0 +1,110 @@
+use regex::Regex;
+use std::str::FromStr;
+use url::Url;
+
+/// A `pattern=>replacement` rule applied to outgoing request URLs. The
+/// replacement may refer to capture groups of the pattern as `$1`, `$name`,
+/// etc.
+#[derive(Clone, Debug)]
+pub struct RewriteRule {
+    pattern: Regex,
+    replacement: String,
+}
+
+impl RewriteRule {
+    pub fn apply(&self, url: &str) -> String {
+        self.pattern
+            .replace_all(url, self.replacement.as_str())
+            .into_owned()
+    }
+}
+
+impl FromStr for RewriteRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, replacement) = s
+            .split_once("=>")
+            .ok_or_else(|| format!("expected 'pattern=>replacement', got '{}'", s))?;
+        let pattern = Regex::new(pattern).map_err(|e| e.to_string())?;
+        let replacement = replacement.to_owned();
+
+        Ok(Self {
+            pattern,
+            replacement,
+        })
+    }
+}
+
+/// Run a URL through each rule in order. If the rewritten URL no longer
+/// parses, the original is kept.
+pub fn rewrite_url(rules: &[RewriteRule], url: &Url) -> Url {
+    if rules.is_empty() {
+        return url.clone();
+    }
+
+    let rewritten = rules
+        .iter()
+        .fold(url.as_str().to_owned(), |url, rule| rule.apply(&url));
+
+    match Url::parse(&rewritten) {
+        Ok(rewritten) => {
+            log::debug!("rewrote {} -> {}", url, rewritten);
+            rewritten
+        }
+        Err(e) => {
+            log::warn!(
+                "ignoring rewrite of {} to invalid URL {}: {}",
+                url,
+                rewritten,
+                e
+            );
+            url.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_rules() {
+        let rule: RewriteRule = r"^http://cdn(\d)\.example\.com/=>https://mirror$1.example.net/"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            rule.apply("http://cdn2.example.com/a.ts"),
+            "https://mirror2.example.net/a.ts"
+        );
+
+        let rule: RewriteRule = "token=[^&]*=>".parse().unwrap();
+        assert_eq!(
+            rule.apply("https://a/b.ts?token=x&q=1"),
+            "https://a/b.ts?&q=1"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_rules() {
+        assert!("no arrow".parse::<RewriteRule>().is_err());
+        assert!("(unclosed=>x".parse::<RewriteRule>().is_err());
+    }
+
+    #[test]
+    fn rewrites_urls_in_order() {
+        let rules: Vec<RewriteRule> = ["http:=>https:", "example.com=>example.net"]
+            .iter()
+            .map(|rule| rule.parse().unwrap())
+            .collect();
+        let url = Url::parse("http://example.com/a.ts").unwrap();
+        assert_eq!(
+            rewrite_url(&rules, &url).as_str(),
+            "https://example.net/a.ts"
+        );
+
+        // A rewrite to something that isn't a URL is ignored
+        let rules = vec!["^https?://=>".parse().unwrap()];
+        assert_eq!(rewrite_url(&rules, &url), url);
+    }
+}
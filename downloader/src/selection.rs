@@ -0,0 +1,150 @@
+use hls::{MediaPlaylist, Playlist};
+use std::error::Error;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Media sequence numbers of the first and last segments to download, either
+/// of which may be left open
+pub type SequenceRange = (Option<u64>, Option<u64>);
+
+/// A `--segments` range of media sequence numbers, e.g. `100-250`, `100-`
+/// or `-250`. Both ends are included.
+#[derive(Clone, Copy, Debug)]
+pub struct Segments(pub SequenceRange);
+
+impl FromStr for Segments {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected 'first-last', got '{}'", s))?;
+        let bound = |n: &str| match n.trim() {
+            "" => Ok(None),
+            n => n
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("bad sequence number '{}'", n)),
+        };
+
+        match (bound(start)?, bound(end)?) {
+            (Some(start), Some(end)) if start > end => {
+                Err(format!("{} comes after {} in '{}'", start, end, s))
+            }
+            range => Ok(Self(range)),
+        }
+    }
+}
+
+/// A point in or length of a playlist's timeline, either as a timestamp
+/// like `01:10:00`, `10:30` or `90.5`, or as a duration like `1h 10m`
+pub fn parse_time(s: &str) -> Result<Duration, String> {
+    if let Ok(duration) = humantime::parse_duration(s) {
+        return Ok(duration);
+    }
+
+    let invalid = || format!("expected a time like 01:10:00 or 1h 10m, got '{}'", s);
+    if s.split(':').count() > 3 {
+        return Err(invalid());
+    }
+    let mut seconds = 0.0;
+    for part in s.split(':') {
+        let part: f64 = part.parse().map_err(|_| invalid())?;
+        seconds = seconds * 60.0 + part;
+    }
+    Duration::try_from_secs_f64(seconds).map_err(|_| invalid())
+}
+
+/// The segments of a VOD playlist that cover `duration` of its timeline from
+/// `start`, by their EXTINF durations, or everything after `start` without a
+/// duration
+pub fn time_range(
+    manifest: &str,
+    start: Duration,
+    duration: Option<Duration>,
+) -> Result<SequenceRange, Box<dyn Error>> {
+    let playlist = match Playlist::parse(manifest)? {
+        Playlist::Media(playlist) if playlist.ended => playlist,
+        _ => return Err("selecting by time needs a VOD media playlist".into()),
+    };
+
+    segments_between(&playlist, start, duration).ok_or_else(|| {
+        let length = playlist.segments.iter().map(|s| s.duration).sum::<f64>();
+        format!("the playlist is only {:.1}s long", length).into()
+    })
+}
+
+fn segments_between(
+    playlist: &MediaPlaylist,
+    start: Duration,
+    duration: Option<Duration>,
+) -> Option<SequenceRange> {
+    let start = start.as_secs_f64();
+    let end = duration.map(|d| start + d.as_secs_f64());
+    let mut first = None;
+    let mut last = None;
+    let mut elapsed = 0.0;
+
+    for segment in &playlist.segments {
+        if end.is_some_and(|end| elapsed >= end) {
+            break;
+        }
+        let segment_end = elapsed + segment.duration;
+        if segment_end > start {
+            first.get_or_insert(segment.sequence);
+            last = Some(segment.sequence);
+        }
+        elapsed = segment_end;
+    }
+
+    Some((Some(first?), last.filter(|_| end.is_some())))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_segment_ranges() {
+        let range = |s: &str| s.parse::<Segments>().map(|segments| segments.0);
+        assert_eq!(range("100-250"), Ok((Some(100), Some(250))));
+        assert_eq!(range("100-"), Ok((Some(100), None)));
+        assert_eq!(range("-250"), Ok((None, Some(250))));
+        assert_eq!(range(" 7 - 7 "), Ok((Some(7), Some(7))));
+
+        assert!(range("100").is_err());
+        assert!(range("a-b").is_err());
+        assert!(range("250-100").is_err());
+        assert!(range("1-2-3").is_err());
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_time("1h 30m"), Ok(Duration::from_secs(5400)));
+        assert_eq!(parse_time("00:05:00"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_time("10:30"), Ok(Duration::from_secs(630)));
+        assert_eq!(parse_time("90.5"), Ok(Duration::from_millis(90_500)));
+
+        assert!(parse_time("").is_err());
+        assert!(parse_time("soon").is_err());
+        assert!(parse_time("-5").is_err());
+        assert!(parse_time("NaN").is_err());
+        assert!(parse_time("1::2").is_err());
+        assert!(parse_time("1:00:00:00").is_err());
+    }
+
+    #[test]
+    fn selects_segments_by_time() {
+        let vod = "#EXTM3U\n#EXT-X-TARGETDURATION:10\n#EXT-X-MEDIA-SEQUENCE:5\n#EXTINF:10,\na.ts\n#EXTINF:10,\nb.ts\n#EXTINF:10,\nc.ts\n#EXT-X-ENDLIST\n";
+        let secs = Duration::from_secs;
+        assert_eq!(time_range(vod, secs(0), None).unwrap(), (Some(5), None));
+        assert_eq!(
+            time_range(vod, secs(12), Some(secs(10))).unwrap(),
+            (Some(6), Some(7))
+        );
+
+        assert!(time_range(vod, secs(30), None).is_err());
+        let live = vod.trim_end_matches("#EXT-X-ENDLIST\n");
+        assert!(time_range(live, secs(0), None).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use reqwest::header::{HeaderName, HeaderValue};
+use reqwest::Request;
+use std::error::Error;
+use std::process::Command;
+use url::Url;
+
+pub type SignError = Box<dyn Error + Send + Sync>;
+
+/// A step run on every request just before it is sent, for origins that need
+/// short-lived tokens or per-request signatures rather than static headers
+pub trait RequestSigner: Send + Sync {
+    fn sign(&self, request: &mut Request) -> Result<(), SignError>;
+}
+
+/// Signs requests by running an external command through `sh -c`, with the
+/// request method and URL appended as arguments. Each line it
+/// prints is either a `Name: value` header to set on the request or an
+/// absolute URL to send the request to instead (e.g. with a token appended).
+pub struct CommandSigner {
+    command: String,
+}
+
+impl CommandSigner {
+    pub fn new(command: String) -> Self {
+        Self { command }
+    }
+}
+
+impl RequestSigner for CommandSigner {
+    fn sign(&self, request: &mut Request) -> Result<(), SignError> {
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!("{} \"$@\"", self.command))
+            .arg("sign")
+            .arg(request.method().as_str())
+            .arg(request.url().as_str())
+            .output()?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "sign command failed ({}): {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+
+        for line in String::from_utf8(output.stdout)?.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Ok(url) = Url::parse(line) {
+                *request.url_mut() = url;
+            } else if let Some((name, value)) = line.split_once(':') {
+                let name = HeaderName::from_bytes(name.trim().as_bytes())?;
+                let value = HeaderValue::from_str(value.trim())?;
+                request.headers_mut().insert(name, value);
+            } else {
+                return Err(format!("unexpected sign command output '{}'", line).into());
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use bytes::Bytes;
+use futures::stream::{self, Stream, StreamExt};
+use hls::{EncryptionMethod, KeyAttributes};
+use openssl::symm::{decrypt, Cipher};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use url::Url;
+
+use crate::http::{HttpClient, HttpError};
+use crate::manifest_watcher::{FileAdd, ManifestWatcher};
+use crate::work_queue::ByteRange;
+
+/// A segment or init segment to send, with the AES-128 key and IV it was
+/// encrypted with, if any
+struct Part {
+    url: Url,
+    range: Option<ByteRange>,
+    key: Option<(Url, Vec<u8>)>,
+}
+
+/// The media of a playlist as one in-order stream of bytes, for feeding
+/// straight into a demuxer or player rather than going through files. Init
+/// segments are sent ahead of the segments that use them. Up to
+/// `concurrency` segments are fetched ahead of the one being read.
+///
+/// AES-128 segments are decrypted. Segments encrypted any other way, such as
+/// SAMPLE-AES or with a DRM key, are passed through as served.
+pub fn segment_stream<'a>(
+    client: &'a HttpClient,
+    base_url: &Url,
+    manifest: &str,
+    sequence_range: (Option<u64>, Option<u64>),
+    concurrency: usize,
+) -> Result<impl Stream<Item = Result<Bytes, StreamError>> + 'a, Box<dyn std::error::Error>> {
+    let mut files = Vec::new();
+    let mut watcher = ManifestWatcher::new(|file| match file {
+        FileAdd::Segment {
+            uri,
+            sequence,
+            range,
+            keys,
+            ..
+        } => files.push((uri, range, aes_key(&keys, sequence))),
+        FileAdd::InitSegment { uri, range } => files.push((uri, range, None)),
+        _ => {}
+    })
+    .sequence_range(sequence_range.0, sequence_range.1);
+    watcher.update(manifest)?;
+
+    let parts = files
+        .into_iter()
+        .map(|(uri, range, key)| {
+            let key = match key {
+                Some((uri, iv)) => Some((base_url.join(&uri)?, iv)),
+                None => None,
+            };
+            Ok(Part {
+                url: base_url.join(&uri)?,
+                range,
+                key,
+            })
+        })
+        .collect::<Result<Vec<_>, url::ParseError>>()?;
+
+    let keys = Arc::new(KeyCache::default());
+    Ok(stream::iter(parts)
+        .map(move |part| {
+            let keys = keys.clone();
+            async move {
+                let body = client.fetch_range(&part.url, part.range).await?;
+                match part.key {
+                    Some((url, iv)) => {
+                        let key = keys.get(client, &url).await?;
+                        let plain = decrypt(Cipher::aes_128_cbc(), &key, Some(&iv), &body)
+                            .map_err(|e| StreamError::Decrypt(part.url.clone(), e))?;
+                        Ok(Bytes::from(plain))
+                    }
+                    None => Ok(body),
+                }
+            }
+        })
+        .buffered(concurrency))
+}
+
+#[derive(Debug)]
+pub enum StreamError {
+    Http(HttpError),
+    Decrypt(Url, openssl::error::ErrorStack),
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Http(e) => e.fmt(f),
+            Self::Decrypt(url, e) => write!(f, "failed to decrypt {}: {}", url, e),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+impl From<HttpError> for StreamError {
+    fn from(e: HttpError) -> Self {
+        Self::Http(e)
+    }
+}
+
+/// Keys fetched so far, as most segments share a key with their neighbours
+#[derive(Default)]
+struct KeyCache {
+    keys: Mutex<HashMap<Url, Bytes>>,
+}
+
+impl KeyCache {
+    async fn get(&self, client: &HttpClient, url: &Url) -> Result<Bytes, HttpError> {
+        if let Some(key) = self.keys.lock().unwrap().get(url) {
+            return Ok(key.clone());
+        }
+
+        let key = client.fetch_key(url).await?;
+        self.keys.lock().unwrap().insert(url.clone(), key.clone());
+        Ok(key)
+    }
+}
+
+/// The URI and IV of a segment's AES-128 key. Without an IV attribute, the
+/// IV is the segment's media sequence number.
+fn aes_key(keys: &[KeyAttributes], sequence: u64) -> Option<(String, Vec<u8>)> {
+    let key = keys
+        .iter()
+        .find(|key| key.method == EncryptionMethod::Aes128 && key.is_identity());
+    let Some(key) = key else {
+        if keys.iter().any(|key| key.method != EncryptionMethod::None) {
+            log::warn!("segment {} can't be decrypted, sending as is", sequence);
+        }
+        return None;
+    };
+    let iv = match key.iv.clone() {
+        Some(iv) => iv,
+        None => (sequence as u128).to_be_bytes().to_vec(),
+    };
+    Some((key.uri.clone()?, iv))
+}
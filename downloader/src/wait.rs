@@ -0,0 +1,32 @@
+use reqwest::StatusCode;
+use std::time::Duration;
+use tokio::time::Instant;
+use url::Url;
+
+use crate::http::{HttpClient, HttpError};
+
+/// Poll `url` every `interval` for as long as it returns 404 or 403, as
+/// origins do before an event has started. Returns false if it still hadn't
+/// appeared after `timeout`.
+pub async fn wait_for_playlist(
+    client: &HttpClient,
+    url: &Url,
+    interval: Duration,
+    timeout: Duration,
+) -> Result<bool, HttpError> {
+    let give_up = Instant::now() + timeout;
+
+    loop {
+        let status = client.fetch(url).await?.status();
+        if status != StatusCode::NOT_FOUND && status != StatusCode::FORBIDDEN {
+            return Ok(true);
+        }
+
+        if Instant::now() + interval > give_up {
+            return Ok(false);
+        }
+
+        log::info!("{} is not available yet ({}); waiting", url, status);
+        tokio::time::sleep(interval).await;
+    }
+}
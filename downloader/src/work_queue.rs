@@ -1,19 +1,73 @@
-use std::collections::VecDeque;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::sync::Notify;
 
 use url::Url;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub enum FileType {
+    IFrame,
+    InitSegment,
     Key,
     MediaSegment,
 }
 
-#[derive(Debug)]
+/// A contiguous range of bytes within a remote resource
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub struct ByteRange {
+    pub length: u64,
+    pub offset: u64,
+}
+
+impl ByteRange {
+    /// Value for an HTTP `Range` request header
+    pub fn header_value(&self) -> String {
+        format!("bytes={}-{}", self.offset, self.offset + self.length - 1)
+    }
+
+    /// Cut the range out of a whole resource, for origins that ignore Range
+    /// requests
+    pub fn slice(&self, body: Bytes) -> Bytes {
+        let start = (self.offset as usize).min(body.len());
+        let end = (start + self.length as usize).min(body.len());
+        body.slice(start..end)
+    }
+}
+
+/// Fills in the offsets EXT-X-BYTERANGE leaves out. A range without an
+/// offset starts where the previous range of the same resource ended.
+#[derive(Default)]
+pub struct RangeTracker {
+    last_end: Option<(String, u64)>,
+}
+
+impl RangeTracker {
+    pub fn resolve(&mut self, uri: &str, range: hls::ByteRange) -> ByteRange {
+        let offset = range.offset.unwrap_or(match &self.last_end {
+            Some((last_uri, end)) if last_uri == uri => *end,
+            _ => 0,
+        });
+        self.last_end = Some((uri.to_owned(), offset + range.length));
+
+        ByteRange {
+            length: range.length,
+            offset,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct WorkItem {
     pub local_path: PathBuf,
     pub remote_url: Url,
     pub file_type: FileType,
+    /// The part of the remote resource to download, if not all of it
+    pub range: Option<ByteRange>,
+    /// Media sequence number, for a segment
+    pub sequence: Option<u64>,
 }
 
 impl WorkItem {
@@ -22,29 +76,288 @@ impl WorkItem {
             file_type,
             local_path,
             remote_url,
+            range: None,
+            sequence: None,
         }
     }
+
+    pub fn with_range(mut self, range: Option<ByteRange>) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn with_sequence(mut self, sequence: Option<u64>) -> Self {
+        self.sequence = sequence;
+        self
+    }
 }
 
+/// Which part of a URL's query string tells files apart when looking for
+/// files that were already queued
+#[derive(Clone, Debug, Default)]
+pub enum QueryMatch {
+    /// URLs must match exactly
+    #[default]
+    Whole,
+    /// The query string is ignored altogether
+    Ignore,
+    /// These parameters, e.g. expiring tokens, are ignored
+    IgnoreParams(Vec<String>),
+}
+
+impl QueryMatch {
+    /// `url` with the ignored parts of its query removed
+    fn normalize(&self, url: &Url) -> Url {
+        let mut url = url.clone();
+        match self {
+            Self::Whole => {}
+            Self::Ignore => url.set_query(None),
+            Self::IgnoreParams(names) => {
+                let kept: Vec<_> = url
+                    .query_pairs()
+                    .filter(|(name, _)| !names.iter().any(|n| n == name))
+                    .map(|(name, value)| (name.into_owned(), value.into_owned()))
+                    .collect();
+                if kept.is_empty() {
+                    url.set_query(None);
+                } else {
+                    url.query_pairs_mut().clear().extend_pairs(kept);
+                }
+            }
+        }
+        url
+    }
+}
+
+/// Order in which queued files are downloaded, lowest first. Keys and init
+/// segments come first, as the media that needs them can't be used until
+/// they arrive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    /// A segment that was already in the playlist when it was first loaded
+    Backfill,
+    /// A segment that appeared in a later refresh of a live playlist
+    LiveEdge,
+    InitSegment,
+    Key,
+}
+
+impl Priority {
+    pub fn of(work_item: &WorkItem, live_edge: bool) -> Self {
+        match work_item.file_type {
+            FileType::Key => Self::Key,
+            FileType::InitSegment => Self::InitSegment,
+            FileType::MediaSegment | FileType::IFrame if live_edge => Self::LiveEdge,
+            FileType::MediaSegment | FileType::IFrame => Self::Backfill,
+        }
+    }
+}
+
+/// Files waiting to be downloaded, shared between whatever finds them and
+/// the download workers. Items are taken highest priority first, and in the
+/// order they were added within a priority. Workers waiting on an empty queue
+/// are woken as soon as an item is added or the queue is closed.
+///
+/// A file is only queued once, however many times it is added, as keys and
+/// init segments are listed again on every refresh of a live playlist. Only
+/// the last [`MAX_SEEN`] files added are remembered, so a long recording
+/// doesn't keep every segment it has seen.
+#[derive(Default)]
 pub struct WorkQueue {
-    work: VecDeque<WorkItem>,
+    state: Mutex<QueueState>,
+    notify: Notify,
+    query_match: QueryMatch,
+}
+
+/// How many of the files added last the queue remembers. Keys and init
+/// segments are added again on every refresh, which keeps them remembered
+/// for as long as a playlist lists them. Segments are added once, and only
+/// need remembering while they are in a live playlist's window, which is
+/// far shorter than this.
+pub const MAX_SEEN: usize = 1 << 16;
+
+type SeenKey = (Url, Option<(u64, u64)>);
+
+#[derive(Default)]
+struct QueueState {
+    /// One queue per priority
+    work: [VecDeque<WorkItem>; 4],
+    /// Normalized URL and range of the items added lately, with when each
+    /// was last added
+    seen: HashMap<SeenKey, u64>,
+    /// Every addition, oldest first. Entries for items added again since are
+    /// stale, and skipped when they are dropped.
+    additions: VecDeque<(u64, SeenKey)>,
+    added: u64,
+    closed: bool,
+}
+
+impl QueueState {
+    fn pop(&mut self) -> Option<WorkItem> {
+        self.work.iter_mut().rev().find_map(|work| work.pop_front())
+    }
+
+    /// Note that `key` was added. Returns whether it was new.
+    fn see(&mut self, key: SeenKey) -> bool {
+        self.added += 1;
+        let new = self.seen.insert(key.clone(), self.added).is_none();
+        self.additions.push_back((self.added, key));
+
+        while self.additions.len() > MAX_SEEN {
+            let (added, key) = self.additions.pop_front().unwrap();
+            if self.seen.get(&key) == Some(&added) {
+                self.seen.remove(&key);
+            }
+        }
+        new
+    }
 }
 
 impl WorkQueue {
     pub fn new() -> Self {
-        let work = VecDeque::new();
-        Self { work }
+        Self::default()
     }
 
-    pub fn add(&mut self, item: WorkItem) {
-        self.work.push_back(item);
+    /// Compare URLs by `query_match` when looking for duplicates
+    pub fn with_query_match(mut self, query_match: QueryMatch) -> Self {
+        self.query_match = query_match;
+        self
+    }
+
+    /// Queue an item, unless the same file was queued before. Returns whether
+    /// it was queued.
+    pub fn add(&self, item: WorkItem, priority: Priority) -> bool {
+        let url = self.query_match.normalize(&item.remote_url);
+        let range = item.range.map(|r| (r.offset, r.length));
+
+        let mut state = self.state.lock().unwrap();
+        if !state.see((url, range)) {
+            return false;
+        }
+        state.work[priority as usize].push_back(item);
+        drop(state);
+
+        self.notify.notify_one();
+        true
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.work.is_empty()
+    /// No more items will be added. Workers finish what is left and stop.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_waiters();
     }
 
-    pub fn take(&mut self) -> Option<WorkItem> {
-        self.work.pop_front()
+    /// The next item, without waiting for one
+    pub fn take(&self) -> Option<WorkItem> {
+        self.state.lock().unwrap().pop()
+    }
+
+    /// Wait for the next item. None once the queue is closed and empty.
+    pub async fn next(&self) -> Option<WorkItem> {
+        loop {
+            // Registered before checking, so a close in between isn't missed
+            let mut notified = std::pin::pin!(self.notify.notified());
+            notified.as_mut().enable();
+
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(item) = state.pop() {
+                    return Some(item);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    fn item(s: &str) -> WorkItem {
+        WorkItem::new(PathBuf::from("a.ts"), url(s), FileType::MediaSegment)
+    }
+
+    #[test]
+    fn normalizes_queries() {
+        let u = url("https://a/b.ts?token=1&v=2&exp=3");
+        assert_eq!(QueryMatch::Whole.normalize(&u), u);
+        assert_eq!(QueryMatch::Ignore.normalize(&u), url("https://a/b.ts"));
+
+        let names = vec!["token".to_owned(), "exp".to_owned()];
+        let params = QueryMatch::IgnoreParams(names);
+        assert_eq!(params.normalize(&u), url("https://a/b.ts?v=2"));
+        assert_eq!(
+            params.normalize(&url("https://a/b.ts?token=1")),
+            url("https://a/b.ts")
+        );
+    }
+
+    #[test]
+    fn resolves_byte_ranges() {
+        let range = |length, offset| hls::ByteRange { length, offset };
+        let mut tracker = RangeTracker::default();
+
+        let first = tracker.resolve("a.ts", range(100, None));
+        let second = tracker.resolve("a.ts", range(50, None));
+        let other = tracker.resolve("b.ts", range(10, None));
+        let explicit = tracker.resolve("b.ts", range(10, Some(500)));
+        let after = tracker.resolve("b.ts", range(5, None));
+
+        assert_eq!((first.offset, second.offset, other.offset), (0, 100, 0));
+        assert_eq!((explicit.offset, after.offset), (500, 510));
+        assert_eq!(second.header_value(), "bytes=100-149");
+    }
+
+    #[test]
+    fn slices_whole_resources() {
+        let body = Bytes::from_static(b"0123456789");
+        let range = |length, offset| ByteRange { length, offset };
+        assert_eq!(range(3, 2).slice(body.clone()), "234");
+        assert_eq!(range(5, 8).slice(body.clone()), "89");
+        assert_eq!(range(5, 20).slice(body), "");
+    }
+
+    #[test]
+    fn queues_each_file_once_by_priority() {
+        let queue = WorkQueue::new().with_query_match(QueryMatch::Ignore);
+        assert!(queue.add(item("https://a/1.ts"), Priority::Backfill));
+        assert!(queue.add(item("https://a/2.ts"), Priority::LiveEdge));
+        assert!(queue.add(item("https://a/k.bin"), Priority::Key));
+        assert!(!queue.add(item("https://a/1.ts?token=x"), Priority::Backfill));
+
+        let order: Vec<_> = std::iter::from_fn(|| queue.take())
+            .map(|item| item.remote_url.path().to_owned())
+            .collect();
+        assert_eq!(order, ["/k.bin", "/2.ts", "/1.ts"]);
+    }
+
+    #[test]
+    fn forgets_old_segments_but_not_keys_still_listed() {
+        let queue = WorkQueue::new();
+        assert!(queue.add(item("https://a/k.bin"), Priority::Key));
+        assert!(queue.add(item("https://a/0.ts"), Priority::LiveEdge));
+        for i in 1..=MAX_SEEN {
+            // Keys are listed again on every refresh
+            if i % 100 == 0 {
+                assert!(!queue.add(item("https://a/k.bin"), Priority::Key));
+            }
+            assert!(queue.add(item(&format!("https://a/{}.ts", i)), Priority::LiveEdge));
+        }
+
+        let state = queue.state.lock().unwrap();
+        assert!(state.seen.len() <= MAX_SEEN);
+        assert!(state.additions.len() <= MAX_SEEN);
+        drop(state);
+        assert!(!queue.add(item("https://a/k.bin"), Priority::Key));
+        assert!(queue.add(item("https://a/0.ts"), Priority::LiveEdge));
     }
 }
@@ -0,0 +1,10 @@
+use hls::Line;
+use wasm_bindgen::prelude::*;
+
+/// Parse a playlist and return its lines in the same shape that the `hls`
+/// crate's serde models serialize to
+#[wasm_bindgen]
+pub fn parse(text: &str) -> Result<JsValue, JsError> {
+    let lines: Vec<Line> = hls::from_str(text).map_err(|e| JsError::new(&e.to_string()))?;
+    serde_wasm_bindgen::to_value(&lines).map_err(|e| JsError::new(&e.to_string()))
+}
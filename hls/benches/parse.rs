@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use std::fmt::Write;
+
+const DVR_SEGMENTS: usize = 50_000;
+const MASTER_VARIANTS: usize = 200;
+
+fn dvr_playlist(segments: usize) -> String {
+    let mut out = String::from(
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:1000\n",
+    );
+
+    for i in 0..segments {
+        if i % 500 == 0 {
+            writeln!(
+                out,
+                "#EXT-X-KEY:METHOD=AES-128,URI=\"https://keys.example.com/{}.key\",IV=0x{:032x}",
+                i, i
+            )
+            .unwrap();
+        }
+        writeln!(out, "#EXTINF:6.006,\nsegment_{}.ts", i + 1000).unwrap();
+    }
+
+    out
+}
+
+fn master_playlist(variants: usize) -> String {
+    let mut out = String::from("#EXTM3U\n#EXT-X-INDEPENDENT-SEGMENTS\n");
+
+    for i in 0..variants {
+        writeln!(
+            out,
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud{}\",NAME=\"Audio {}\",LANGUAGE=\"en\",DEFAULT=YES,AUTOSELECT=YES,URI=\"audio/{}.m3u8\"",
+            i, i, i
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "#EXT-X-STREAM-INF:BANDWIDTH={},AVERAGE-BANDWIDTH={},CODECS=\"avc1.640028,mp4a.40.2\",RESOLUTION=1920x1080,FRAME-RATE=29.970,AUDIO=\"aud{}\"\nvideo/{}.m3u8",
+            100_000 * (i + 1),
+            90_000 * (i + 1),
+            i,
+            i
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+
+    let dvr = dvr_playlist(DVR_SEGMENTS);
+    group.throughput(Throughput::Bytes(dvr.len() as u64));
+    group.bench_function("dvr_50k_segments", |b| {
+        b.iter(|| hls::from_str::<Vec<hls::Line>>(black_box(&dvr)).unwrap())
+    });
+
+    let master = master_playlist(MASTER_VARIANTS);
+    group.throughput(Throughput::Bytes(master.len() as u64));
+    group.bench_function("master_200_variants", |b| {
+        b.iter(|| hls::from_str::<Vec<hls::Line>>(black_box(&master)).unwrap())
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
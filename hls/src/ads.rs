@@ -0,0 +1,132 @@
+use serde::de::{self, IgnoredAny, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// An ad break marker attached to the segment it precedes
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdMarker {
+    /// EXT-X-CUE-OUT: an ad break starts with this segment
+    CueOut { duration: Option<f64> },
+    /// EXT-X-CUE-OUT-CONT: this segment is part way through an ad break
+    CueOutCont {
+        elapsed: Option<f64>,
+        duration: Option<f64>,
+    },
+    /// EXT-X-CUE-IN: the ad break is over and content resumes
+    CueIn,
+    /// EXT-X-SCTE35, carrying the splice information itself
+    Scte35(Scte35Attributes),
+}
+
+/// The arguments of EXT-X-CUE-OUT and EXT-X-CUE-OUT-CONT, which packagers
+/// write in several ways: `30`, `DURATION=30`, `ElapsedTime=5,Duration=30`
+/// or `5/30`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CueArgs {
+    pub duration: Option<f64>,
+    pub elapsed: Option<f64>,
+}
+
+impl CueArgs {
+    fn set(&mut self, name: &str, value: f64) {
+        if name.eq_ignore_ascii_case("duration") {
+            self.duration = Some(value);
+        } else if name.eq_ignore_ascii_case("elapsedtime") || name.eq_ignore_ascii_case("elapsed") {
+            self.elapsed = Some(value);
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CueArgs {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CueArgsVisitor)
+    }
+}
+
+struct CueArgsVisitor;
+
+impl<'de> Visitor<'de> for CueArgsVisitor {
+    type Value = CueArgs;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an ad break duration or attribute list")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<CueArgs, E> {
+        self.visit_f64(v as f64)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<CueArgs, E> {
+        Ok(CueArgs {
+            duration: Some(v),
+            elapsed: None,
+        })
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<CueArgs, E> {
+        let number = |s: &str| s.trim().parse().map_err(|_| E::custom("invalid number"));
+
+        if let Some((elapsed, duration)) = v.split_once('/') {
+            return Ok(CueArgs {
+                duration: Some(number(duration)?),
+                elapsed: Some(number(elapsed)?),
+            });
+        }
+
+        if !v.contains('=') {
+            return self.visit_f64(number(v)?);
+        }
+
+        let mut args = CueArgs::default();
+        for (name, value) in v.split(',').filter_map(|attr| attr.split_once('=')) {
+            args.set(name, number(value)?);
+        }
+        Ok(args)
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<CueArgs, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut args = CueArgs::default();
+        while let Some(name) = map.next_key::<String>()? {
+            if name.eq_ignore_ascii_case("duration") || name.eq_ignore_ascii_case("elapsedtime") {
+                args.set(&name, map.next_value()?);
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(args)
+    }
+}
+
+impl Serialize for CueArgs {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(elapsed) = self.elapsed {
+            map.serialize_entry("ELAPSEDTIME", &elapsed)?;
+        }
+        if let Some(duration) = self.duration {
+            map.serialize_entry("DURATION", &duration)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct Scte35Attributes {
+    /// The base64 encoded splice_info_section
+    pub cue: String,
+    pub id: Option<String>,
+    pub duration: Option<f64>,
+    pub elapsed: Option<f64>,
+    pub time: Option<f64>,
+}
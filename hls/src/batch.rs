@@ -0,0 +1,74 @@
+use crate::de::from_str;
+use crate::error::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::thread;
+
+/// Parse a batch of playlists across all available cores. Each input is
+/// paired with a key (a URL, a file name, ...) which is used to look up its
+/// result in the returned map.
+pub fn parse_many<'a, K, T, I>(inputs: I) -> HashMap<K, Result<T>>
+where
+    I: IntoIterator<Item = (K, &'a str)>,
+    K: Eq + Hash + Send,
+    T: Deserialize<'a> + Send,
+{
+    let inputs: Vec<(K, &'a str)> = inputs.into_iter().collect();
+    if inputs.is_empty() {
+        return HashMap::new();
+    }
+
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(inputs.len());
+    let chunk_size = inputs.len().div_ceil(threads);
+
+    let mut chunks = Vec::with_capacity(threads);
+    let mut inputs = inputs.into_iter();
+    loop {
+        let chunk: Vec<_> = inputs.by_ref().take(chunk_size).collect();
+        if chunk.is_empty() {
+            break;
+        }
+        chunks.push(chunk);
+    }
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = chunks
+            .into_iter()
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .into_iter()
+                        .map(|(key, input)| (key, from_str(input)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("playlist parser thread panicked"))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::manifest::{Line, Tag};
+
+    #[test]
+    fn parses_each_input_under_its_key() {
+        let inputs = (0..10).map(|i| (i, "#EXTM3U\n#EXT-X-TARGETDURATION:6\n"));
+        let results = parse_many::<_, Vec<Line>, _>(inputs);
+
+        assert_eq!(10, results.len());
+        for i in 0..10 {
+            let lines = results[&i].as_ref().unwrap();
+            assert!(matches!(lines[1], Line::Tag(Tag::Targetduration(6))));
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use crate::manifest::Line;
+
+/// Where a comment sits relative to the segments of a media playlist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommentPosition<'a> {
+    pub text: &'a str,
+    /// Index into the parsed lines
+    pub index: usize,
+    /// The last segment before the comment, if any
+    pub preceding_segment: Option<usize>,
+    /// The first segment after the comment, if any
+    pub following_segment: Option<usize>,
+}
+
+/// Locate every comment in lines parsed with
+/// [`crate::from_str_with_comments`]. Segments are numbered from 0 in
+/// playlist order.
+pub fn comments(lines: &[Line]) -> Vec<CommentPosition<'_>> {
+    let mut positions = Vec::new();
+    let mut segments: usize = 0;
+    let mut waiting = 0;
+
+    for (index, line) in lines.iter().enumerate() {
+        match line {
+            Line::Comment(text) => {
+                positions.push(CommentPosition {
+                    text,
+                    index,
+                    preceding_segment: segments.checked_sub(1),
+                    following_segment: None,
+                });
+                waiting += 1;
+            }
+            Line::Uri(_) => {
+                for position in positions.iter_mut().rev().take(waiting) {
+                    position.following_segment = Some(segments);
+                }
+                waiting = 0;
+                segments += 1;
+            }
+            Line::Tag(_) => {}
+        }
+    }
+
+    positions
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn places_comments_between_segments() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "# packager v1.2\n",
+            "#EXTINF:6.0,\n",
+            "a.ts\n",
+            "#AD-BREAK id=7\n",
+            "#EXTINF:6.0,\n",
+            "b.ts\n",
+            "# end\n",
+        );
+        let lines: Vec<Line> = crate::from_str_with_comments(input).unwrap();
+        let found = comments(&lines);
+
+        let summary: Vec<_> = found
+            .iter()
+            .map(|c| (c.text, c.preceding_segment, c.following_segment))
+            .collect();
+        assert_eq!(
+            vec![
+                (" packager v1.2", None, Some(0)),
+                ("AD-BREAK id=7", Some(0), Some(1)),
+                (" end", Some(1), None),
+            ],
+            summary
+        );
+
+        let lines: Vec<Line> = crate::from_str(input).unwrap();
+        assert!(comments(&lines).is_empty());
+    }
+}
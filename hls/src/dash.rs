@@ -0,0 +1,325 @@
+//! Experimental conversion of an HLS presentation into a static DASH MPD.
+//!
+//! Every variant stream becomes a `Representation` of a single video
+//! `AdaptationSet` and every `EXT-X-MEDIA` rendition with a URI becomes an
+//! `AdaptationSet` of its own. Segments are listed explicitly with a
+//! `SegmentList`/`SegmentTimeline`, so no segment naming template is needed.
+//! Segment URIs are relative to their media playlist, so each
+//! `Representation` gets a `BaseURL` pointing at the playlist's directory,
+//! and the first `EXT-X-MAP` of a playlist becomes its `Initialization`.
+
+use crate::manifest::{Line, MapAttributes, MediaAttributes, MediaType, StreamInfAttributes, Tag};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const TIMESCALE: f64 = 1000.0;
+
+/// Render a static MPD for a master playlist. `media_playlists` maps each URI
+/// referenced by the master playlist (as written in the playlist) to its
+/// parsed media playlist; variants and renditions without an entry are
+/// skipped.
+pub fn to_mpd(master: &[Line], media_playlists: &HashMap<String, Vec<Line>>) -> String {
+    let mut variants = Vec::new();
+    let mut renditions = Vec::new();
+    let mut pending_variant = None;
+
+    for line in master {
+        match line {
+            Line::Tag(Tag::StreamInf(attrs)) => pending_variant = Some(attrs),
+            Line::Tag(Tag::Media(attrs)) => renditions.push(attrs),
+            Line::Uri(uri) => {
+                if let Some(attrs) = pending_variant.take() {
+                    variants.push((attrs, uri.as_str()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let duration = media_playlists
+        .values()
+        .map(|lines| segments(lines).iter().map(|(_, d)| d).sum::<f64>())
+        .fold(0.0, f64::max);
+
+    let mut out = String::new();
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        out,
+        r#"<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" profiles="urn:mpeg:dash:profile:full:2011" type="static" minBufferTime="PT2S" mediaPresentationDuration="PT{:.3}S">"#,
+        duration
+    )
+    .unwrap();
+    writeln!(out, r#"  <Period id="0" start="PT0S">"#).unwrap();
+
+    let mut next_id = 0;
+
+    if variants
+        .iter()
+        .any(|(_, uri)| media_playlists.contains_key(*uri))
+    {
+        writeln!(out, r#"    <AdaptationSet contentType="video">"#).unwrap();
+        for (attrs, uri) in &variants {
+            if let Some(lines) = media_playlists.get(*uri) {
+                write_variant(&mut out, next_id, attrs, uri, lines);
+                next_id += 1;
+            }
+        }
+        writeln!(out, "    </AdaptationSet>").unwrap();
+    }
+
+    for attrs in renditions {
+        let Some(uri) = attrs.uri.as_ref() else {
+            continue;
+        };
+        let Some(lines) = media_playlists.get(uri) else {
+            continue;
+        };
+
+        write_rendition(&mut out, next_id, attrs, uri, lines);
+        next_id += 1;
+    }
+
+    writeln!(out, "  </Period>").unwrap();
+    writeln!(out, "</MPD>").unwrap();
+
+    out
+}
+
+fn write_variant(
+    out: &mut String,
+    id: usize,
+    attrs: &StreamInfAttributes,
+    uri: &str,
+    lines: &[Line],
+) {
+    let segments = segments(lines);
+
+    write!(
+        out,
+        r#"      <Representation id="{}" bandwidth="{}" mimeType="{}""#,
+        id,
+        attrs.bandwidth,
+        mime_type(&segments, "video")
+    )
+    .unwrap();
+    if let Some(codecs) = &attrs.codecs {
+        write!(out, r#" codecs="{}""#, escape(codecs)).unwrap();
+    }
+    if let Some(resolution) = attrs.resolution {
+        write!(
+            out,
+            r#" width="{}" height="{}""#,
+            resolution.width, resolution.height
+        )
+        .unwrap();
+    }
+    if let Some(frame_rate) = attrs.frame_rate {
+        write!(out, r#" frameRate="{}""#, frame_rate).unwrap();
+    }
+    writeln!(out, ">").unwrap();
+
+    write_segment_list(out, uri, lines);
+    writeln!(out, "      </Representation>").unwrap();
+}
+
+fn write_rendition(
+    out: &mut String,
+    id: usize,
+    attrs: &MediaAttributes,
+    uri: &str,
+    lines: &[Line],
+) {
+    let segments = segments(lines);
+    let content_type = match attrs.media_type {
+        MediaType::Audio => "audio",
+        MediaType::Video => "video",
+        MediaType::Subtitles | MediaType::ClosedCaptions => "text",
+    };
+
+    write!(out, r#"    <AdaptationSet contentType="{}""#, content_type).unwrap();
+    if let Some(language) = &attrs.language {
+        write!(out, r#" lang="{}""#, escape(language)).unwrap();
+    }
+    writeln!(out, ">").unwrap();
+    writeln!(out, r#"      <Label>{}</Label>"#, escape(&attrs.name)).unwrap();
+
+    // HLS renditions don't declare a bitrate, so there is nothing better to
+    // put here than zero
+    writeln!(
+        out,
+        r#"      <Representation id="{}" bandwidth="0" mimeType="{}">"#,
+        id,
+        mime_type(&segments, content_type)
+    )
+    .unwrap();
+    write_segment_list(out, uri, lines);
+    writeln!(out, "      </Representation>").unwrap();
+    writeln!(out, "    </AdaptationSet>").unwrap();
+}
+
+/// Write the segments of the media playlist at `uri`
+fn write_segment_list(out: &mut String, uri: &str, lines: &[Line]) {
+    let segments = segments(lines);
+
+    if let Some(base) = base_url(uri) {
+        writeln!(out, "        <BaseURL>{}</BaseURL>", escape(base)).unwrap();
+    }
+    writeln!(
+        out,
+        r#"        <SegmentList timescale="{}">"#,
+        TIMESCALE as u64
+    )
+    .unwrap();
+    if let Some(map) = init_segment(lines) {
+        write!(
+            out,
+            r#"          <Initialization sourceURL="{}""#,
+            escape(&map.uri)
+        )
+        .unwrap();
+        if let Some(range) = map.byterange {
+            let start = range.offset.unwrap_or(0);
+            let end = start + range.length - 1;
+            write!(out, r#" range="{}-{}""#, start, end).unwrap();
+        }
+        writeln!(out, "/>").unwrap();
+    }
+    writeln!(out, "          <SegmentTimeline>").unwrap();
+    for (_, duration) in &segments {
+        writeln!(
+            out,
+            r#"            <S d="{}"/>"#,
+            (duration * TIMESCALE).round() as u64
+        )
+        .unwrap();
+    }
+    writeln!(out, "          </SegmentTimeline>").unwrap();
+    for (uri, _) in &segments {
+        writeln!(out, r#"          <SegmentURL media="{}"/>"#, escape(uri)).unwrap();
+    }
+    writeln!(out, "        </SegmentList>").unwrap();
+}
+
+/// The directory of a media playlist's URI, which its segment URIs are
+/// relative to. None for a playlist next to the master playlist.
+fn base_url(uri: &str) -> Option<&str> {
+    let path = uri.split(['?', '#']).next().unwrap_or(uri);
+    path.rfind('/').map(|end| &path[..=end])
+}
+
+/// The first EXT-X-MAP of a media playlist. A SegmentList has only one
+/// Initialization, so later ones are left out.
+fn init_segment(lines: &[Line]) -> Option<&MapAttributes> {
+    lines.iter().find_map(|line| match line {
+        Line::Tag(Tag::Map(attrs)) => Some(attrs),
+        _ => None,
+    })
+}
+
+/// Pair each segment URI of a media playlist with its EXTINF duration
+fn segments(lines: &[Line]) -> Vec<(&str, f64)> {
+    let mut duration = 0.0;
+    let mut segments = Vec::new();
+
+    for line in lines {
+        match line {
+            Line::Tag(Tag::Inf(d, _)) => duration = *d,
+            Line::Uri(uri) => {
+                segments.push((uri.as_str(), duration));
+                duration = 0.0;
+            }
+            _ => {}
+        }
+    }
+
+    segments
+}
+
+fn mime_type(segments: &[(&str, f64)], content_type: &str) -> String {
+    let extension = segments
+        .first()
+        .and_then(|(uri, _)| uri.split('?').next())
+        .and_then(|path| path.rsplit_once('.'))
+        .map(|(_, ext)| ext.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("ts") => format!("{}/mp2t", content_type),
+        Some("vtt") | Some("webvtt") => "text/vtt".to_owned(),
+        Some("aac") => "audio/aac".to_owned(),
+        _ => format!("{}/mp4", content_type),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::de::from_str;
+
+    #[test]
+    fn converts_variants_and_renditions() {
+        let master: Vec<Line> = from_str(concat!(
+            "#EXTM3U\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",LANGUAGE=\"en\",URI=\"audio.m3u8\"\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,CODECS=\"avc1.4d401f\",RESOLUTION=640x360,AUDIO=\"aud\"\n",
+            "low.m3u8\n",
+        ))
+        .unwrap();
+        let media =
+            "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.006,\na.ts\n#EXTINF:4.000,\nb.ts\n";
+
+        let mut playlists = HashMap::new();
+        playlists.insert("low.m3u8".to_owned(), from_str(media).unwrap());
+        playlists.insert("audio.m3u8".to_owned(), from_str(media).unwrap());
+
+        let mpd = to_mpd(&master, &playlists);
+
+        assert!(mpd.contains(r#"mediaPresentationDuration="PT10.006S""#));
+        assert!(mpd.contains(r#"bandwidth="1280000" mimeType="video/mp2t" codecs="avc1.4d401f" width="640" height="360""#));
+        assert!(mpd.contains(r#"<AdaptationSet contentType="audio" lang="en">"#));
+        assert!(mpd.contains(r#"<S d="6006"/>"#));
+        assert!(mpd.contains(r#"<SegmentURL media="b.ts"/>"#));
+        assert!(!mpd.contains("<BaseURL>"));
+        assert!(!mpd.contains("<Initialization"));
+    }
+
+    #[test]
+    fn resolves_nested_playlists() {
+        let master: Vec<Line> = from_str(concat!(
+            "#EXTM3U\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",URI=\"audio/en/prog.m3u8\"\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"aud\"\n",
+            "video/720p/prog.m3u8?token=a/b\n",
+        ))
+        .unwrap();
+        let video = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"\n",
+            "#EXTINF:6.0,\n",
+            "seg-1.m4s\n",
+        );
+        let audio = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-MAP:URI=\"../init.mp4\"\n#EXTINF:6.0,\na-1.m4s\n";
+
+        let mut playlists = HashMap::new();
+        playlists.insert(
+            "video/720p/prog.m3u8?token=a/b".to_owned(),
+            from_str(video).unwrap(),
+        );
+        playlists.insert("audio/en/prog.m3u8".to_owned(), from_str(audio).unwrap());
+
+        let mpd = to_mpd(&master, &playlists);
+
+        assert!(mpd.contains("<BaseURL>video/720p/</BaseURL>"));
+        assert!(mpd.contains(r#"<Initialization sourceURL="init.mp4" range="0-719"/>"#));
+        assert!(mpd.contains(r#"<SegmentURL media="seg-1.m4s"/>"#));
+        assert!(mpd.contains("<BaseURL>audio/en/</BaseURL>"));
+        assert!(mpd.contains(r#"<Initialization sourceURL="../init.mp4"/>"#));
+    }
+}
@@ -1,69 +1,127 @@
 use crate::error::{Error, Result};
-use crate::models::{AttributeValue, Manifest, Node};
-use serde::de::{self, Deserialize, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor};
+use crate::models::{AttributeValue, Manifest, Node, Nodes};
+use serde::de::value::{MapDeserializer, StrDeserializer, UnitDeserializer};
+use serde::de::{
+    self, Deserialize, EnumAccess, IntoDeserializer, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
 use serde::{self, forward_to_deserialize_any};
+use std::iter::Peekable;
+use std::marker::PhantomData;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default)]
 enum Context {
     AttributeName,
     Attributes,
+    Comment,
     EnumAttribute,
     FloatAttribute,
+    #[default]
     Manifest,
     Tag,
     TagName,
     IntAttribute,
     StringAttribute,
+    Title,
     Uri,
 }
 
-impl Default for Context {
-    fn default() -> Self {
-        Self::Manifest
-    }
-}
-
 pub struct Deserializer<'de> {
-    nodes: Vec<Node<'de>>,
+    nodes: Peekable<Nodes<'de>>,
     context: Context,
-    next_index: usize,
 }
 
 impl<'de> Deserializer<'de> {
     pub fn from_str(input: &'de str) -> Result<Self> {
-        let manifest = Manifest::parse(input).map_err(|_| Error::Syntax)?;
-        let nodes = manifest.nodes();
-        let next_index = 0;
+        Self::parse(input, false)
+    }
+
+    /// Like [`Deserializer::from_str`], but comment lines are deserialized
+    /// as `Comment` variants rather than skipped
+    pub fn from_str_with_comments(input: &'de str) -> Result<Self> {
+        Self::parse(input, true)
+    }
+
+    fn parse(input: &'de str, keep_comments: bool) -> Result<Self> {
+        let manifest = Manifest::parse(input).map_err(|e| {
+            let line = input[..input.len() - e.input.len()].matches('\n').count() + 1;
+            let text = e.input.lines().next().unwrap_or_default();
+            Error::Parse(format!("line {}: {:?} at {:?}", line, e.code, text))
+        })?;
+        let nodes = manifest.nodes_iter(keep_comments).peekable();
 
         Ok(Self {
-            next_index,
             nodes,
             context: Default::default(),
         })
     }
 
-    fn peek(&self) -> Result<&Node> {
-        self.nodes.get(self.next_index).ok_or(Error::UnexpectedEof)
+    /// Deserialize from the nodes of a single line
+    pub(crate) fn from_line(line: crate::models::Line<'de>) -> Self {
+        Self {
+            nodes: Nodes::line(line).peekable(),
+            context: Default::default(),
+        }
+    }
+
+    /// Whether every node up to the end of the manifest has been
+    /// deserialized
+    pub(crate) fn is_finished(&mut self) -> bool {
+        matches!(self.nodes.peek(), None | Some(Node::ManifestEnd))
+    }
+
+    pub fn from_slice(input: &'de [u8]) -> Result<Self> {
+        let input = std::str::from_utf8(input).map_err(|_| Error::InvalidUtf8)?;
+        Self::from_str(input)
+    }
+
+    fn peek(&mut self) -> Result<&Node<'de>> {
+        self.nodes.peek().ok_or(Error::UnexpectedEof)
     }
 
     fn next(&mut self) -> Result<()> {
         log::debug!(" --- next --- ");
-        self.nodes
-            .get(self.next_index)
-            .ok_or(Error::UnexpectedEof)?;
-        self.next_index += 1;
+        self.nodes.next().ok_or(Error::UnexpectedEof)?;
+        Ok(())
+    }
+
+    /// Drop the title of an EXTINF tag modelled without one
+    fn skip_title(&mut self) -> Result<()> {
+        if let Some(Node::Title(_)) = self.nodes.peek() {
+            self.next()?;
+            self.context = Context::Manifest;
+        }
+        Ok(())
+    }
+
+    /// Consume the arguments of the current tag without deserializing them
+    fn skip_tag_args(&mut self) -> Result<()> {
+        while let Some(
+            Node::String(_)
+            | Node::Integer(_)
+            | Node::SignedInteger(_)
+            | Node::Float(_)
+            | Node::Title(_)
+            | Node::AttributesStart
+            | Node::AttributeName(_)
+            | Node::AttributeValue(_)
+            | Node::AttributesEnd,
+        ) = self.nodes.peek()
+        {
+            self.next()?;
+        }
         Ok(())
     }
 }
 
-impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     type Error = Error;
 
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        log::debug!("[{:?}] {:?}", self.context, self.peek()?);
+        let context = self.context;
+        log::debug!("[{:?}] {:?}", context, self.peek()?);
 
         match (self.context, self.peek()?) {
             (Context::Manifest, Node::TagStart) => visitor.visit_enum(TagLine::new(self)),
@@ -71,15 +129,17 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                 self.next()?;
                 visitor.visit_borrowed_str("Tag")
             }
-            (Context::Tag, Node::TagName(_)) => {
+            (Context::Tag, Node::TagName(..)) => {
                 self.context = Context::TagName;
                 visitor.visit_enum(TagName::new(self))
             }
-            (Context::TagName, Node::TagName(s)) => {
+            (Context::TagName, Node::TagName(s, ..)) => {
                 let res = visitor.visit_str(s)?;
                 self.next()?;
                 match self.peek()? {
-                    Node::Integer(_) => self.context = Context::IntAttribute,
+                    Node::Integer(_) | Node::SignedInteger(_) => {
+                        self.context = Context::IntAttribute
+                    }
                     Node::Float(_) => self.context = Context::FloatAttribute,
                     Node::String(_) => self.context = Context::StringAttribute,
                     Node::AttributesStart => self.context = Context::Attributes,
@@ -93,8 +153,23 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                 self.next()?;
                 Ok(res)
             }
+            (Context::IntAttribute, Node::SignedInteger(i)) => {
+                let res = visitor.visit_i64(*i)?;
+                self.context = Context::Manifest;
+                self.next()?;
+                Ok(res)
+            }
             (Context::FloatAttribute, Node::Float(f)) => {
                 let res = visitor.visit_f64(*f)?;
+                self.next()?;
+                self.context = match self.nodes.peek() {
+                    Some(Node::Title(_)) => Context::Title,
+                    _ => Context::Manifest,
+                };
+                Ok(res)
+            }
+            (Context::Title, Node::Title(t)) => {
+                let res = visitor.visit_str(t)?;
                 self.context = Context::Manifest;
                 self.next()?;
                 Ok(res)
@@ -126,6 +201,11 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                     self.next()?;
                     Ok(res)
                 }
+                AttributeValue::SignedInteger(i) => {
+                    let res = visitor.visit_i64(*i)?;
+                    self.next()?;
+                    Ok(res)
+                }
                 AttributeValue::String(s) => {
                     let res = visitor.visit_str(s)?;
                     self.next()?;
@@ -169,22 +249,35 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                     self.next()?;
                     Ok(res)
                 } else {
-                    unreachable!()
+                    Err(Error::Syntax)
                 }
             }
-            (Context::Manifest, Node::Uri(_)) => visitor.visit_enum(UriLine::new(self)),
+            (Context::Manifest, Node::Uri(_) | Node::Comment(_)) => {
+                visitor.visit_enum(UriLine::new(self))
+            }
             (Context::Tag, Node::Uri(_)) => {
                 let res = visitor.visit_borrowed_str("Uri")?;
                 self.context = Context::Uri;
                 Ok(res)
             }
+            (Context::Tag, Node::Comment(_)) => {
+                let res = visitor.visit_borrowed_str("Comment")?;
+                self.context = Context::Comment;
+                Ok(res)
+            }
+            (Context::Comment, Node::Comment(c)) => {
+                let res = visitor.visit_str(c)?;
+                self.next()?;
+                self.context = Context::Manifest;
+                Ok(res)
+            }
             (Context::Uri, Node::Uri(u)) => {
                 let res = visitor.visit_str(u)?;
                 self.next()?;
                 self.context = Context::Manifest;
                 Ok(res)
             }
-            _ => unreachable!(),
+            _ => Err(Error::Syntax),
         }
     }
 
@@ -198,15 +291,38 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
                 self.next()?;
                 Ok(res)
             }
-            Node::Integer(_) => self.deserialize_any(visitor),
-            _ => todo!(),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        // Tag arguments such as EXT-X-BYTERANGE:1024 lex as integers but may
+        // be modelled as strings
+        match self.peek()? {
+            Node::Integer(i) => {
+                let res = visitor.visit_string(i.to_string())?;
+                self.context = Context::Manifest;
+                self.next()?;
+                Ok(res)
+            }
+            _ => self.deserialize_any(visitor),
         }
     }
 
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
     fn deserialize_enum<V>(
         self,
         _name: &'static str,
-        _variants: &'static [&'static str],
+        variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
@@ -214,6 +330,25 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
     {
         log::debug!("deserialize_enum {:?}", self.peek()?);
         match self.peek()? {
+            Node::TagName(name, raw_name, raw_args) if !variants.contains(name) => {
+                // Tags the enum doesn't know about go to its `Unknown`
+                // variant, if it has one, along with their arguments
+                let unknown = variants.iter().find(|v| v.eq_ignore_ascii_case("unknown"));
+                match unknown {
+                    Some(variant) => {
+                        let (name, raw_args) = (*raw_name, *raw_args);
+                        self.next()?;
+                        self.skip_tag_args()?;
+                        self.context = Context::Manifest;
+                        visitor.visit_enum(UnknownTag {
+                            variant,
+                            name,
+                            raw_args,
+                        })
+                    }
+                    None => self.deserialize_any(visitor),
+                }
+            }
             Node::String(_) | Node::AttributeValue(AttributeValue::Keyword(_)) => {
                 self.context = Context::EnumAttribute;
                 visitor.visit_enum(AttrEnum::new(self))
@@ -227,10 +362,15 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
         V: Visitor<'de>,
     {
         log::debug!("option");
-        if let Node::AttributeValue(_) = self.peek()? {
-            visitor.visit_some(self)
-        } else {
-            visitor.visit_none()
+        match (self.context, self.peek()?) {
+            (_, Node::AttributeValue(_))
+            | (Context::Attributes, Node::AttributesStart)
+            | (Context::IntAttribute, Node::Integer(_) | Node::SignedInteger(_))
+            | (Context::FloatAttribute, Node::Float(_))
+            | (Context::StringAttribute, Node::String(_))
+            | (Context::Title, Node::Title(_))
+            | (Context::Uri, Node::Uri(_)) => visitor.visit_some(self),
+            _ => visitor.visit_none(),
         }
     }
 
@@ -242,14 +382,35 @@ impl<'de, 'a> de::Deserializer<'de> for &'a mut Deserializer<'de> {
             self.next()?;
             visitor.visit_seq(Lines::new(self))
         } else {
-            unreachable!("Only manifests support sequential access");
+            // Only manifests support sequential access
+            Err(Error::Syntax)
+        }
+    }
+
+    /// Skip a whole tag, name, arguments and all, so nothing of it is left
+    /// for the next line
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match (self.context, self.peek()?) {
+            (Context::Manifest | Context::Tag, Node::TagStart | Node::TagName(..)) => {
+                if let Node::TagStart = self.peek()? {
+                    self.next()?;
+                }
+                self.next()?;
+                self.skip_tag_args()?;
+                self.context = Context::Manifest;
+                visitor.visit_unit()
+            }
+            _ => self.deserialize_any(visitor),
         }
     }
 
     forward_to_deserialize_any! {
-        bool i8 i16 i32 i64 i128 u8 u16 u32 u128 f32 f64 char str string
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u128 f32 f64 char
         bytes byte_buf unit unit_struct newtype_struct tuple
-        tuple_struct map struct identifier ignored_any
+        tuple_struct map struct identifier
     }
 }
 
@@ -306,14 +467,20 @@ impl<'de, 'a> VariantAccess<'de> for TagLine<'a, 'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        todo!();
+        Err(de::Error::invalid_type(
+            de::Unexpected::UnitVariant,
+            &"a newtype or struct variant",
+        ))
     }
 
     fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!("tuple variant");
+        Err(de::Error::invalid_type(
+            de::Unexpected::TupleVariant,
+            &"a newtype or struct variant",
+        ))
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
@@ -323,11 +490,13 @@ impl<'de, 'a> VariantAccess<'de> for TagLine<'a, 'de> {
         seed.deserialize(self.de)
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+    /// A tag line as a struct: the first field is the tag and the second,
+    /// if there is one, the URI line that follows it
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!("struct variant");
+        visitor.visit_map(VariantFields::new(self.de, fields, true))
     }
 }
 
@@ -362,25 +531,145 @@ impl<'de, 'a> VariantAccess<'de> for TagName<'a, 'de> {
     where
         T: de::DeserializeSeed<'de>,
     {
-        seed.deserialize(self.de)
+        let res = seed.deserialize(&mut *self.de)?;
+        self.de.skip_title()?;
+        Ok(res)
     }
 
+    /// A tag without arguments, or one whose arguments aren't wanted, as
+    /// with a `#[serde(other)]` variant
     fn unit_variant(self) -> Result<()> {
+        self.de.skip_tag_args()?;
+        self.de.context = Context::Manifest;
         Ok(())
     }
 
+    /// A tag as a tuple of its arguments, e.g. an EXTINF duration and title.
+    /// Elements the tag doesn't have are deserialized from `()`, so trailing
+    /// `Option`s are `None`.
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let res = visitor.visit_seq(TagTuple {
+            de: &mut *self.de,
+            len,
+        })?;
+        self.de.skip_title()?;
+        Ok(res)
+    }
+
+    /// A tag as a struct: the first field is the tag's arguments, if it has
+    /// any, and the second the URI line that follows it, as with
+    /// EXT-X-STREAM-INF
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        let has_args = !matches!(self.de.context, Context::Manifest);
+        visitor.visit_map(VariantFields::new(self.de, fields, has_args))
+    }
+}
+
+/// A tag with no matching variant, already consumed from the node stream
+struct UnknownTag<'de> {
+    variant: &'static str,
+    name: &'de str,
+    raw_args: Option<&'de str>,
+}
+
+impl<'de> EnumAccess<'de> for UnknownTag<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant: StrDeserializer<Error> = self.variant.into_deserializer();
+        let val = seed.deserialize(variant)?;
+        Ok((val, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for UnknownTag<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, _seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        Err(de::Error::invalid_type(
+            de::Unexpected::NewtypeVariant,
+            &"a unit or struct variant",
+        ))
+    }
+
     fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!("tuple variant");
+        Err(de::Error::invalid_type(
+            de::Unexpected::TupleVariant,
+            &"a unit or struct variant",
+        ))
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!("struct variant");
+        let fields = [
+            ("name", RawArgs(Some(self.name.to_owned()))),
+            ("raw_args", RawArgs(self.raw_args.map(str::to_owned))),
+        ];
+        visitor.visit_map(MapDeserializer::new(fields.into_iter()))
+    }
+}
+
+/// The raw arguments of an unknown tag, which may be modelled as either a
+/// string or an optional string
+struct RawArgs(Option<String>);
+
+impl<'de> IntoDeserializer<'de, Error> for RawArgs {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for RawArgs {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Some(s) => visitor.visit_string(s),
+            None => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.0 {
+            Some(s) => visitor.visit_some(s.into_deserializer()),
+            None => visitor.visit_none(),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
     }
 }
 
@@ -428,14 +717,22 @@ impl<'de, 'a> VariantAccess<'de> for AttrEnum<'a, 'de> {
     where
         V: Visitor<'de>,
     {
-        todo!("tuple variant");
+        Err(de::Error::invalid_type(
+            de::Unexpected::TupleVariant,
+            &"a unit or newtype variant",
+        ))
     }
 
-    fn struct_variant<V>(self, __fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!("struct variant");
+        // Enumerated attribute values are bare keywords with nothing to fill
+        // a struct from
+        Err(de::Error::invalid_type(
+            de::Unexpected::StructVariant,
+            &"a unit or newtype variant",
+        ))
     }
 }
 
@@ -447,6 +744,93 @@ where
     T::deserialize(&mut deserializer)
 }
 
+/// Deserialize a manifest, keeping comment lines as `Comment` variants
+pub fn from_str_with_comments<'a, T>(s: &'a str) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_str_with_comments(s)?;
+    T::deserialize(&mut deserializer)
+}
+
+/// Deserialize each line of a manifest in turn, handing it to `f` without
+/// collecting the whole playlist into memory
+pub fn for_each<'a, T, F>(s: &'a str, f: F) -> Result<()>
+where
+    T: Deserialize<'a>,
+    F: FnMut(T),
+{
+    let mut deserializer = Deserializer::from_str(s)?;
+    de::Deserializer::deserialize_seq(&mut deserializer, ForEach::new(f))
+}
+
+/// Deserialize only the tags of a manifest, skipping URI lines, into a
+/// `Vec` of the caller's tag type
+pub fn tags_from_str<'a, T>(s: &'a str) -> Result<Vec<T>>
+where
+    T: Deserialize<'a>,
+{
+    let mut tags = Vec::new();
+    for_each(s, |line: AnyLine<T>| {
+        if let AnyLine::Tag(tag) = line {
+            tags.push(tag);
+        }
+    })?;
+    Ok(tags)
+}
+
+/// A manifest line whose URI, if any, is not needed
+#[derive(serde::Deserialize)]
+enum AnyLine<T> {
+    Tag(T),
+    #[allow(dead_code)]
+    Uri(de::IgnoredAny),
+}
+
+struct ForEach<T, F> {
+    f: F,
+    marker: PhantomData<T>,
+}
+
+impl<T, F> ForEach<T, F> {
+    fn new(f: F) -> Self {
+        Self {
+            f,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'de, T, F> Visitor<'de> for ForEach<T, F>
+where
+    T: Deserialize<'de>,
+    F: FnMut(T),
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a sequence of manifest lines")
+    }
+
+    fn visit_seq<A>(mut self, mut seq: A) -> std::result::Result<(), A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        while let Some(line) = seq.next_element()? {
+            (self.f)(line);
+        }
+        Ok(())
+    }
+}
+
+pub fn from_slice<'a, T>(v: &'a [u8]) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_slice(v)?;
+    T::deserialize(&mut deserializer)
+}
+
 struct Attributes<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
 }
@@ -491,6 +875,7 @@ impl<'a, 'de> MapAccess<'de> for Attributes<'a, 'de> {
     }
 }
 
+/// A URI or comment line, both of which are newtype variants of a string
 struct UriLine<'a, 'de: 'a> {
     de: &'a mut Deserializer<'de>,
 }
@@ -519,14 +904,20 @@ impl<'de, 'a> VariantAccess<'de> for UriLine<'a, 'de> {
     type Error = Error;
 
     fn unit_variant(self) -> Result<()> {
-        todo!();
+        Err(de::Error::invalid_type(
+            de::Unexpected::UnitVariant,
+            &"a newtype or struct variant",
+        ))
     }
 
     fn tuple_variant<V>(self, _len: usize, _visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!("tuple variant");
+        Err(de::Error::invalid_type(
+            de::Unexpected::TupleVariant,
+            &"a newtype or struct variant",
+        ))
     }
 
     fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
@@ -536,10 +927,515 @@ impl<'de, 'a> VariantAccess<'de> for UriLine<'a, 'de> {
         seed.deserialize(self.de)
     }
 
-    fn struct_variant<V>(self, _fields: &'static [&'static str], _visitor: V) -> Result<V::Value>
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!("struct variant");
+        visitor.visit_map(VariantFields::new(
+            self.de,
+            &fields[..fields.len().min(1)],
+            true,
+        ))
+    }
+}
+
+/// The arguments of a tag modelled as a tuple variant
+struct TagTuple<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    len: usize,
+}
+
+impl<'de, 'a> SeqAccess<'de> for TagTuple<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.len == 0 {
+            return Ok(None);
+        }
+        self.len -= 1;
+
+        match self.de.context {
+            Context::Manifest => seed.deserialize(UnitDeserializer::new()).map(Some),
+            _ => seed.deserialize(&mut *self.de).map(Some),
+        }
+    }
+}
+
+/// The fields of a struct variant of a line. The first is filled from the
+/// current node, if `first` is set, and the second from the URI line that
+/// follows, if there is one.
+struct VariantFields<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    fields: &'static [&'static str],
+    index: usize,
+}
+
+impl<'a, 'de> VariantFields<'a, 'de> {
+    fn new(de: &'a mut Deserializer<'de>, fields: &'static [&'static str], first: bool) -> Self {
+        Self {
+            de,
+            fields,
+            index: if first { 0 } else { 1 },
+        }
+    }
+}
+
+impl<'a, 'de> MapAccess<'de> for VariantFields<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        let field = match self.fields.get(self.index) {
+            Some(field) => *field,
+            None => return Ok(None),
+        };
+
+        if self.index == 1 {
+            match self.de.nodes.peek() {
+                Some(Node::Uri(_)) => self.de.context = Context::Uri,
+                _ => return Ok(None),
+            }
+        }
+
+        self.index += 1;
+        let field: StrDeserializer<Error> = field.into_deserializer();
+        seed.deserialize(field).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::manifest::{Line, Resolution, Tag};
+
+    #[test]
+    fn rejects_malformed_input_without_panicking() {
+        let inputs = [
+            "",
+            "#EXT-X-TARGETDURATION:abc\n",
+            "#EXT-X-TARGETDURATION:6.5\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=\"lots\"\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=184467440737095516151\n",
+            "#EXT-X-KEY:METHOD=0x00\n",
+            "#EXT-X-KEY:METHOD=AES-128,IV=0x0\n",
+            "#EXTINF:-,\n",
+            "#EXT-X-PLAYLIST-TYPE:1\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO\n",
+            "#EXT-X-MEDIA-SEQUENCE:-1\n",
+            "#EXT-X-MEDIA-SEQUENCE:99999999999999999999999\n",
+            "#EXTINF:abc,\n",
+            "#EXT-X-BYTERANGE:12@x\n",
+            "#EXT-X-TARGETDURATION:\n",
+            "\u{0}\u{1}\u{2}",
+        ];
+
+        for input in inputs {
+            assert!(from_str::<Vec<Line>>(input).is_err(), "{:?}", input);
+            assert!(
+                from_slice::<Vec<Line>>(input.as_bytes()).is_err(),
+                "{:?}",
+                input
+            );
+        }
+        assert!(from_str::<Vec<Line>>("\n\n").unwrap().is_empty());
+        // The last line doesn't need a line ending
+        assert_eq!(1, from_str::<Vec<Line>>("#EXTM3U").unwrap().len());
+
+        assert!(from_slice::<Vec<Line>>(&[0xff, 0xfe, b'\n']).is_err());
+
+        let err = from_str::<Vec<Line>>("\u{0}\u{1}\u{2}").unwrap_err();
+        assert!(
+            matches!(&err, Error::Parse(message) if message.starts_with("line 1:")),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn rejects_a_bad_line_mid_playlist() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "# a comment\n",
+            "\n",
+            "#EXTINF:6.0,\n",
+            "a.ts\n",
+            "bad line here\n",
+            "#EXTINF:6.0,\n",
+            "b.ts\n",
+            "#EXT-X-ENDLIST\n",
+        );
+
+        let err = from_str::<Vec<Line>>(input).unwrap_err();
+        assert!(
+            matches!(&err, Error::Parse(message) if message.starts_with("line 7:")),
+            "{:?}",
+            err
+        );
+        let lines = from_str::<Vec<Line>>(input.replace("bad line here\n", "").trim_end()).unwrap();
+        assert!(matches!(lines.last(), Some(Line::Tag(Tag::Endlist))));
+    }
+
+    #[test]
+    fn deserializes_from_bytes() {
+        let input = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXTINF:6.0,caf\u{e9}\na.ts\n";
+        let from_bytes: Vec<Line> = from_slice(input.as_bytes()).unwrap();
+        let from_text: Vec<Line> = from_str(input).unwrap();
+        assert_eq!(
+            crate::to_string(&from_bytes).unwrap(),
+            crate::to_string(&from_text).unwrap()
+        );
+
+        // The first byte of the é, leaving a stray continuation byte
+        let mut invalid = input.as_bytes().to_vec();
+        let e = input.find('\u{e9}').unwrap();
+        invalid[e] = 0xff;
+        assert!(matches!(
+            from_slice::<Vec<Line>>(&invalid),
+            Err(Error::InvalidUtf8)
+        ));
+    }
+
+    #[test]
+    fn rejects_mutated_tag_values() {
+        // A fixed-seed xorshift keeps the inputs the same from run to run
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let junk = b"xyz-+.,=@\"'#:; \t";
+
+        for _ in 0..500 {
+            let len = 1 + next() % 12;
+            let value: String = (0..len)
+                .map(|_| junk[(next() % junk.len() as u64) as usize] as char)
+                .collect();
+            for tag in ["TARGETDURATION", "MEDIA-SEQUENCE", "PLAYLIST-TYPE"] {
+                let input = format!("#EXT-X-{}:{}\n", tag, value);
+                assert!(from_str::<Vec<Line>>(&input).is_err(), "{:?}", input);
+            }
+            let input = format!("#EXT-X-STREAM-INF:BANDWIDTH={}\nlow.m3u8\n", value);
+            assert!(from_str::<Vec<Line>>(&input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn rejects_unit_variants_for_lines() {
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(dead_code)]
+        enum BareLine {
+            Tag,
+            Uri,
+        }
+
+        assert!(from_str::<Vec<BareLine>>("#EXT-X-ENDLIST\n").is_err());
+        assert!(from_str::<Vec<BareLine>>("a.ts\n").is_err());
+    }
+
+    #[test]
+    fn parses_large_playlists() {
+        let mut input = String::from("#EXTM3U\n#EXT-X-TARGETDURATION:6\n");
+        for i in 0..100_000 {
+            input.push_str(&format!("#EXTINF:6.0,\nsegment-{}.ts\n", i));
+        }
+        let lines: Vec<Line> = from_str(&input).unwrap();
+        assert_eq!(lines.len(), 200_002);
+
+        let long_uri = "a".repeat(1 << 20);
+        let lines: Vec<Line> = from_str(&format!("{}\n", long_uri)).unwrap();
+        assert!(matches!(&lines[..], [Line::Uri(uri)] if uri.len() == 1 << 20));
+    }
+
+    #[test]
+    fn keeps_unknown_tags_and_their_arguments() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-VENDOR-CUE:ID=\"ad-1\",DURATION=30.50,SIZE=1920x1080\n",
+            "#EXT-X-VENDOR-FLAG\n",
+            "#EXT-X-TARGETDURATION:6\n",
+        );
+        let lines: Vec<Line> = from_str(input).unwrap();
+
+        assert!(matches!(
+            &lines[1],
+            Line::Tag(Tag::Unknown { name, raw_args: Some(args) })
+                if name == "EXT-X-VENDOR-CUE" && args == "ID=\"ad-1\",DURATION=30.50,SIZE=1920x1080"
+        ));
+        assert!(matches!(
+            &lines[2],
+            Line::Tag(Tag::Unknown { name, raw_args: None }) if name == "EXT-X-VENDOR-FLAG"
+        ));
+        assert!(matches!(lines[3], Line::Tag(Tag::Targetduration(6))));
+    }
+
+    #[test]
+    fn deserializes_variant_resolution() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=1920x1080\n",
+            "hd.m3u8\n",
+        );
+        let lines: Vec<Line> = from_str(input).unwrap();
+
+        assert!(matches!(
+            &lines[1],
+            Line::Tag(Tag::StreamInf(attrs))
+                if attrs.resolution == Some(Resolution { width: 1920, height: 1080 })
+        ));
+    }
+
+    #[test]
+    fn deserializes_struct_variants_with_trailing_uri() {
+        use crate::manifest::StreamInfAttributes;
+        use serde::Deserialize;
+
+        #[derive(Debug, Deserialize)]
+        #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+        enum VariantTag {
+            M3u,
+            IndependentSegments {},
+            StreamInf {
+                attrs: Box<StreamInfAttributes>,
+                uri: Option<String>,
+            },
+        }
+
+        #[derive(Debug, Deserialize)]
+        enum VariantLine {
+            Tag(VariantTag),
+        }
+
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-INDEPENDENT-SEGMENTS\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000\n",
+            "low.m3u8\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=2560000\n",
+        );
+        let lines: Vec<VariantLine> = from_str(input).unwrap();
+
+        assert!(matches!(
+            &lines[1],
+            VariantLine::Tag(VariantTag::IndependentSegments {})
+        ));
+        assert!(matches!(
+            &lines[2],
+            VariantLine::Tag(VariantTag::StreamInf { attrs, uri: Some(uri) })
+                if attrs.bandwidth == 1280000 && uri == "low.m3u8"
+        ));
+        assert!(matches!(
+            &lines[3],
+            VariantLine::Tag(VariantTag::StreamInf { attrs, uri: None })
+                if attrs.bandwidth == 2560000
+        ));
+    }
+
+    #[test]
+    fn keeps_segment_titles() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXTINF:6.006,Segment Title\n",
+            "a.ts\n",
+            "#EXTINF:6.006,\n",
+            "b.ts\n",
+            "#EXT-X-VENDOR-INF:2.5,Vendor Title\n",
+        );
+        let lines: Vec<Line> = from_str(input).unwrap();
+
+        assert!(matches!(
+            &lines[1],
+            Line::Tag(Tag::Inf(d, Some(title))) if *d == 6.006 && title == "Segment Title"
+        ));
+        assert!(matches!(lines[2], Line::Uri(_)));
+        assert!(matches!(lines[3], Line::Tag(Tag::Inf(_, None))));
+        assert!(matches!(
+            &lines[5],
+            Line::Tag(Tag::Unknown { raw_args: Some(args), .. }) if args == "2.5,Vendor Title"
+        ));
+
+        let input = "#EXTINF:5,\n#EXT-X-STREAM-INF:BANDWIDTH=1000,FRAME-RATE=30\nlow.m3u8\n";
+        let lines: Vec<Line> = from_str(input).unwrap();
+        assert!(matches!(lines[0], Line::Tag(Tag::Inf(d, None)) if d == 5.0));
+        assert!(matches!(
+            &lines[1],
+            Line::Tag(Tag::StreamInf(attrs)) if attrs.frame_rate == Some(30.0)
+        ));
+    }
+
+    #[test]
+    fn deserializes_attribute_lists_into_maps() {
+        use crate::manifest::AttrValue;
+        use serde::Deserialize;
+        use std::collections::HashMap;
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+        enum VendorTag {
+            VendorCue(HashMap<String, AttrValue>),
+        }
+
+        #[derive(Deserialize)]
+        enum VendorLine {
+            Tag(VendorTag),
+        }
+
+        let input = "#EXT-X-VENDOR-CUE:ID=\"ad\",KIND=MIDROLL,SKIP=YES,SIZE=640x360,COUNT=3,OFFSET=-2,DURATION=1.5,KEY=0x0A0B\n";
+        let lines: Vec<VendorLine> = from_str(input).unwrap();
+        let VendorLine::Tag(VendorTag::VendorCue(attrs)) = &lines[0];
+
+        assert_eq!(attrs["ID"], AttrValue::String("ad".to_owned()));
+        assert_eq!(attrs["KIND"], AttrValue::String("MIDROLL".to_owned()));
+        assert_eq!(attrs["SKIP"], AttrValue::Bool(true));
+        assert_eq!(attrs["SIZE"], AttrValue::String("640x360".to_owned()));
+        assert_eq!(attrs["COUNT"], AttrValue::Integer(3));
+        assert_eq!(attrs["OFFSET"], AttrValue::SignedInteger(-2));
+        assert_eq!(attrs["DURATION"], AttrValue::Float(1.5));
+        assert_eq!(attrs["KEY"], AttrValue::Hex(vec![10, 11]));
+    }
+
+    #[test]
+    fn deserializes_only_tags() {
+        let input =
+            "#EXTM3U\n#EXT-X-TARGETDURATION:6\n# note\n#EXTINF:6.0,\nseg1.ts\n#EXT-X-ENDLIST\n";
+        let tags: Vec<Tag> = tags_from_str(input).unwrap();
+
+        assert_eq!(tags.len(), 4);
+        assert!(matches!(tags[1], Tag::Targetduration(6)));
+        assert!(matches!(tags[3], Tag::Endlist));
+    }
+
+    #[test]
+    fn ignores_whole_tags() {
+        #[derive(serde::Deserialize)]
+        enum UriLine {
+            Tag(de::IgnoredAny),
+            Uri(String),
+        }
+
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x0A0B\n",
+            "#EXT-X-VENDOR-AD:ID=\"ad-1\",DURATION=30.5,MARKERS=YES\n",
+            "#EXTINF:6.0,Title\n",
+            "a.ts\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=1280x720\n",
+            "b.ts\n",
+        );
+        let lines: Vec<UriLine> = from_str(input).unwrap();
+        let uris: Vec<_> = lines
+            .into_iter()
+            .filter_map(|line| match line {
+                UriLine::Uri(uri) => Some(uri),
+                UriLine::Tag(_) => None,
+            })
+            .collect();
+        assert_eq!(uris, ["a.ts", "b.ts"]);
+
+        #[derive(serde::Deserialize)]
+        #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+        enum FewTags {
+            Targetduration(u64),
+            #[serde(other)]
+            Other,
+        }
+
+        #[derive(serde::Deserialize)]
+        enum FewLines {
+            Tag(FewTags),
+            Uri(String),
+        }
+
+        let lines: Vec<FewLines> = from_str(input).unwrap();
+        let uris: Vec<_> = lines
+            .iter()
+            .filter_map(|line| match line {
+                FewLines::Uri(uri) => Some(uri.as_str()),
+                FewLines::Tag(_) => None,
+            })
+            .collect();
+        assert_eq!(uris, ["a.ts", "b.ts"]);
+        assert!(matches!(
+            lines[1],
+            FewLines::Tag(FewTags::Targetduration(6))
+        ));
+        assert!(matches!(lines[3], FewLines::Tag(FewTags::Other)));
+    }
+
+    #[test]
+    fn deserializes_flattened_attribute_structs() {
+        use crate::manifest::EncryptionMethod;
+        use serde::Deserialize;
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+        struct Drm {
+            keyformat: Option<String>,
+            keyformatversions: Option<String>,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+        struct Key {
+            method: EncryptionMethod,
+            uri: Option<String>,
+            #[serde(default, with = "serde_bytes")]
+            iv: Option<Vec<u8>>,
+            #[serde(flatten)]
+            drm: Drm,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+        enum DrmTag {
+            Key(Key),
+            SessionKey(Key),
+            Targetduration(u64),
+        }
+
+        #[derive(Deserialize)]
+        enum DrmLine {
+            Tag(DrmTag),
+        }
+
+        let input = concat!(
+            "#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"skd://key\",IV=0x0A0B,KEYFORMAT=\"com.apple.streamingkeydelivery\",KEYFORMATVERSIONS=\"1\"\n",
+            "#EXT-X-SESSION-KEY:METHOD=AES-128,URI=\"key.bin\"\n",
+            "#EXT-X-TARGETDURATION:6\n",
+        );
+        let lines: Vec<DrmLine> = from_str(input).unwrap();
+
+        let DrmLine::Tag(DrmTag::Key(key)) = &lines[0] else {
+            panic!("expected a key");
+        };
+        assert_eq!(key.method, EncryptionMethod::SampleAes);
+        assert_eq!(key.uri.as_deref(), Some("skd://key"));
+        assert_eq!(key.iv.as_deref(), Some(&[10, 11][..]));
+        assert_eq!(
+            key.drm.keyformat.as_deref(),
+            Some("com.apple.streamingkeydelivery")
+        );
+        assert_eq!(key.drm.keyformatversions.as_deref(), Some("1"));
+
+        let DrmLine::Tag(DrmTag::SessionKey(session)) = &lines[1] else {
+            panic!("expected a session key");
+        };
+        assert_eq!(session.drm.keyformat, None);
+        assert!(matches!(lines[2], DrmLine::Tag(DrmTag::Targetduration(6))));
     }
 }
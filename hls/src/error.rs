@@ -1,13 +1,17 @@
-use serde::de;
+use serde::{de, ser};
 use std::fmt::Display;
 
 #[derive(Debug)]
 pub enum Error {
     Message(String),
+    /// The playlist couldn't be read at all, with where and why
+    Parse(String),
     Syntax,
     InvalidHex,
+    InvalidUtf8,
     TrailingCharacters,
     UnexpectedEof,
+    Io(std::io::Error),
 }
 
 impl Display for Error {
@@ -24,4 +28,51 @@ impl de::Error for Error {
     }
 }
 
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+/// A tag attribute that was missing or did not have the expected type
+#[derive(Debug)]
+pub struct AttrError {
+    pub name: String,
+    pub expected: &'static str,
+    pub found: Option<String>,
+}
+
+impl AttrError {
+    pub(crate) fn missing(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            expected: "a value",
+            found: None,
+        }
+    }
+
+    pub(crate) fn mismatch<V: Display>(name: &str, expected: &'static str, found: V) -> Self {
+        Self {
+            name: name.to_owned(),
+            expected,
+            found: Some(found.to_string()),
+        }
+    }
+}
+
+impl Display for AttrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.found {
+            Some(found) => write!(
+                f,
+                "attribute {}: expected {}, found {}",
+                self.name, self.expected, found
+            ),
+            None => write!(f, "missing required attribute {}", self.name),
+        }
+    }
+}
+
+impl std::error::Error for AttrError {}
+
 pub type Result<T> = std::result::Result<T, Error>;
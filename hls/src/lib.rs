@@ -1,8 +1,38 @@
+mod ads;
+mod batch;
+pub mod comments;
+pub mod dash;
 mod de;
 mod error;
+mod lines;
 mod manifest;
 mod models;
 mod parser;
+mod playlist;
+mod recover;
+mod ser;
+mod span;
+pub mod validate;
+mod variables;
 
-pub use de::from_str;
-pub use manifest::{Line, Tag};
+pub use ads::{AdMarker, CueArgs, Scte35Attributes};
+pub use batch::parse_many;
+pub use de::{for_each, from_slice, from_str, from_str_with_comments, tags_from_str};
+pub use error::{AttrError, Error};
+pub use lines::{from_reader, iter_lines, read_lines, LineIterator, LineReader};
+pub use manifest::{
+    AttrValue, ByteRange, ClientAttribute, ContentSteering, DateRange, DateTime, DefineAttributes,
+    EncryptionMethod, HdcpLevel, IFrameStreamInfAttributes, InstreamId, KeyAttributes, Line,
+    MapAttributes, MediaAttributes, MediaType, PartAttributes, PartInfAttributes, PlaylistType,
+    PreloadHint, PreloadHintType, RenditionReport, Resolution, ServerControl, SkipAttributes,
+    StartAttributes, StreamInfAttributes, Tag,
+};
+pub use models::{Attribute, AttributeValue, Attributes, HexSequence};
+pub use playlist::{MasterPlaylist, MediaPlaylist, Playlist, Segment, Variant};
+pub use recover::{
+    from_str_recovering, from_str_with_options, LineError, ParseOptions, ParseWarning, Parsed,
+    WarningKind,
+};
+pub use ser::to_string;
+pub use span::{line_spans, AttributeSpan, LineSpan, Span, SpanKind};
+pub use variables::{substitute_variables, DefineContext};
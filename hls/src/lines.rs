@@ -0,0 +1,167 @@
+use crate::de::Deserializer;
+use crate::error::{Error, Result};
+use crate::models::Line;
+use crate::parser::playlist_line;
+use nom::Finish;
+use serde::de::DeserializeOwned;
+use std::io::{BufRead, BufReader, Read};
+use std::marker::PhantomData;
+
+/// Iterator over the lines of a playlist in memory, each parsed only when it
+/// is reached. Blank lines and comments are skipped.
+pub struct LineIterator<'a, T> {
+    input: &'a str,
+    marker: PhantomData<T>,
+}
+
+/// Parse the lines of `input` one at a time, without building up the whole
+/// playlist first
+pub fn iter_lines<T>(input: &str) -> LineIterator<'_, T> {
+    LineIterator {
+        input,
+        marker: PhantomData,
+    }
+}
+
+impl<T: DeserializeOwned> Iterator for LineIterator<'_, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        while !self.input.is_empty() {
+            let end = self.input.find('\n').map_or(self.input.len(), |i| i + 1);
+            let (line, rest) = self.input.split_at(end);
+            self.input = rest;
+
+            if let Some(parsed) = parse_line(line).transpose() {
+                return Some(parsed);
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over the lines of a playlist read from `R`, reusing a single
+/// line buffer
+pub struct LineReader<R, T> {
+    reader: R,
+    buf: String,
+    marker: PhantomData<T>,
+}
+
+/// Read and parse lines from `reader` one at a time, e.g. to process a
+/// playlist too large to hold in memory
+pub fn read_lines<R: BufRead, T>(reader: R) -> LineReader<R, T> {
+    LineReader {
+        reader,
+        buf: String::new(),
+        marker: PhantomData,
+    }
+}
+
+impl<R: BufRead, T: DeserializeOwned> Iterator for LineReader<R, T> {
+    type Item = Result<T>;
+
+    fn next(&mut self) -> Option<Result<T>> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => return Some(Err(Error::Io(e))),
+            }
+
+            if let Some(parsed) = parse_line(&self.buf).transpose() {
+                return Some(parsed);
+            }
+        }
+    }
+}
+
+/// Like [`read_lines`], for a reader without its own buffering such as an
+/// HTTP response body. Each line is parsed as soon as it has been read
+/// rather than after the whole playlist has arrived.
+pub fn from_reader<R: Read, T>(reader: R) -> LineReader<BufReader<R>, T> {
+    read_lines(BufReader::new(reader))
+}
+
+/// Parse one line, including its line ending. None for lines with nothing
+/// to deserialize.
+pub(crate) fn parse_line<T: DeserializeOwned>(line: &str) -> Result<Option<T>> {
+    // The last line of a file may have no line ending
+    if !line.ends_with('\n') {
+        return parse_line(&format!("{}\n", line));
+    }
+
+    let (rest, line) = playlist_line(line).finish().map_err(|_| Error::Syntax)?;
+    if !rest.is_empty() {
+        return Err(Error::TrailingCharacters);
+    }
+
+    match line {
+        None | Some(Line::Comment(_)) => Ok(None),
+        Some(line) => {
+            let mut deserializer = Deserializer::from_line(line);
+            let value = T::deserialize(&mut deserializer)?;
+            if !deserializer.is_finished() {
+                return Err(Error::TrailingCharacters);
+            }
+            Ok(Some(value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::manifest::{Line, Tag};
+
+    #[test]
+    fn parses_lines_lazily() {
+        let input = "#EXTM3U\n\n# comment\n#EXTINF:6.0,\nseg1.ts\n#EXT-X-ENDLIST";
+
+        let lines: Vec<Line> = iter_lines(input).collect::<Result<_>>().unwrap();
+        assert_eq!(lines.len(), 4);
+        assert!(matches!(lines[1], Line::Tag(Tag::Inf(d, None)) if d == 6.0));
+        assert!(matches!(lines[3], Line::Tag(Tag::Endlist)));
+
+        let read: Vec<Line> = read_lines(input.as_bytes()).collect::<Result<_>>().unwrap();
+        assert_eq!(read.len(), 4);
+
+        let streamed: Vec<Line> = from_reader(input.as_bytes())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(streamed.len(), 4);
+
+        let mut bad = iter_lines::<Line>("#EXT-X-TARGETDURATION:abc\nseg1.ts\n");
+        assert!(bad.next().unwrap().is_err());
+        assert!(matches!(bad.next(), Some(Ok(Line::Uri(_)))));
+    }
+
+    /// Gives out `data`, then fails as a dropped connection would
+    struct Interrupted<'a> {
+        data: &'a [u8],
+    }
+
+    impl Read for Interrupted<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.data.is_empty() {
+                return Err(std::io::ErrorKind::ConnectionReset.into());
+            }
+            let n = self.data.read(buf)?;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reads_lines_before_the_rest_arrives() {
+        let reader = Interrupted {
+            data: b"#EXTM3U\n#EXTINF:6.0,\nseg1.ts\n",
+        };
+        let mut lines = from_reader::<_, Line>(reader);
+
+        assert!(matches!(lines.next(), Some(Ok(Line::Tag(Tag::M3u)))));
+        assert!(matches!(lines.next(), Some(Ok(Line::Tag(Tag::Inf(..))))));
+        assert!(matches!(lines.next(), Some(Ok(Line::Uri(_)))));
+        assert!(matches!(lines.next(), Some(Err(Error::Io(_)))));
+    }
+}
@@ -1,6 +1,10 @@
-use serde::Deserialize;
+use crate::ads::{CueArgs, Scte35Attributes};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::str::FromStr;
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub enum MediaType {
     Audio,
@@ -9,21 +13,202 @@ pub enum MediaType {
     ClosedCaptions,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub enum PlaylistType {
     Event,
     Vod,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub enum HdcpLevel {
     None,
     Type0,
 }
 
-#[derive(Debug, Deserialize)]
+/// The closed caption channel (`CC1`-`CC4`) or digital TV service
+/// (`SERVICE1`-`SERVICE63`) carried by a CLOSED-CAPTIONS rendition
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum InstreamId {
+    Cc(u8),
+    Service(u8),
+}
+
+impl FromStr for InstreamId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |n: &str, max| n.parse().ok().filter(|n| (1..=max).contains(n));
+
+        let id = if let Some(n) = s.strip_prefix("CC") {
+            parse(n, 4).map(InstreamId::Cc)
+        } else if let Some(n) = s.strip_prefix("SERVICE") {
+            parse(n, 63).map(InstreamId::Service)
+        } else {
+            None
+        };
+
+        id.ok_or_else(|| format!("invalid INSTREAM-ID '{}'", s))
+    }
+}
+
+impl TryFrom<String> for InstreamId {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<InstreamId> for String {
+    fn from(id: InstreamId) -> Self {
+        id.to_string()
+    }
+}
+
+impl Display for InstreamId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Cc(n) => write!(f, "CC{}", n),
+            Self::Service(n) => write!(f, "SERVICE{}", n),
+        }
+    }
+}
+
+/// The `<width>x<height>` pixel dimensions of a variant
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Resolution {
+    pub width: u64,
+    pub height: u64,
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once('x')
+            .and_then(|(width, height)| {
+                Some(Resolution {
+                    width: width.parse().ok()?,
+                    height: height.parse().ok()?,
+                })
+            })
+            .ok_or_else(|| format!("invalid RESOLUTION '{}'", s))
+    }
+}
+
+impl TryFrom<String> for Resolution {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Resolution> for String {
+    fn from(resolution: Resolution) -> Self {
+        resolution.to_string()
+    }
+}
+
+impl Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// The timestamp of an EXT-X-PROGRAM-DATE-TIME tag. With the `chrono`
+/// feature it is parsed as an ISO 8601 date and time; otherwise it is kept
+/// as written.
+#[cfg(feature = "chrono")]
+pub type DateTime = chrono::DateTime<chrono::FixedOffset>;
+#[cfg(not(feature = "chrono"))]
+pub type DateTime = String;
+
+#[cfg(feature = "chrono")]
+mod date_time {
+    use super::DateTime;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<DateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        DateTime::parse_from_rfc3339(&s).map_err(|e| {
+            de::Error::custom(format!("invalid EXT-X-PROGRAM-DATE-TIME '{}': {}", s, e))
+        })
+    }
+
+    pub fn serialize<S>(date_time: &DateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = date_time.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true);
+        serializer.serialize_str(&s)
+    }
+}
+
+/// A `<length>[@<offset>]` sub-range of a resource. Without an offset the
+/// range starts where the previous range of the same resource ended. Empty
+/// ranges are rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ByteRange {
+    pub length: u64,
+    pub offset: Option<u64>,
+}
+
+impl FromStr for ByteRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (length, offset) = match s.split_once('@') {
+            Some((length, offset)) => (length, Some(offset)),
+            None => (s, None),
+        };
+        let invalid = || format!("invalid BYTERANGE '{}'", s);
+        let length = match length.parse() {
+            Ok(0) | Err(_) => return Err(invalid()),
+            Ok(length) => length,
+        };
+
+        Ok(ByteRange {
+            length,
+            offset: offset
+                .map(|offset| offset.parse().map_err(|_| invalid()))
+                .transpose()?,
+        })
+    }
+}
+
+impl TryFrom<String> for ByteRange {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ByteRange> for String {
+    fn from(range: ByteRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl Display for ByteRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{}@{}", self.length, offset),
+            None => write!(f, "{}", self.length),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub struct MediaAttributes {
     #[serde(rename = "TYPE")]
@@ -36,27 +221,42 @@ pub struct MediaAttributes {
     pub default: Option<bool>,
     pub autoselect: Option<bool>,
     pub forced: Option<bool>,
-    pub instream_id: Option<String>,
+    pub instream_id: Option<InstreamId>,
     pub characteristics: Option<String>,
     pub channels: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub struct StreamInfAttributes {
     pub bandwidth: u64,
     pub average_bandwidth: Option<u64>,
     pub codecs: Option<String>,
-    pub resolution: Option<String>,
+    pub resolution: Option<Resolution>,
     pub frame_rate: Option<f64>,
     pub hdcp_level: Option<HdcpLevel>,
     pub audio: Option<String>,
     pub video: Option<String>,
     pub subtitles: Option<String>,
     pub closed_captions: Option<String>,
+    /// The content steering pathway the variant belongs to
+    pub pathway_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct IFrameStreamInfAttributes {
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    pub codecs: Option<String>,
+    pub resolution: Option<Resolution>,
+    pub hdcp_level: Option<HdcpLevel>,
+    pub video: Option<String>,
+    pub pathway_id: Option<String>,
+    pub uri: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub enum EncryptionMethod {
     #[serde(rename = "AES-128")]
@@ -65,37 +265,303 @@ pub enum EncryptionMethod {
     SampleAes,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub struct KeyAttributes {
     pub method: EncryptionMethod,
     pub uri: Option<String>,
-    #[serde(with = "serde_bytes")]
+    #[serde(default, with = "serde_bytes")]
     pub iv: Option<Vec<u8>>,
     pub keyformat: Option<String>,
     pub keyformatversions: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+impl KeyAttributes {
+    /// Whether this is a plain AES key that can be fetched from its URI, as
+    /// opposed to one delivered by a DRM system
+    pub fn is_identity(&self) -> bool {
+        self.keyformat.as_deref().is_none_or(|f| f == "identity")
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct MapAttributes {
+    pub uri: String,
+    pub byterange: Option<ByteRange>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct PartInfAttributes {
+    pub part_target: f64,
+}
+
+/// A partial segment of a Low-Latency HLS playlist
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct PartAttributes {
+    pub uri: String,
+    pub duration: f64,
+    pub independent: Option<bool>,
+    pub byterange: Option<ByteRange>,
+    pub gap: Option<bool>,
+}
+
+/// What a live origin supports when reloading a playlist, from
+/// EXT-X-SERVER-CONTROL
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct ServerControl {
+    /// How far from the live edge, in seconds, playlist deltas may skip
+    /// segments up to
+    pub can_skip_until: Option<f64>,
+    pub can_skip_dateranges: Option<bool>,
+    /// Seconds from the end of the playlist that playback should start
+    pub hold_back: Option<f64>,
+    pub part_hold_back: Option<f64>,
+    /// Whether blocking reloads with `_HLS_msn` are supported
+    pub can_block_reload: Option<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub enum PreloadHintType {
+    Part,
+    Map,
+}
+
+/// A resource that will be part of the next playlist update, which can be
+/// requested ahead of time
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct PreloadHint {
+    #[serde(rename = "TYPE")]
+    pub hint_type: PreloadHintType,
+    pub uri: String,
+    pub byterange_start: Option<u64>,
+    pub byterange_length: Option<u64>,
+}
+
+/// The latest segment and part of another rendition of the same content
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct RenditionReport {
+    pub uri: String,
+    pub last_msn: Option<u64>,
+    pub last_part: Option<u64>,
+}
+
+/// Where to fetch the steering manifest that picks between a master
+/// playlist's pathways, e.g. one per CDN
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct ContentSteering {
+    pub server_uri: String,
+    /// The pathway to use until the steering manifest has been fetched
+    pub pathway_id: Option<String>,
+}
+
+/// An EXT-X-DEFINE variable. Exactly one of `name` (with `value`),
+/// `import` or `queryparam` is given.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct DefineAttributes {
+    pub name: Option<String>,
+    pub value: Option<String>,
+    pub import: Option<String>,
+    pub queryparam: Option<String>,
+}
+
+/// Marks where a playlist delta update left out segments the client already
+/// has
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct SkipAttributes {
+    pub skipped_segments: u64,
+    /// Tab separated IDs of dateranges removed since the last update
+    pub recently_removed_dateranges: Option<String>,
+}
+
+/// EXT-X-START: where playback should begin. A negative TIME-OFFSET counts
+/// back from the end of the playlist.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct StartAttributes {
+    pub time_offset: f64,
+    pub precise: Option<bool>,
+}
+
+/// An EXT-X-DATERANGE tag, marking a range of time such as an ad break
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "SCREAMING-KEBAB-CASE")]
+pub struct DateRange {
+    pub id: String,
+    pub class: Option<String>,
+    pub start_date: String,
+    pub end_date: Option<String>,
+    pub duration: Option<f64>,
+    pub planned_duration: Option<f64>,
+    #[serde(default, with = "serde_bytes")]
+    pub scte35_cmd: Option<Vec<u8>>,
+    #[serde(default, with = "serde_bytes")]
+    pub scte35_out: Option<Vec<u8>>,
+    #[serde(default, with = "serde_bytes")]
+    pub scte35_in: Option<Vec<u8>>,
+    pub end_on_next: Option<bool>,
+    /// `X-` attributes defined by the client, keyed by name
+    #[serde(flatten)]
+    pub client_attributes: BTreeMap<String, ClientAttribute>,
+}
+
+/// The value of an `X-` attribute, which may be a quoted string, a hex
+/// sequence or a number
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum ClientAttribute {
+    String(String),
+    Hex(#[serde(with = "serde_bytes")] Vec<u8>),
+    Number(f64),
+}
+
+/// Any attribute value, for reading the attribute list of a tag that has no
+/// struct of its own into e.g. a `HashMap<String, AttrValue>`
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttrValue {
+    /// A quoted string, or an enumerated string other than YES or NO
+    String(String),
+    /// YES or NO
+    Bool(bool),
+    Integer(u64),
+    SignedInteger(i64),
+    Float(f64),
+    Hex(Vec<u8>),
+}
+
+impl<'de> Deserialize<'de> for AttrValue {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_any(AttrValueVisitor)
+    }
+}
+
+struct AttrValueVisitor;
+
+impl<'de> serde::de::Visitor<'de> for AttrValueVisitor {
+    type Value = AttrValue;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an attribute value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<AttrValue, E> {
+        Ok(AttrValue::Bool(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<AttrValue, E> {
+        Ok(AttrValue::Integer(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<AttrValue, E> {
+        Ok(AttrValue::SignedInteger(v))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<AttrValue, E> {
+        Ok(AttrValue::Float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<AttrValue, E> {
+        Ok(AttrValue::String(v.to_owned()))
+    }
+
+    fn visit_byte_buf<E>(self, v: Vec<u8>) -> Result<AttrValue, E> {
+        Ok(AttrValue::Hex(v))
+    }
+}
+
+impl Serialize for AttrValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        match self {
+            AttrValue::String(s) => serializer.serialize_str(s),
+            AttrValue::Bool(b) => serializer.serialize_bool(*b),
+            AttrValue::Integer(i) => serializer.serialize_u64(*i),
+            AttrValue::SignedInteger(i) => serializer.serialize_i64(*i),
+            AttrValue::Float(f) => serializer.serialize_f64(*f),
+            AttrValue::Hex(bytes) => serializer.serialize_bytes(bytes),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(rename_all = "SCREAMING-KEBAB-CASE")]
 pub enum Tag {
     M3u,
+    Byterange(ByteRange),
+    ContentSteering(ContentSteering),
+    CueIn,
+    CueOut(Option<CueArgs>),
+    CueOutCont(Option<CueArgs>),
+    Daterange(Box<DateRange>),
+    Define(DefineAttributes),
+    Discontinuity,
+    DiscontinuitySequence(u64),
+    Endlist,
+    Gap,
     IndependentSegments,
-    Inf(f64),
+    IFramesOnly,
+    IFrameStreamInf(IFrameStreamInfAttributes),
+    /// Segment duration and optional title
+    Inf(f64, Option<String>),
     Key(KeyAttributes),
+    Map(MapAttributes),
     Media(MediaAttributes),
     MediaSequence(u64),
+    Part(PartAttributes),
+    PartInf(PartInfAttributes),
     Targetduration(u64),
     Version(u64),
     PlaylistType(PlaylistType),
-    ProgramDateTime(String),
+    PreloadHint(PreloadHint),
+    ProgramDateTime(#[cfg_attr(feature = "chrono", serde(with = "date_time"))] DateTime),
+    RenditionReport(RenditionReport),
+    Scte35(Scte35Attributes),
+    ServerControl(ServerControl),
+    Skip(SkipAttributes),
+    Start(StartAttributes),
     StreamInf(StreamInfAttributes),
-    #[serde(other)]
-    Unknown,
+    /// Any tag not modelled above, e.g. a vendor extension. `name` is the
+    /// full name as written, such as `EXT-X-VENDOR-CUE` or `EXTGRP`, and
+    /// `raw_args` holds the tag's arguments exactly as they were written, so
+    /// the tag can be written back out unchanged.
+    Unknown {
+        name: String,
+        raw_args: Option<String>,
+    },
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub enum Line {
     Tag(Tag),
     Uri(String),
+    /// Only produced by [`crate::from_str_with_comments`]
+    Comment(String),
+}
+
+/// The line as playlist text, without a line ending
+impl Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&crate::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// The tag as playlist text, without a line ending
+impl Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&crate::ser::tag_to_string(self).map_err(|_| fmt::Error)?)
+    }
 }
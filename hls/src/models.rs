@@ -1,6 +1,14 @@
+use crate::error::AttrError;
+use crate::manifest::Resolution;
 use crate::parser::all_tags;
-use nom::{error::Error, Finish};
+use nom::{
+    error::{Error, ErrorKind},
+    Finish,
+};
+use serde::de::value;
 use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt::Display;
 
 #[derive(Debug)]
 pub enum Node<'a> {
@@ -8,13 +16,19 @@ pub enum Node<'a> {
     AttributesEnd,
     AttributesStart,
     AttributeValue(AttributeValue<'a>),
+    Comment(&'a str),
     Float(f64),
     Integer(u64),
     ManifestEnd,
     ManifestStart,
+    SignedInteger(i64),
     String(&'a str),
-    TagName(&'a str),
+    /// A tag's name, its full name including the `EXT` prefix, and its
+    /// arguments exactly as they were written
+    TagName(&'a str, &'a str, Option<&'a str>),
     TagStart,
+    /// The title following an EXTINF duration
+    Title(&'a str),
     Uri(&'a str),
 }
 
@@ -26,16 +40,11 @@ pub struct Manifest<'a> {
 impl<'a> Manifest<'a> {
     pub fn parse(s: &'a str) -> Result<Self, Error<String>> {
         match all_tags(s).finish() {
-            Ok((remaining, lines)) => {
-                if !remaining.is_empty() {
-                    log::error!("Failed to parse! Next 3 lines:");
-                    for i in 0..3 {
-                        log::error!("{:?}", remaining.lines().nth(i));
-                    }
-                }
-
-                Ok(Self { lines })
-            }
+            Ok(("", lines)) => Ok(Self { lines }),
+            Ok((remaining, _)) => Err(Error {
+                input: remaining.to_string(),
+                code: ErrorKind::Eof,
+            }),
             Err(Error { input, code }) => Err(Error {
                 input: input.to_string(),
                 code,
@@ -43,41 +52,117 @@ impl<'a> Manifest<'a> {
         }
     }
 
-    pub fn nodes(self) -> Vec<Node<'a>> {
-        let mut ret = vec![Node::ManifestStart];
+    /// Lazily flatten the parsed lines into deserializer nodes. Comments are
+    /// only included if `keep_comments` is set.
+    pub fn nodes_iter(self, keep_comments: bool) -> Nodes<'a> {
+        Nodes {
+            lines: self.lines.into_iter(),
+            pending: VecDeque::new(),
+            keep_comments,
+            started: false,
+            ended: false,
+        }
+    }
+}
 
-        for line in self.lines {
-            match line {
-                Line::Tag { name, args } => {
-                    ret.push(Node::TagStart);
-                    ret.push(Node::TagName(name));
+impl<'a> Nodes<'a> {
+    /// The nodes of a single line, followed by the end of manifest marker
+    /// but without the start marker
+    pub fn line(line: Line<'a>) -> Self {
+        let mut nodes = Nodes {
+            lines: Vec::new().into_iter(),
+            pending: VecDeque::new(),
+            keep_comments: false,
+            started: true,
+            ended: false,
+        };
+        nodes.expand(line);
+        nodes
+    }
+}
 
-                    match args {
-                        Some(TagArgs::Attributes(attrs)) => {
-                            ret.push(Node::AttributesStart);
-                            for attr in attrs {
-                                ret.push(Node::AttributeName(attr.name));
-                                ret.push(Node::AttributeValue(attr.value));
-                            }
-                            ret.push(Node::AttributesEnd);
+/// Iterator over the nodes of a manifest. Only the nodes belonging to the
+/// current line are ever buffered.
+#[derive(Debug)]
+pub struct Nodes<'a> {
+    lines: std::vec::IntoIter<Line<'a>>,
+    pending: VecDeque<Node<'a>>,
+    keep_comments: bool,
+    started: bool,
+    ended: bool,
+}
+
+impl<'a> Nodes<'a> {
+    fn expand(&mut self, line: Line<'a>) {
+        match line {
+            Line::Tag {
+                name,
+                raw_name,
+                args,
+                raw_args,
+            } => {
+                self.pending.push_back(Node::TagStart);
+                self.pending
+                    .push_back(Node::TagName(name, raw_name, raw_args));
+
+                match args {
+                    Some(TagArgs::Attributes(attrs)) => {
+                        self.pending.push_back(Node::AttributesStart);
+                        for attr in attrs {
+                            self.pending.push_back(Node::AttributeName(attr.name));
+                            self.pending.push_back(Node::AttributeValue(attr.value));
                         }
-                        Some(TagArgs::String(s)) => ret.push(Node::String(s)),
-                        Some(TagArgs::Integer(i)) => ret.push(Node::Integer(i)),
-                        Some(TagArgs::Float(f)) => ret.push(Node::Float(f)),
-                        None => {}
+                        self.pending.push_back(Node::AttributesEnd);
                     }
-
-                    // ret.push(Node::TagEnd);
-                }
-                Line::Uri(uri) => {
-                    ret.push(Node::Uri(uri));
+                    Some(TagArgs::String(s)) => self.pending.push_back(Node::String(s)),
+                    Some(TagArgs::Integer(i)) => self.pending.push_back(Node::Integer(i)),
+                    Some(TagArgs::SignedInteger(i)) => {
+                        self.pending.push_back(Node::SignedInteger(i))
+                    }
+                    Some(TagArgs::Float(f, title)) => {
+                        self.pending.push_back(Node::Float(f));
+                        self.pending.extend(title.map(Node::Title));
+                    }
+                    None => {}
                 }
             }
+            Line::Uri(uri) => self.pending.push_back(Node::Uri(uri)),
+            Line::Comment(text) if self.keep_comments => {
+                self.pending.push_back(Node::Comment(text))
+            }
+            Line::Comment(_) => {}
+        }
+    }
+}
+
+impl<'a> Iterator for Nodes<'a> {
+    type Item = Node<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            return Some(Node::ManifestStart);
         }
 
-        ret.push(Node::ManifestEnd);
+        if let Some(node) = self.pending.pop_front() {
+            return Some(node);
+        }
 
-        ret
+        // Skipped comments expand to nothing, so keep going until a line
+        // produces a node
+        while let Some(line) = self.lines.next() {
+            self.expand(line);
+            if let Some(node) = self.pending.pop_front() {
+                return Some(node);
+            }
+        }
+
+        if self.ended {
+            None
+        } else {
+            self.ended = true;
+            Some(Node::ManifestEnd)
+        }
     }
 }
 
@@ -98,12 +183,127 @@ impl<'a> HexSequence<'a> {
 pub enum Line<'a> {
     Tag {
         name: &'a str,
+        /// The name as written, e.g. `EXT-X-KEY` or `EXTGRP`, without the `#`
+        raw_name: &'a str,
         args: Option<TagArgs<'a>>,
+        /// The text of `args`, for tags that are passed through untouched
+        raw_args: Option<&'a str>,
     },
     Uri(&'a str),
+    /// The text of a `#` line that is not a tag, without the leading `#`
+    Comment(&'a str),
+}
+
+#[derive(Debug)]
+pub struct Attributes<'a>(Vec<Attribute<'a>>);
+
+impl<'a> Attributes<'a> {
+    pub fn new(attrs: Vec<Attribute<'a>>) -> Self {
+        Self(attrs)
+    }
+
+    /// The attributes in the order they were written
+    pub fn iter(&self) -> impl Iterator<Item = &Attribute<'a>> {
+        self.0.iter()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&AttributeValue<'a>> {
+        self.0
+            .iter()
+            .find(|attr| attr.name == name)
+            .map(|attr| &attr.value)
+    }
+
+    pub fn get_str(&self, name: &str) -> Result<&'a str, AttrError> {
+        match self.require(name)? {
+            AttributeValue::String(s) => Ok(s),
+            other => Err(AttrError::mismatch(name, "quoted string", other)),
+        }
+    }
+
+    pub fn get_u64(&self, name: &str) -> Result<u64, AttrError> {
+        match self.require(name)? {
+            AttributeValue::Integer(i) => Ok(*i),
+            other => Err(AttrError::mismatch(name, "decimal integer", other)),
+        }
+    }
+
+    /// A signed decimal integer
+    pub fn get_i64(&self, name: &str) -> Result<i64, AttrError> {
+        match self.require(name)? {
+            AttributeValue::Integer(i) => i64::try_from(*i)
+                .map_err(|_| AttrError::mismatch(name, "signed decimal integer", i)),
+            AttributeValue::SignedInteger(i) => Ok(*i),
+            other => Err(AttrError::mismatch(name, "signed decimal integer", other)),
+        }
+    }
+
+    /// A decimal floating point number. Integers are accepted as well.
+    pub fn get_f64(&self, name: &str) -> Result<f64, AttrError> {
+        match self.require(name)? {
+            AttributeValue::Float(f) => Ok(*f),
+            AttributeValue::Integer(i) => Ok(*i as f64),
+            AttributeValue::SignedInteger(i) => Ok(*i as f64),
+            other => Err(AttrError::mismatch(name, "decimal floating point", other)),
+        }
+    }
+
+    /// An enumerated string such as METHOD=AES-128, read into any enum that
+    /// deserializes from its keywords, e.g. [`crate::EncryptionMethod`]
+    pub fn get_enum<T>(&self, name: &str) -> Result<T, AttrError>
+    where
+        T: Deserialize<'a>,
+    {
+        match self.require(name)? {
+            AttributeValue::Keyword(k) => {
+                let keyword = value::BorrowedStrDeserializer::<value::Error>::new(k);
+                T::deserialize(keyword)
+                    .map_err(|_| AttrError::mismatch(name, "known enumerated string", k))
+            }
+            other => Err(AttrError::mismatch(name, "enumerated string", other)),
+        }
+    }
+
+    pub fn get_resolution(&self, name: &str) -> Result<Resolution, AttrError> {
+        match self.require(name)? {
+            AttributeValue::Resolution { width, height } => Ok(Resolution {
+                width: *width,
+                height: *height,
+            }),
+            other => Err(AttrError::mismatch(name, "decimal resolution", other)),
+        }
+    }
+
+    pub fn get_bool(&self, name: &str) -> Result<bool, AttrError> {
+        match self.require(name)? {
+            AttributeValue::Keyword("YES") => Ok(true),
+            AttributeValue::Keyword("NO") => Ok(false),
+            other => Err(AttrError::mismatch(name, "YES or NO", other)),
+        }
+    }
+
+    pub fn get_hex(&self, name: &str) -> Result<Vec<u8>, AttrError> {
+        match self.require(name)? {
+            AttributeValue::Hex(h) => h.bytes().map_err(|_| {
+                AttrError::mismatch(name, "hexadecimal sequence", format!("0x{}", h.0))
+            }),
+            other => Err(AttrError::mismatch(name, "hexadecimal sequence", other)),
+        }
+    }
+
+    fn require(&self, name: &str) -> Result<&AttributeValue<'a>, AttrError> {
+        self.get(name).ok_or_else(|| AttrError::missing(name))
+    }
 }
 
-pub type Attributes<'a> = Vec<Attribute<'a>>;
+impl<'a> IntoIterator for Attributes<'a> {
+    type Item = Attribute<'a>;
+    type IntoIter = std::vec::IntoIter<Attribute<'a>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
 
 #[derive(Debug)]
 pub struct Attribute<'a> {
@@ -114,17 +314,38 @@ pub struct Attribute<'a> {
 #[derive(Debug)]
 pub enum AttributeValue<'a> {
     Integer(u64),
+    /// A negative integer; non-negative ones are always `Integer`
+    SignedInteger(i64),
     Hex(HexSequence<'a>),
     Float(f64),
     String(&'a str),
     Keyword(&'a str),
-    Resolution { width: u64, height: u64 },
+    Resolution {
+        width: u64,
+        height: u64,
+    },
+}
+
+impl Display for AttributeValue<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Integer(i) => write!(f, "{}", i),
+            Self::SignedInteger(i) => write!(f, "{}", i),
+            Self::Hex(h) => write!(f, "0x{}", h.0),
+            Self::Float(x) => write!(f, "{}", x),
+            Self::String(s) => write!(f, "\"{}\"", s),
+            Self::Keyword(k) => write!(f, "{}", k),
+            Self::Resolution { width, height } => write!(f, "{}x{}", width, height),
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum TagArgs<'a> {
     Attributes(Attributes<'a>),
-    Float(f64),
+    /// An EXTINF-style duration and optional title
+    Float(f64, Option<&'a str>),
     Integer(u64),
+    SignedInteger(i64),
     String(&'a str),
 }
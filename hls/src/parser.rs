@@ -1,34 +1,63 @@
 use crate::models::{Attribute, AttributeValue, Attributes, HexSequence, Line, TagArgs};
 use nom::{
     branch::alt,
-    bytes::complete::{is_not, tag, take_till, take_while},
+    bytes::complete::tag,
     character::complete::{char, digit0, digit1, hex_digit1, line_ending, one_of},
-    combinator::{map, map_res, not, opt, peek, recognize, value},
-    multi::{fold_many1, separated_list1},
+    combinator::{consumed, eof, map, map_res, not, opt, peek, recognize, verify},
+    error::{Error, ErrorKind},
+    multi::separated_list1,
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
 
-const WHITESPACE: &str = " \t\r\n";
+/// Split `i` at the first byte matching `pred`. All of the delimiters used in
+/// playlists are ASCII, so scanning bytes is both valid UTF-8 slicing and much
+/// cheaper than decoding chars.
+fn split_at_byte(i: &str, pred: impl Fn(u8) -> bool) -> (&str, &str) {
+    let end = i.bytes().position(pred).unwrap_or(i.len());
+    (&i[end..], &i[..end])
+}
+
+fn rest_of_line(i: &str) -> IResult<&str, &str> {
+    Ok(split_at_byte(i, |b| b == b'\r' || b == b'\n'))
+}
+
+fn rest_of_line1(i: &str) -> IResult<&str, &str> {
+    match rest_of_line(i)? {
+        (_, "") => Err(nom::Err::Error(Error::new(i, ErrorKind::IsNot))),
+        res => Ok(res),
+    }
+}
+
+fn non_whitespace1(i: &str) -> IResult<&str, &str> {
+    match split_at_byte(i, |b| matches!(b, b' ' | b'\t' | b'\r' | b'\n')) {
+        (_, "") => Err(nom::Err::Error(Error::new(i, ErrorKind::IsNot))),
+        res => Ok(res),
+    }
+}
 
 fn keyword_start(i: &str) -> IResult<&str, char> {
     one_of("ABCDEFGHIJKLMNOPQRSTUVWXYZ")(i)
 }
 
-fn keyword_char(c: char) -> bool {
-    "ABCDEFGHIJKLMNOPQRSTUVWXYZ-0123456789".contains(c)
+fn keyword_byte(b: u8) -> bool {
+    b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'-'
+}
+
+fn keyword_chars0(i: &str) -> IResult<&str, &str> {
+    Ok(split_at_byte(i, |b| !keyword_byte(b)))
 }
 
 fn keyword1(i: &str) -> IResult<&str, &str> {
-    recognize(pair(keyword_start, take_while(keyword_char)))(i)
+    recognize(pair(keyword_start, keyword_chars0))(i)
 }
 
-fn is_non_string(c: char) -> bool {
-    "\"\r\n".contains(c)
+fn string_chars0(i: &str) -> IResult<&str, &str> {
+    Ok(split_at_byte(i, |b| matches!(b, b'"' | b'\r' | b'\n')))
 }
 
 fn quoted_string(i: &str) -> IResult<&str, &str> {
-    delimited(char('"'), take_till(is_non_string), char('"'))(i)
+    delimited(char('"'), string_chars0, char('"'))(i)
 }
 
 fn dec_digit1(i: &str) -> IResult<&str, &str> {
@@ -39,6 +68,10 @@ fn integer(i: &str) -> IResult<&str, u64> {
     map_res(dec_digit1, |s| s.parse::<u64>())(i)
 }
 
+fn signed_integer(i: &str) -> IResult<&str, i64> {
+    map_res(recognize(pair(char('-'), dec_digit1)), |s| s.parse::<i64>())(i)
+}
+
 fn float(i: &str) -> IResult<&str, f64> {
     map_res(
         recognize(tuple((opt(char('-')), opt(dec_digit1), char('.'), digit1))),
@@ -49,10 +82,7 @@ fn float(i: &str) -> IResult<&str, f64> {
 fn tag_name(i: &str) -> IResult<&str, &str> {
     preceded(
         char('#'),
-        preceded(
-            tag("EXT"),
-            preceded(opt(tag("-X-")), take_while(keyword_char)),
-        ),
+        preceded(tag("EXT"), preceded(opt(tag("-X-")), keyword_chars0)),
     )(i)
 }
 
@@ -60,100 +90,188 @@ fn hex_sequence(i: &str) -> IResult<&str, &str> {
     preceded(alt((tag("0x"), tag("0X"))), hex_digit1)(i)
 }
 
-fn duration_name(i: &str) -> IResult<&str, f64> {
-    terminated(
-        terminated(float, char(',')),
-        take_till(|c| "\r\n".contains(c)),
-    )(i)
+/// Integer durations are allowed before protocol version 3
+fn duration(i: &str) -> IResult<&str, f64> {
+    alt((float, map(integer, |i| i as f64)))(i)
 }
 
-fn comment(i: &str) -> IResult<&str, ()> {
-    value(
-        (),
-        tuple((
-            char('#'),
-            not(tag("EXT")),
-            take_till(|c| "\r\n".contains(c)),
-            line_ending,
-        )),
-    )(i)
+fn duration_name(i: &str) -> IResult<&str, (f64, &str)> {
+    pair(terminated(duration, char(',')), rest_of_line)(i)
+}
+
+/// The end of a line, which for the last line of a file may be the end of
+/// the input
+fn line_end(i: &str) -> IResult<&str, &str> {
+    alt((line_ending, eof))(i)
 }
 
-fn resolution(i: &str) -> IResult<&str, AttributeValue> {
+fn comment(i: &str) -> IResult<&str, &str> {
+    delimited(pair(char('#'), not(tag("EXT"))), rest_of_line, line_end)(i)
+}
+
+fn resolution(i: &str) -> IResult<&str, AttributeValue<'_>> {
     map(
         separated_pair(integer, char('x'), integer),
         |(width, height)| AttributeValue::Resolution { width, height },
     )(i)
 }
 
-fn attr_val(i: &str) -> IResult<&str, AttributeValue> {
-    alt((
-        map(hex_sequence, |s| AttributeValue::Hex(HexSequence::new(s))),
-        resolution,
-        map(float, AttributeValue::Float),
-        map(integer, AttributeValue::Integer),
-        map(quoted_string, AttributeValue::String),
-        map(keyword1, AttributeValue::Keyword),
-    ))(i)
+fn attr_val(i: &str) -> IResult<&str, AttributeValue<'_>> {
+    // Dispatch on the first byte so that strings and keywords (the bulk of
+    // attribute values) never backtrack through the numeric parsers
+    match i.as_bytes().first() {
+        Some(b'"') => map(quoted_string, AttributeValue::String)(i),
+        Some(b'A'..=b'Z') => map(keyword1, AttributeValue::Keyword)(i),
+        _ => alt((
+            map(hex_sequence, |s| AttributeValue::Hex(HexSequence::new(s))),
+            resolution,
+            map(float, AttributeValue::Float),
+            map(integer, AttributeValue::Integer),
+            map(signed_integer, AttributeValue::SignedInteger),
+        ))(i),
+    }
 }
 
-fn attr(i: &str) -> IResult<&str, Attribute> {
-    map(
-        separated_pair(keyword1, char('='), attr_val),
-        |(name, value)| Attribute { name, value },
-    )(i)
+fn attr(i: &str) -> IResult<&str, Attribute<'_>> {
+    let (i, name) = terminated(keyword1, char('='))(i)?;
+    // A resolution such as 0x0 also reads as a hex sequence, so the attribute
+    // name decides which one it is
+    let (i, value) = if name == "RESOLUTION" {
+        alt((resolution, attr_val))(i)?
+    } else {
+        attr_val(i)?
+    };
+    Ok((i, Attribute { name, value }))
 }
 
-fn attrs(i: &str) -> IResult<&str, Attributes> {
-    separated_list1(char(','), attr)(i)
+fn attrs(i: &str) -> IResult<&str, Attributes<'_>> {
+    map(separated_list1(char(','), attr), Attributes::new)(i)
 }
 
-fn maybe_tag_args(i: &str) -> IResult<&str, Option<TagArgs>> {
-    opt(preceded(char(':'), tag_args))(i)
+/// The parsed arguments of a tag, along with their text as written
+fn maybe_tag_args(i: &str) -> IResult<&str, Option<(&str, TagArgs<'_>)>> {
+    opt(preceded(char(':'), consumed(tag_args)))(i)
 }
 
-fn tag_args(i: &str) -> IResult<&str, TagArgs> {
-    alt((
-        map(duration_name, TagArgs::Float),
-        map(attrs, TagArgs::Attributes),
-        map(terminated(integer, peek(line_ending)), TagArgs::Integer),
-        map(is_not(WHITESPACE), TagArgs::String),
-    ))(i)
+fn tag_args(i: &str) -> IResult<&str, TagArgs<'_>> {
+    match i.as_bytes().first() {
+        Some(b'A'..=b'Z') => alt((
+            map(terminated(attrs, peek(line_end)), TagArgs::Attributes),
+            map(rest_of_line1, TagArgs::String),
+        ))(i),
+        _ => alt((
+            map(duration_name, |(duration, title)| {
+                TagArgs::Float(duration, Some(title).filter(|t| !t.is_empty()))
+            }),
+            map(terminated(integer, peek(line_end)), TagArgs::Integer),
+            map(
+                terminated(signed_integer, peek(line_end)),
+                TagArgs::SignedInteger,
+            ),
+            // Anything else, e.g. the free text of #EXTALB, is kept whole
+            map(rest_of_line1, TagArgs::String),
+        ))(i),
+    }
 }
 
-fn playlist_tag(i: &str) -> IResult<&str, Line> {
+fn playlist_tag(i: &str) -> IResult<&str, Line<'_>> {
     map(
-        terminated(pair(tag_name, maybe_tag_args), line_ending),
-        |(name, args)| Line::Tag { name, args },
+        terminated(pair(consumed(tag_name), maybe_tag_args), line_end),
+        |((raw_name, name), args)| Line::Tag {
+            name,
+            raw_name: &raw_name[1..],
+            raw_args: args.as_ref().map(|(raw, _)| *raw),
+            args: args.map(|(_, args)| args),
+        },
     )(i)
 }
 
+/// Control characters can't appear in a URI, so a line of them is garbage
+/// rather than a segment
 fn uri(i: &str) -> IResult<&str, &str> {
-    preceded(not(char('#')), terminated(is_not(WHITESPACE), line_ending))(i)
+    let chars = verify(non_whitespace1, |s: &str| {
+        !s.bytes().any(|b| b.is_ascii_control())
+    });
+    preceded(not(char('#')), terminated(chars, line_end))(i)
 }
 
-fn playlist_line(i: &str) -> IResult<&str, Option<Line>> {
+pub fn playlist_line(i: &str) -> IResult<&str, Option<Line<'_>>> {
     alt((
         map(line_ending, |_| None),
         map(playlist_tag, Some),
-        map(comment, |_| None),
+        map(comment, |c| Some(Line::Comment(c))),
         map(uri, |u| Some(Line::Uri(u))),
     ))(i)
 }
 
-pub fn all_tags(i: &str) -> IResult<&str, Vec<Line>> {
-    fold_many1(playlist_line, Vec::new, |mut acc, line| {
-        if let Some(line) = line {
-            acc.push(line);
+/// Upper bound on the number of lines in a single playlist. Even day-long DVR
+/// windows stay well below this; anything larger is rejected outright rather
+/// than being allowed to exhaust memory.
+pub const MAX_LINES: usize = 1 << 20;
+
+pub fn all_tags(mut i: &str) -> IResult<&str, Vec<Line<'_>>> {
+    let mut lines = Vec::new();
+    let mut count = 0;
+
+    loop {
+        match playlist_line(i) {
+            Ok((rest, line)) => {
+                // Every alternative consumes at least a line ending, but guard
+                // against looping forever should that ever change
+                if rest.len() == i.len() {
+                    return Err(nom::Err::Error(Error::new(i, ErrorKind::Many1)));
+                }
+
+                count += 1;
+                if count > MAX_LINES {
+                    return Err(nom::Err::Failure(Error::new(i, ErrorKind::TooLarge)));
+                }
+
+                if let Some(line) = line {
+                    lines.push(line);
+                }
+                i = rest;
+            }
+            // Stopping anywhere but the end of the input would silently drop
+            // the rest of the playlist, so a bad line fails the whole parse
+            Err(nom::Err::Error(_)) if count > 0 && i.is_empty() => return Ok((i, lines)),
+            Err(e) => return Err(e),
         }
-        acc
-    })(i)
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    #[test]
+    fn caps_the_number_of_lines() {
+        let at_cap = "\n".repeat(MAX_LINES);
+        let (rest, lines) = all_tags(&at_cap).unwrap();
+        assert!(rest.is_empty() && lines.is_empty());
+
+        let over_cap = "a.ts\n".repeat(MAX_LINES + 1);
+        assert!(matches!(
+            all_tags(&over_cap),
+            Err(nom::Err::Failure(Error {
+                code: ErrorKind::TooLarge,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn fails_on_a_bad_line_after_good_ones() {
+        let input = "#EXTM3U\na.ts\nbad line here\nb.ts\n#EXT-X-ENDLIST\n";
+        match all_tags(input) {
+            Err(nom::Err::Error(e)) => {
+                let at = input.len() - e.input.len();
+                assert_eq!(2, input[..at].matches('\n').count());
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
     #[test]
     fn parses_header_tag() {
         let input = "#EXTM3U";
@@ -163,7 +281,7 @@ mod test {
     #[test]
     fn parses_comment() {
         let input = "# EXTM3U\r\n";
-        assert_eq!(Ok(("", ())), comment(input));
+        assert_eq!(Ok(("", " EXTM3U")), comment(input));
     }
 
     #[test]
@@ -175,11 +293,20 @@ mod test {
     #[test]
     fn parses_duration_name() {
         // This is a special case for EXTINF, which has an unusal arg format of <float>,[name]
-        assert_eq!(Ok(("", 12.345)), duration_name("12.345,"));
-        assert_eq!(Ok(("", 12.345)), duration_name("12.345,SegmentName"));
-        assert_eq!(Ok(("", 12.345)), duration_name("12.345,The rain in Spain"));
+        assert_eq!(Ok(("", (12.345, ""))), duration_name("12.345,"));
+        assert_eq!(
+            Ok(("", (12.345, "SegmentName"))),
+            duration_name("12.345,SegmentName")
+        );
+        assert_eq!(
+            Ok(("", (12.345, "The rain in Spain"))),
+            duration_name("12.345,The rain in Spain")
+        );
+        assert_eq!(Ok(("", (6.0, ""))), duration_name("6,"));
+        assert_eq!(Ok(("", (10.0, "Title"))), duration_name("10,Title"));
         // Trailing comma is required
         assert!(duration_name("12.345").is_err());
+        assert!(duration_name("6").is_err());
     }
 
     #[test]
@@ -194,6 +321,25 @@ mod test {
         assert!(integer("").is_err());
     }
 
+    #[test]
+    fn parses_signed_numbers() {
+        assert_eq!(Ok(("", -12)), signed_integer("-12"));
+        assert_eq!(Ok(("", 0)), signed_integer("-0"));
+        assert!(signed_integer("12").is_err());
+        assert!(signed_integer("-9223372036854775809").is_err());
+
+        let (_, attrs) = attrs("TIME-OFFSET=-12.5,OFFSET=-3,PRECISE=YES").unwrap();
+        assert!(matches!(attrs.get("TIME-OFFSET"), Some(AttributeValue::Float(f)) if *f == -12.5));
+        assert!(matches!(
+            attrs.get("OFFSET"),
+            Some(AttributeValue::SignedInteger(-3))
+        ));
+        assert!(matches!(
+            tag_args("-5\n"),
+            Ok((_, TagArgs::SignedInteger(-5)))
+        ));
+    }
+
     #[test]
     fn parses_float() {
         assert_eq!(Ok(("", 0.42)), float(".42"));
@@ -233,4 +379,94 @@ mod test {
             }
         ));
     }
+
+    #[test]
+    fn reads_typed_attributes() {
+        let (_, attrs) =
+            attrs(r#"METHOD=AES-128,URI="key.bin",IV=0x0102,DEFAULT=YES,BANDWIDTH=1280000"#)
+                .unwrap();
+
+        assert_eq!(
+            Ok("key.bin"),
+            attrs.get_str("URI").map_err(|e| e.to_string())
+        );
+        assert_eq!(
+            Ok(vec![1, 2]),
+            attrs.get_hex("IV").map_err(|e| e.to_string())
+        );
+        assert_eq!(
+            Ok(true),
+            attrs.get_bool("DEFAULT").map_err(|e| e.to_string())
+        );
+        assert_eq!(
+            Ok(1280000),
+            attrs.get_u64("BANDWIDTH").map_err(|e| e.to_string())
+        );
+
+        let err = attrs.get_u64("METHOD").unwrap_err();
+        assert_eq!("METHOD", err.name);
+        assert_eq!(Some("AES-128".to_owned()), err.found);
+
+        let err = attrs.get_str("KEYFORMAT").unwrap_err();
+        assert_eq!(None, err.found);
+    }
+
+    #[test]
+    fn reads_enums_resolutions_and_numbers() {
+        use crate::manifest::{EncryptionMethod, Resolution};
+
+        let (_, attrs) = attrs(
+            "METHOD=SAMPLE-AES,RESOLUTION=1280x720,FRAME-RATE=30,TIME-OFFSET=-2.5,MEDIA=VIDEO",
+        )
+        .unwrap();
+
+        assert!(matches!(
+            attrs.get_enum("METHOD"),
+            Ok(EncryptionMethod::SampleAes)
+        ));
+        assert!(matches!(
+            attrs.get_resolution("RESOLUTION"),
+            Ok(Resolution {
+                width: 1280,
+                height: 720
+            })
+        ));
+        assert_eq!(30.0, attrs.get_f64("FRAME-RATE").unwrap());
+        assert_eq!(-2.5, attrs.get_f64("TIME-OFFSET").unwrap());
+        assert_eq!(30, attrs.get_i64("FRAME-RATE").unwrap());
+
+        let err = attrs.get_enum::<EncryptionMethod>("MEDIA").unwrap_err();
+        assert_eq!(
+            ("MEDIA", Some("VIDEO")),
+            (err.name.as_str(), err.found.as_deref())
+        );
+        assert!(attrs.get_resolution("FRAME-RATE").is_err());
+    }
+
+    #[test]
+    fn tells_resolutions_from_hex_sequences() {
+        let (_, attrs) =
+            attrs("RESOLUTION=0x0,SIZE=640x360,IV=0x000102030405060708090A0B0C0D0E0F,ID=0x0")
+                .unwrap();
+
+        assert!(matches!(
+            attrs.get("RESOLUTION"),
+            Some(AttributeValue::Resolution {
+                width: 0,
+                height: 0
+            })
+        ));
+        assert!(matches!(
+            attrs.get("SIZE"),
+            Some(AttributeValue::Resolution {
+                width: 640,
+                height: 360
+            })
+        ));
+        assert_eq!(
+            Ok((0..16).collect::<Vec<u8>>()),
+            attrs.get_hex("IV").map_err(|e| e.to_string())
+        );
+        assert!(matches!(attrs.get("ID"), Some(AttributeValue::Hex(_))));
+    }
 }
@@ -0,0 +1,963 @@
+use crate::ads::AdMarker;
+use crate::error::{Error, Result};
+use crate::manifest::{
+    ByteRange, ContentSteering, DateRange, DateTime, EncryptionMethod, IFrameStreamInfAttributes,
+    KeyAttributes, Line, MapAttributes, MediaAttributes, MediaType, PartAttributes, PlaylistType,
+    PreloadHint, RenditionReport, ServerControl, SkipAttributes, StartAttributes,
+    StreamInfAttributes, Tag,
+};
+use crate::validate::{check_master_playlist, check_media_playlist, Problem};
+use serde::de::{Deserialize, Deserializer, Error as _};
+
+/// Either kind of playlist, depending on what the input turned out to be
+#[derive(Debug)]
+pub enum Playlist {
+    Master(MasterPlaylist),
+    Media(MediaPlaylist),
+}
+
+impl Playlist {
+    /// Parse a playlist, telling master and media playlists apart by their
+    /// tags
+    pub fn parse(input: &str) -> Result<Self> {
+        crate::from_str(input)
+    }
+
+    fn from_lines(lines: Vec<Line>) -> Result<Self> {
+        let is_master = lines.iter().any(|line| {
+            matches!(
+                line,
+                Line::Tag(Tag::StreamInf(_) | Tag::IFrameStreamInf(_) | Tag::Media(_))
+            )
+        });
+
+        if is_master {
+            Ok(Playlist::Master(MasterPlaylist::from_lines(lines)))
+        } else {
+            MediaPlaylist::from_lines(lines).map(Playlist::Media)
+        }
+    }
+
+    /// Check the playlist against the rules of RFC 8216
+    pub fn validate(&self) -> Vec<Problem> {
+        match self {
+            Playlist::Master(playlist) => playlist.validate(),
+            Playlist::Media(playlist) => playlist.validate(),
+        }
+    }
+}
+
+/// A playlist of variant streams and the renditions they can be paired with
+#[derive(Debug, Default)]
+pub struct MasterPlaylist {
+    pub version: Option<u64>,
+    pub independent_segments: bool,
+    pub variants: Vec<Variant>,
+    /// Alternative audio, video, subtitle and closed caption renditions,
+    /// from EXT-X-MEDIA
+    pub renditions: Vec<MediaAttributes>,
+    pub iframe_variants: Vec<IFrameStreamInfAttributes>,
+    pub content_steering: Option<ContentSteering>,
+    pub start: Option<StartAttributes>,
+}
+
+/// An EXT-X-STREAM-INF tag and the media playlist URI that follows it
+#[derive(Debug)]
+pub struct Variant {
+    pub attrs: StreamInfAttributes,
+    pub uri: String,
+}
+
+impl Variant {
+    /// The EXT-X-MEDIA renditions of the variant's AUDIO group
+    pub fn audio_renditions<'a>(
+        &'a self,
+        master: &'a MasterPlaylist,
+    ) -> impl Iterator<Item = &'a MediaAttributes> {
+        master.renditions_of(MediaType::Audio, self.attrs.audio.as_deref())
+    }
+
+    /// The EXT-X-MEDIA renditions of the variant's VIDEO group
+    pub fn video_renditions<'a>(
+        &'a self,
+        master: &'a MasterPlaylist,
+    ) -> impl Iterator<Item = &'a MediaAttributes> {
+        master.renditions_of(MediaType::Video, self.attrs.video.as_deref())
+    }
+
+    /// The EXT-X-MEDIA renditions of the variant's SUBTITLES group
+    pub fn subtitle_renditions<'a>(
+        &'a self,
+        master: &'a MasterPlaylist,
+    ) -> impl Iterator<Item = &'a MediaAttributes> {
+        master.renditions_of(MediaType::Subtitles, self.attrs.subtitles.as_deref())
+    }
+
+    /// The EXT-X-MEDIA renditions of the variant's CLOSED-CAPTIONS group.
+    /// Empty for CLOSED-CAPTIONS=NONE.
+    pub fn closed_caption_renditions<'a>(
+        &'a self,
+        master: &'a MasterPlaylist,
+    ) -> impl Iterator<Item = &'a MediaAttributes> {
+        let group = self
+            .attrs
+            .closed_captions
+            .as_deref()
+            .filter(|group| *group != "NONE");
+        master.renditions_of(MediaType::ClosedCaptions, group)
+    }
+}
+
+impl MasterPlaylist {
+    pub fn parse(input: &str) -> Result<Self> {
+        crate::from_str(input)
+    }
+
+    /// Check the playlist against the rules of RFC 8216
+    pub fn validate(&self) -> Vec<Problem> {
+        check_master_playlist(self)
+    }
+
+    fn from_lines(lines: Vec<Line>) -> Self {
+        let mut playlist = Self::default();
+        let mut stream_inf = None;
+
+        for line in lines {
+            match line {
+                Line::Tag(Tag::Version(v)) => playlist.version = Some(v),
+                Line::Tag(Tag::IndependentSegments) => playlist.independent_segments = true,
+                Line::Tag(Tag::Media(attrs)) => playlist.renditions.push(attrs),
+                Line::Tag(Tag::IFrameStreamInf(attrs)) => playlist.iframe_variants.push(attrs),
+                Line::Tag(Tag::StreamInf(attrs)) => stream_inf = Some(attrs),
+                Line::Tag(Tag::ContentSteering(steering)) => {
+                    playlist.content_steering = Some(steering)
+                }
+                Line::Tag(Tag::Start(start)) => playlist.start = Some(start),
+                Line::Uri(uri) => {
+                    // A URI without a preceding EXT-X-STREAM-INF isn't a
+                    // variant
+                    if let Some(attrs) = stream_inf.take() {
+                        playlist.variants.push(Variant { attrs, uri });
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        playlist
+    }
+
+    /// The distinct pathways variants are offered on, in playlist order.
+    /// Variants without a PATHWAY-ID are on the default pathway, `.`.
+    pub fn pathways(&self) -> Vec<&str> {
+        let mut pathways = Vec::new();
+        for variant in &self.variants {
+            let pathway = variant.attrs.pathway_id.as_deref().unwrap_or(".");
+            if !pathways.contains(&pathway) {
+                pathways.push(pathway);
+            }
+        }
+        pathways
+    }
+
+    /// The variants offered on a pathway
+    pub fn pathway<'a>(&'a self, pathway_id: &'a str) -> impl Iterator<Item = &'a Variant> {
+        self.variants
+            .iter()
+            .filter(move |variant| variant.attrs.pathway_id.as_deref().unwrap_or(".") == pathway_id)
+    }
+
+    /// The renditions of one type in a group, or none if there's no group
+    fn renditions_of<'a>(
+        &'a self,
+        media_type: MediaType,
+        group_id: Option<&'a str>,
+    ) -> impl Iterator<Item = &'a MediaAttributes> {
+        self.renditions.iter().filter(move |rendition| {
+            rendition.media_type == media_type && Some(rendition.group_id.as_str()) == group_id
+        })
+    }
+
+    /// The renditions in the group a variant refers to, e.g. its AUDIO group
+    pub fn group<'a>(&'a self, group_id: &'a str) -> impl Iterator<Item = &'a MediaAttributes> {
+        self.renditions
+            .iter()
+            .filter(move |rendition| rendition.group_id == group_id)
+    }
+}
+
+/// A playlist of media segments
+#[derive(Debug)]
+pub struct MediaPlaylist {
+    pub version: Option<u64>,
+    pub target_duration: u64,
+    /// Media sequence number of the first segment
+    pub media_sequence: u64,
+    /// Discontinuity sequence number of the first segment
+    pub discontinuity_sequence: u64,
+    pub playlist_type: Option<PlaylistType>,
+    pub independent_segments: bool,
+    pub iframes_only: bool,
+    /// Whether the playlist has an EXT-X-ENDLIST tag, so no more segments
+    /// will be added
+    pub ended: bool,
+    pub segments: Vec<Segment>,
+    /// Segments left out of a delta update by EXT-X-SKIP. These come before
+    /// `segments`, which are numbered after them.
+    pub skipped_segments: u64,
+    /// IDs of dateranges a delta update says were removed
+    pub removed_dateranges: Vec<String>,
+    pub server_control: ServerControl,
+    /// EXT-X-PART-INF's PART-TARGET, for Low-Latency HLS playlists
+    pub part_target: Option<f64>,
+    /// Parts of the segment still being produced, after the last complete
+    /// segment
+    pub trailing_parts: Vec<PartAttributes>,
+    /// Parts and init segments that will appear in the next update
+    pub preload_hints: Vec<PreloadHint>,
+    /// Where the other renditions of the content are up to
+    pub rendition_reports: Vec<RenditionReport>,
+    /// EXT-X-DATERANGE tags, in playlist order
+    pub dateranges: Vec<DateRange>,
+    /// Where playback should begin, if not at the default position
+    pub start: Option<StartAttributes>,
+}
+
+/// A media segment along with everything needed to fetch and decode it
+#[derive(Clone, Debug)]
+pub struct Segment {
+    pub uri: String,
+    /// Media sequence number
+    pub sequence: u64,
+    /// How many discontinuities came before the segment, counting from the
+    /// playlist's EXT-X-DISCONTINUITY-SEQUENCE
+    pub discontinuity_sequence: u64,
+    pub duration: f64,
+    pub title: Option<String>,
+    pub byterange: Option<ByteRange>,
+    pub discontinuity: bool,
+    /// Marked with EXT-X-GAP: the segment has no media and shouldn't be
+    /// requested
+    pub gap: bool,
+    /// The segment's own EXT-X-PROGRAM-DATE-TIME tag
+    pub program_date_time: Option<DateTime>,
+    /// When the segment starts: its own EXT-X-PROGRAM-DATE-TIME, or the
+    /// last one before it plus the durations of the segments in between
+    #[cfg(feature = "chrono")]
+    pub date_time: Option<DateTime>,
+    /// Every key in effect, one per KEYFORMAT. Empty if the segment isn't
+    /// encrypted.
+    pub keys: Vec<KeyAttributes>,
+    /// The EXT-X-MAP init segment in effect, if any
+    pub map: Option<MapAttributes>,
+    /// Ad break markers that come before the segment
+    pub ad_markers: Vec<AdMarker>,
+    /// The partial segments the segment is made up of, for segments recent
+    /// enough to still be listed as parts
+    pub parts: Vec<PartAttributes>,
+}
+
+impl MediaPlaylist {
+    pub fn parse(input: &str) -> Result<Self> {
+        crate::from_str(input)
+    }
+
+    /// Check the playlist against the rules of RFC 8216
+    pub fn validate(&self) -> Vec<Problem> {
+        check_media_playlist(self)
+    }
+
+    fn from_lines(lines: Vec<Line>) -> Result<Self> {
+        let mut version = None;
+        let mut target_duration = None;
+        let mut media_sequence = 0;
+        let mut discontinuity_sequence = 0;
+        let mut playlist_type = None;
+        let mut independent_segments = false;
+        let mut iframes_only = false;
+        let mut ended = false;
+        let mut segments = Vec::new();
+        let mut dateranges = Vec::new();
+        let mut part_target = None;
+        let mut server_control = ServerControl::default();
+        let mut skip: Option<SkipAttributes> = None;
+        let mut parts = Vec::new();
+        let mut preload_hints = Vec::new();
+        let mut rendition_reports = Vec::new();
+        let mut start = None;
+
+        let mut duration = None;
+        let mut byterange = None;
+        let mut discontinuity = false;
+        let mut gap = false;
+        let mut ad_markers = Vec::new();
+        let mut program_date_time = None;
+        let mut keys: Vec<KeyAttributes> = Vec::new();
+        let mut map = None;
+
+        for line in lines {
+            match line {
+                Line::Tag(Tag::Version(v)) => version = Some(v),
+                Line::Tag(Tag::Targetduration(t)) => target_duration = Some(t),
+                Line::Tag(Tag::MediaSequence(s)) => media_sequence = s,
+                Line::Tag(Tag::DiscontinuitySequence(s)) => discontinuity_sequence = s,
+                Line::Tag(Tag::PlaylistType(t)) => playlist_type = Some(t),
+                Line::Tag(Tag::IndependentSegments) => independent_segments = true,
+                Line::Tag(Tag::IFramesOnly) => iframes_only = true,
+                Line::Tag(Tag::Endlist) => ended = true,
+                Line::Tag(Tag::Inf(d, title)) => duration = Some((d, title)),
+                Line::Tag(Tag::Byterange(r)) => byterange = Some(r),
+                Line::Tag(Tag::Discontinuity) => discontinuity = true,
+                Line::Tag(Tag::Gap) => gap = true,
+                Line::Tag(Tag::CueOut(args)) => ad_markers.push(AdMarker::CueOut {
+                    duration: args.and_then(|args| args.duration),
+                }),
+                Line::Tag(Tag::CueOutCont(args)) => {
+                    let args = args.unwrap_or_default();
+                    ad_markers.push(AdMarker::CueOutCont {
+                        elapsed: args.elapsed,
+                        duration: args.duration,
+                    });
+                }
+                Line::Tag(Tag::CueIn) => ad_markers.push(AdMarker::CueIn),
+                Line::Tag(Tag::Scte35(attrs)) => ad_markers.push(AdMarker::Scte35(attrs)),
+                Line::Tag(Tag::ProgramDateTime(t)) => program_date_time = Some(t),
+                Line::Tag(Tag::Map(attrs)) => map = Some(attrs),
+                Line::Tag(Tag::Daterange(range)) => dateranges.push(*range),
+                Line::Tag(Tag::ServerControl(control)) => server_control = control,
+                Line::Tag(Tag::Skip(attrs)) => skip = Some(attrs),
+                Line::Tag(Tag::PartInf(attrs)) => part_target = Some(attrs.part_target),
+                Line::Tag(Tag::Part(attrs)) => parts.push(attrs),
+                Line::Tag(Tag::PreloadHint(hint)) => preload_hints.push(hint),
+                Line::Tag(Tag::RenditionReport(report)) => rendition_reports.push(report),
+                Line::Tag(Tag::Start(attrs)) => start = Some(attrs),
+                Line::Tag(Tag::Key(attrs)) => {
+                    // A key replaces any earlier key of the same format, while
+                    // METHOD=NONE turns encryption off altogether
+                    if attrs.method == EncryptionMethod::None {
+                        keys.clear();
+                    } else {
+                        keys.retain(|k| k.keyformat != attrs.keyformat);
+                        keys.push(attrs);
+                    }
+                }
+                Line::Uri(uri) => {
+                    let (duration, title) = duration
+                        .take()
+                        .ok_or_else(|| Error::Message(format!("{} has no EXTINF", uri)))?;
+                    let skipped = skip.as_ref().map_or(0, |skip| skip.skipped_segments);
+                    segments.push(Segment {
+                        uri,
+                        sequence: media_sequence + skipped + segments.len() as u64,
+                        discontinuity_sequence,
+                        duration,
+                        title,
+                        byterange: byterange.take(),
+                        discontinuity: std::mem::take(&mut discontinuity),
+                        gap: std::mem::take(&mut gap),
+                        ad_markers: std::mem::take(&mut ad_markers),
+                        program_date_time: program_date_time.take(),
+                        #[cfg(feature = "chrono")]
+                        date_time: None,
+                        keys: keys.clone(),
+                        map: map.clone(),
+                        parts: std::mem::take(&mut parts),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let target_duration = target_duration
+            .ok_or_else(|| Error::Message("missing EXT-X-TARGETDURATION".to_owned()))?;
+        // Discontinuities among skipped segments aren't known until the
+        // delta is applied
+        number_segments(&mut segments, media_sequence, discontinuity_sequence);
+
+        let skipped_segments = skip.as_ref().map_or(0, |skip| skip.skipped_segments);
+        let removed_dateranges = skip
+            .and_then(|skip| skip.recently_removed_dateranges)
+            .map(|ids| ids.split('\t').map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        Ok(Self {
+            version,
+            target_duration,
+            media_sequence,
+            discontinuity_sequence,
+            playlist_type,
+            independent_segments,
+            iframes_only,
+            ended,
+            segments,
+            skipped_segments,
+            removed_dateranges,
+            server_control,
+            part_target,
+            trailing_parts: parts,
+            preload_hints,
+            rendition_reports,
+            dateranges,
+            start,
+        })
+    }
+
+    /// Rebuild the full playlist from a delta update requested with
+    /// `_HLS_skip=YES`, taking the segments it skipped from `previous`
+    pub fn apply_delta(previous: &MediaPlaylist, mut delta: MediaPlaylist) -> Result<Self> {
+        let first = delta.media_sequence;
+        let skipped = first..first + delta.skipped_segments;
+        let mut segments: Vec<Segment> = previous
+            .segments
+            .iter()
+            .filter(|segment| skipped.contains(&segment.sequence))
+            .cloned()
+            .collect();
+
+        if segments.len() as u64 != delta.skipped_segments {
+            return Err(Error::Message(format!(
+                "previous playlist doesn't have skipped segments {} to {}",
+                skipped.start,
+                skipped.end.saturating_sub(1)
+            )));
+        }
+
+        // Dateranges may be skipped too, unless they were removed or the
+        // delta lists them again
+        let mut dateranges: Vec<DateRange> = previous
+            .dateranges
+            .iter()
+            .filter(|range| !delta.removed_dateranges.contains(&range.id))
+            .filter(|range| delta.dateranges.iter().all(|r| r.id != range.id))
+            .cloned()
+            .collect();
+
+        segments.append(&mut delta.segments);
+        dateranges.append(&mut delta.dateranges);
+        if let Some(first) = segments.first() {
+            let (sequence, discontinuity_sequence) = (first.sequence, first.discontinuity_sequence);
+            number_segments(&mut segments, sequence, discontinuity_sequence);
+        }
+
+        Ok(Self {
+            segments,
+            skipped_segments: 0,
+            removed_dateranges: Vec::new(),
+            dateranges,
+            ..delta
+        })
+    }
+
+    /// Seconds from the end of the playlist that playback should start,
+    /// defaulting to three target durations
+    pub fn hold_back(&self) -> f64 {
+        let default = 3.0 * self.target_duration as f64;
+        self.server_control.hold_back.unwrap_or(default)
+    }
+
+    /// Whether the origin will hold a reload request until a given segment
+    /// or part is available
+    pub fn can_block_reload(&self) -> bool {
+        self.server_control.can_block_reload.unwrap_or(false)
+    }
+
+    /// Total duration of the segments, in seconds
+    pub fn total_duration(&self) -> f64 {
+        self.segments.iter().map(|segment| segment.duration).sum()
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    /// Duration of the longest segment, in seconds
+    pub fn max_segment_duration(&self) -> Option<f64> {
+        self.segments
+            .iter()
+            .map(|segment| segment.duration)
+            .reduce(f64::max)
+    }
+
+    /// Whether more segments may still be added, i.e. there is no
+    /// EXT-X-ENDLIST
+    pub fn is_live(&self) -> bool {
+        !self.ended
+    }
+}
+
+/// Fill in the discontinuity sequence numbers of segments, and with the
+/// `chrono` feature their extrapolated date and time
+fn number_segments(segments: &mut [Segment], first_sequence: u64, discontinuity_sequence: u64) {
+    let mut discontinuity_sequence = discontinuity_sequence;
+    #[cfg(feature = "chrono")]
+    let mut date_time: Option<DateTime> = None;
+
+    for segment in segments {
+        // A discontinuity on the first segment is already counted by
+        // EXT-X-DISCONTINUITY-SEQUENCE
+        if segment.discontinuity && segment.sequence > first_sequence {
+            discontinuity_sequence += 1;
+        }
+        segment.discontinuity_sequence = discontinuity_sequence;
+
+        #[cfg(feature = "chrono")]
+        {
+            date_time = segment.program_date_time.or(date_time);
+            segment.date_time = date_time;
+            let duration = chrono::Duration::microseconds((segment.duration * 1e6) as i64);
+            date_time = date_time.map(|date_time| date_time + duration);
+        }
+    }
+}
+
+// The typed playlists are built from the lines of a manifest, so they can
+// be deserialized directly with `from_str` and friends
+
+impl<'de> Deserialize<'de> for Playlist {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Self::from_lines(Vec::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for MasterPlaylist {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::from_lines)
+    }
+}
+
+impl<'de> Deserialize<'de> for MediaPlaylist {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Self::from_lines(Vec::deserialize(deserializer)?).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::manifest::{ClientAttribute, PreloadHintType};
+
+    #[test]
+    fn deserializes_typed_playlists_directly() {
+        let input = "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:6\n#EXT-X-MEDIA-SEQUENCE:7\n#EXTINF:6.0,\na.ts\n";
+        let media: MediaPlaylist = crate::from_str(input).unwrap();
+        assert_eq!(
+            (media.version, media.target_duration, media.media_sequence),
+            (Some(3), 6, 7)
+        );
+        assert_eq!(media.segments[0].uri, "a.ts");
+
+        let missing_target = "#EXTM3U\n#EXTINF:6.0,\na.ts\n";
+        assert!(crate::from_str::<MediaPlaylist>(missing_target).is_err());
+    }
+
+    #[test]
+    fn builds_typed_playlists() {
+        let media = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXT-X-MEDIA-SEQUENCE:100\n",
+            "#EXT-X-MAP:URI=\"init.mp4\"\n",
+            "#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n",
+            "#EXTINF:6.0,First\n",
+            "a.m4s\n",
+            "#EXT-X-KEY:METHOD=NONE\n",
+            "#EXT-X-DISCONTINUITY\n",
+            "#EXT-X-GAP\n",
+            "#EXT-X-BYTERANGE:2048\n",
+            "#EXTINF:4.5,\n",
+            "b.m4s\n",
+            "#EXT-X-ENDLIST\n",
+        );
+        let media = match Playlist::parse(media).unwrap() {
+            Playlist::Media(media) => media,
+            other => panic!("expected a media playlist, got {:?}", other),
+        };
+
+        assert_eq!(media.target_duration, 6);
+        assert!(media.ended);
+        assert_eq!(media.total_duration(), 10.5);
+        assert_eq!(media.segment_count(), 2);
+        assert_eq!(media.max_segment_duration(), Some(6.0));
+        assert!(!media.is_live());
+        let [a, b] = &media.segments[..] else {
+            panic!("expected two segments");
+        };
+        assert_eq!(
+            (a.sequence, a.title.as_deref(), a.keys.len()),
+            (100, Some("First"), 1)
+        );
+        assert_eq!((b.sequence, b.discontinuity, b.keys.len()), (101, true, 0));
+        assert!(!a.gap && b.gap);
+        assert_eq!(
+            b.byterange,
+            Some(ByteRange {
+                length: 2048,
+                offset: None
+            })
+        );
+        assert_eq!(b.map.as_ref().unwrap().uri, "init.mp4");
+
+        let master = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",URI=\"en.m3u8\"\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"aac\"\n",
+            "low.m3u8\n",
+            "#EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=86000,URI=\"iframes.m3u8\"\n",
+        );
+        let master = MasterPlaylist::parse(master).unwrap();
+
+        assert_eq!(master.variants.len(), 1);
+        assert_eq!(master.variants[0].uri, "low.m3u8");
+        let audio = master.variants[0].attrs.audio.as_deref().unwrap();
+        assert_eq!(master.group(audio).count(), 1);
+        assert_eq!(master.iframe_variants[0].uri, "iframes.m3u8");
+    }
+
+    #[test]
+    fn rejects_empty_byte_ranges() {
+        assert_eq!(
+            "10@0".parse(),
+            Ok(ByteRange {
+                length: 10,
+                offset: Some(0)
+            })
+        );
+        assert!("0@10".parse::<ByteRange>().is_err());
+        assert!("0".parse::<ByteRange>().is_err());
+
+        let media = "#EXTM3U\n#EXT-X-TARGETDURATION:6\n#EXT-X-BYTERANGE:0@10\n#EXTINF:6.0,\na.ts\n";
+        assert!(Playlist::parse(media).is_err());
+    }
+
+    #[test]
+    fn reads_real_world_master_playlists() {
+        // Abridged from Apple's advanced HEVC/H.264 example stream
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-VERSION:6\n",
+            "#EXT-X-INDEPENDENT-SEGMENTS\n",
+            "\n",
+            "#EXT-X-STREAM-INF:AVERAGE-BANDWIDTH=2168183,BANDWIDTH=2177116,CODECS=\"avc1.640020,mp4a.40.2\",RESOLUTION=960x540,FRAME-RATE=60.000,CLOSED-CAPTIONS=\"cc1\",AUDIO=\"aud1\",SUBTITLES=\"sub1\"\n",
+            "v5/prog_index.m3u8\n",
+            "#EXT-X-STREAM-INF:AVERAGE-BANDWIDTH=7968416,BANDWIDTH=8001098,CODECS=\"avc1.64002a,mp4a.40.2\",RESOLUTION=1920x1080,FRAME-RATE=60.000,CLOSED-CAPTIONS=\"cc1\",AUDIO=\"aud1\",SUBTITLES=\"sub1\"\n",
+            "v9/prog_index.m3u8\n",
+            "\n",
+            "#EXT-X-I-FRAME-STREAM-INF:AVERAGE-BANDWIDTH=186522,BANDWIDTH=187350,CODECS=\"avc1.64002a\",RESOLUTION=1920x1080,URI=\"v9/iframe_index.m3u8\"\n",
+            "\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud1\",LANGUAGE=\"en\",NAME=\"English\",AUTOSELECT=YES,DEFAULT=YES,CHANNELS=\"2\",URI=\"a1/prog_index.m3u8\"\n",
+            "#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc1\",LANGUAGE=\"en\",NAME=\"English\",AUTOSELECT=YES,DEFAULT=YES,INSTREAM-ID=\"CC1\"\n",
+            "#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"sub1\",NAME=\"English\",LANGUAGE=\"en\",DEFAULT=YES,AUTOSELECT=YES,FORCED=NO,URI=\"s1/en/prog_index.m3u8\"\n",
+        );
+        let master = match Playlist::parse(input).unwrap() {
+            Playlist::Master(master) => master,
+            other => panic!("expected a master playlist, got {:?}", other),
+        };
+
+        assert_eq!(master.version, Some(6));
+        assert!(master.independent_segments);
+        let uris: Vec<_> = master.variants.iter().map(|v| v.uri.as_str()).collect();
+        assert_eq!(uris, ["v5/prog_index.m3u8", "v9/prog_index.m3u8"]);
+        let hd = &master.variants[1].attrs;
+        assert_eq!(hd.resolution.map(|r| r.height), Some(1080));
+        assert_eq!(hd.frame_rate, Some(60.0));
+        assert_eq!(master.group("cc1").count(), 1);
+        assert_eq!(master.group("sub1").next().unwrap().forced, Some(false));
+        assert_eq!(master.iframe_variants.len(), 1);
+    }
+
+    #[test]
+    fn collects_dateranges() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXT-X-DATERANGE:ID=\"splice-6FFFFFF0\",START-DATE=\"2014-03-05T11:15:00Z\",PLANNED-DURATION=59.993,SCTE35-OUT=0xFC002F0000000000FF000014056FFFFFF000E011622DCAFF000052636200000000000A0008029896F50000008700000000\n",
+            "#EXTINF:6.0,\n",
+            "a.ts\n",
+            "#EXT-X-DATERANGE:ID=\"ad\",CLASS=\"com.example.ad\",START-DATE=\"2014-03-05T11:15:06Z\",DURATION=30,END-ON-NEXT=YES,X-AD-ID=\"1234\",X-TRACKING=0xABCD\n",
+        );
+        let media = MediaPlaylist::parse(input).unwrap();
+
+        let [splice, ad] = &media.dateranges[..] else {
+            panic!("expected two dateranges");
+        };
+        assert_eq!(splice.planned_duration, Some(59.993));
+        assert_eq!(splice.scte35_out.as_ref().map(|b| b[0]), Some(0xfc));
+        assert_eq!(ad.class.as_deref(), Some("com.example.ad"));
+        assert_eq!((ad.duration, ad.end_on_next), (Some(30.0), Some(true)));
+        assert_eq!(
+            ad.client_attributes.get("X-AD-ID"),
+            Some(&ClientAttribute::String("1234".to_owned()))
+        );
+        assert_eq!(
+            ad.client_attributes.get("X-TRACKING"),
+            Some(&ClientAttribute::Hex(vec![0xab, 0xcd]))
+        );
+
+        let line = crate::to_string(&Line::Tag(Tag::Daterange(Box::new(ad.clone())))).unwrap();
+        assert!(line.starts_with("#EXT-X-DATERANGE:ID=\"ad\",CLASS=\"com.example.ad\""));
+        assert!(line.ends_with("X-AD-ID=\"1234\",X-TRACKING=0xABCD"));
+    }
+
+    #[test]
+    fn groups_parts_by_segment() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:4\n",
+            "#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=3.0,CAN-SKIP-UNTIL=24.0\n",
+            "#EXT-X-PART-INF:PART-TARGET=1.002\n",
+            "#EXT-X-MEDIA-SEQUENCE:266\n",
+            "#EXT-X-PART:DURATION=2.0,URI=\"filePart266.0.mp4\",INDEPENDENT=YES\n",
+            "#EXT-X-PART:DURATION=2.0,URI=\"filePart266.1.mp4\"\n",
+            "#EXTINF:4.0,\n",
+            "fileSequence266.mp4\n",
+            "#EXT-X-PART:DURATION=1.0,URI=\"filePart267.0.mp4\",BYTERANGE=\"1000@0\",GAP=YES\n",
+            "#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"filePart267.1.mp4\",BYTERANGE-START=1000\n",
+            "#EXT-X-RENDITION-REPORT:URI=\"../1M/waitForMSN.php\",LAST-MSN=266,LAST-PART=1\n",
+        );
+        let media = MediaPlaylist::parse(input).unwrap();
+
+        assert_eq!(media.part_target, Some(1.002));
+        assert!(media.can_block_reload());
+        assert_eq!(media.server_control.part_hold_back, Some(3.0));
+        assert_eq!(media.server_control.can_skip_until, Some(24.0));
+        assert_eq!(media.hold_back(), 12.0);
+        let parts = &media.segments[0].parts;
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].independent, Some(true));
+        assert_eq!(parts[1].uri, "filePart266.1.mp4");
+        let trailing = &media.trailing_parts[0];
+        assert_eq!(
+            trailing.byterange,
+            Some(ByteRange {
+                length: 1000,
+                offset: Some(0)
+            })
+        );
+        assert_eq!(trailing.gap, Some(true));
+
+        let hint = &media.preload_hints[0];
+        assert_eq!(hint.hint_type, PreloadHintType::Part);
+        assert_eq!(
+            (hint.byterange_start, hint.byterange_length),
+            (Some(1000), None)
+        );
+        let report = &media.rendition_reports[0];
+        assert_eq!((report.last_msn, report.last_part), (Some(266), Some(1)));
+    }
+
+    #[test]
+    fn applies_delta_updates() {
+        let previous = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:4\n",
+            "#EXT-X-MEDIA-SEQUENCE:10\n",
+            "#EXT-X-DATERANGE:ID=\"old\",START-DATE=\"2024-01-01T00:00:00Z\"\n",
+            "#EXT-X-DATERANGE:ID=\"kept\",START-DATE=\"2024-01-01T00:00:04Z\"\n",
+            "#EXTINF:4.0,\n",
+            "10.ts\n",
+            "#EXTINF:4.0,\n",
+            "11.ts\n",
+            "#EXTINF:4.0,\n",
+            "12.ts\n",
+        );
+        let delta = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:4\n",
+            "#EXT-X-MEDIA-SEQUENCE:11\n",
+            "#EXT-X-SKIP:SKIPPED-SEGMENTS=1,RECENTLY-REMOVED-DATERANGES=\"old\"\n",
+            "#EXTINF:4.0,\n",
+            "12.ts\n",
+            "#EXTINF:4.0,\n",
+            "13.ts\n",
+        );
+        let previous = MediaPlaylist::parse(previous).unwrap();
+        let delta = MediaPlaylist::parse(delta).unwrap();
+        assert_eq!(delta.segments[0].sequence, 12);
+
+        let full = MediaPlaylist::apply_delta(&previous, delta).unwrap();
+        let segments: Vec<_> = full
+            .segments
+            .iter()
+            .map(|s| (s.sequence, s.uri.as_str()))
+            .collect();
+        assert_eq!(segments, [(11, "11.ts"), (12, "12.ts"), (13, "13.ts")]);
+        let ids: Vec<_> = full.dateranges.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["kept"]);
+
+        let stale = MediaPlaylist::parse(
+            "#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:20\n#EXT-X-SKIP:SKIPPED-SEGMENTS=2\n",
+        )
+        .unwrap();
+        assert!(MediaPlaylist::apply_delta(&full, stale).is_err());
+    }
+
+    #[test]
+    fn reads_content_steering_pathways() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-CONTENT-STEERING:SERVER-URI=\"/steering?video=00012\",PATHWAY-ID=\"CDN-A\"\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,PATHWAY-ID=\"CDN-A\"\n",
+            "https://a.example.com/low.m3u8\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,PATHWAY-ID=\"CDN-B\"\n",
+            "https://b.example.com/low.m3u8\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=2560000,PATHWAY-ID=\"CDN-A\"\n",
+            "https://a.example.com/high.m3u8\n",
+        );
+        let master = MasterPlaylist::parse(input).unwrap();
+
+        let steering = master.content_steering.as_ref().unwrap();
+        assert_eq!(steering.server_uri, "/steering?video=00012");
+        assert_eq!(steering.pathway_id.as_deref(), Some("CDN-A"));
+        assert_eq!(master.pathways(), ["CDN-A", "CDN-B"]);
+        assert_eq!(master.pathway("CDN-A").count(), 2);
+    }
+
+    #[test]
+    fn attaches_ad_markers_to_segments() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:10\n",
+            "#EXT-X-CUE-OUT:DURATION=30\n",
+            "#EXTINF:10.0,\n",
+            "ad1.ts\n",
+            "#EXT-X-CUE-OUT-CONT:ElapsedTime=10.0,Duration=30\n",
+            "#EXTINF:10.0,\n",
+            "ad2.ts\n",
+            "#EXT-X-CUE-OUT-CONT:20/30\n",
+            "#EXTINF:10.0,\n",
+            "ad3.ts\n",
+            "#EXT-X-CUE-IN\n",
+            "#EXT-X-CUE-OUT:15\n",
+            "#EXT-X-SCTE35:CUE=\"/DAlAAAAAAAAAP/wFAUAAAABf+/+\",ID=\"1\",DURATION=15.0\n",
+            "#EXTINF:10.0,\n",
+            "main.ts\n",
+        );
+        let media = MediaPlaylist::parse(input).unwrap();
+        let markers: Vec<_> = media.segments.iter().map(|s| &s.ad_markers[..]).collect();
+
+        assert_eq!(
+            markers[0],
+            [AdMarker::CueOut {
+                duration: Some(30.0)
+            }]
+        );
+        let cont = AdMarker::CueOutCont {
+            elapsed: Some(10.0),
+            duration: Some(30.0),
+        };
+        assert_eq!(markers[1], [cont]);
+        assert!(matches!(
+            markers[2],
+            [AdMarker::CueOutCont { elapsed: Some(e), .. }] if *e == 20.0
+        ));
+        assert!(matches!(
+            markers[3],
+            [AdMarker::CueIn, AdMarker::CueOut { duration: Some(d) }, AdMarker::Scte35(scte)]
+                if *d == 15.0 && scte.id.as_deref() == Some("1")
+        ));
+    }
+
+    #[test]
+    fn reads_negative_start_offsets() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXT-X-START:TIME-OFFSET=-12.5,PRECISE=YES\n",
+            "#EXTINF:6,\n",
+            "a.ts\n",
+        );
+        let media = MediaPlaylist::parse(input).unwrap();
+        let start = media.start.unwrap();
+        assert_eq!((start.time_offset, start.precise), (-12.5, Some(true)));
+
+        let input =
+            "#EXTM3U\n#EXT-X-START:TIME-OFFSET=-12\n#EXT-X-STREAM-INF:BANDWIDTH=1\na.m3u8\n";
+        let master = MasterPlaylist::parse(input).unwrap();
+        assert_eq!(master.start.unwrap().time_offset, -12.0);
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn parses_program_date_times() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXT-X-PROGRAM-DATE-TIME:2023-03-01T12:00:00.500+01:00\n",
+            "#EXTINF:6,\n",
+            "a.ts\n",
+        );
+        let media = MediaPlaylist::parse(input).unwrap();
+        let pdt = media.segments[0].program_date_time.unwrap();
+        assert_eq!(pdt.timestamp_millis(), 1677668400500);
+
+        let lines: Vec<Line> = crate::from_str(input).unwrap();
+        assert!(crate::to_string(&lines)
+            .unwrap()
+            .contains("#EXT-X-PROGRAM-DATE-TIME:2023-03-01T12:00:00.500+01:00\n"));
+
+        let invalid = input.replace("2023-03-01", "2023-13-01");
+        let error = MediaPlaylist::parse(&invalid).unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("invalid EXT-X-PROGRAM-DATE-TIME"));
+    }
+
+    #[test]
+    fn links_variants_to_their_renditions() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",URI=\"en.m3u8\"\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"French\",URI=\"fr.m3u8\"\n",
+            "#EXT-X-MEDIA:TYPE=SUBTITLES,GROUP-ID=\"aac\",NAME=\"Subs\",URI=\"subs.m3u8\"\n",
+            "#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"CC\",INSTREAM-ID=\"CC1\"\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,AUDIO=\"aac\",CLOSED-CAPTIONS=\"cc\"\n",
+            "low.m3u8\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=2560000,CLOSED-CAPTIONS=NONE\n",
+            "high.m3u8\n",
+        );
+        let master = MasterPlaylist::parse(input).unwrap();
+        let [low, high] = &master.variants[..] else {
+            panic!("expected two variants");
+        };
+
+        let audio: Vec<_> = low.audio_renditions(&master).map(|r| &r.name).collect();
+        assert_eq!(audio, ["English", "French"]);
+        assert_eq!(low.subtitle_renditions(&master).count(), 0);
+        assert_eq!(low.closed_caption_renditions(&master).count(), 1);
+        assert_eq!(high.audio_renditions(&master).count(), 0);
+        assert_eq!(high.closed_caption_renditions(&master).count(), 0);
+    }
+
+    #[test]
+    fn numbers_segments_across_discontinuities() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXT-X-MEDIA-SEQUENCE:10\n",
+            "#EXT-X-DISCONTINUITY-SEQUENCE:5\n",
+            "#EXT-X-DISCONTINUITY\n",
+            "#EXT-X-PROGRAM-DATE-TIME:2023-03-01T12:00:00Z\n",
+            "#EXTINF:6.0,\n",
+            "a.ts\n",
+            "#EXTINF:4.5,\n",
+            "b.ts\n",
+            "#EXT-X-DISCONTINUITY\n",
+            "#EXTINF:6.0,\n",
+            "c.ts\n",
+        );
+        let media = MediaPlaylist::parse(input).unwrap();
+
+        let numbers: Vec<_> = media
+            .segments
+            .iter()
+            .map(|s| (s.sequence, s.discontinuity_sequence))
+            .collect();
+        assert_eq!(numbers, [(10, 5), (11, 5), (12, 6)]);
+
+        #[cfg(feature = "chrono")]
+        {
+            let c = media.segments[2].date_time.unwrap();
+            assert_eq!(c.to_rfc3339(), "2023-03-01T12:00:10.500+00:00");
+        }
+    }
+}
@@ -0,0 +1,342 @@
+use crate::de::from_str;
+use crate::error::{Error, Result};
+use crate::lines::parse_line;
+use serde::de::DeserializeOwned;
+use std::fmt::{self, Display};
+
+/// A line of a playlist that could not be parsed
+#[derive(Debug)]
+pub struct LineError {
+    /// 1-based line number in the input
+    pub line: usize,
+    pub error: Error,
+}
+
+impl Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.error)
+    }
+}
+
+impl std::error::Error for LineError {}
+
+/// Parse a playlist one line at a time, skipping lines that fail to parse
+/// instead of stopping at the first one. Returns everything that did parse
+/// along with an error for each line that didn't, so that every problem in a
+/// playlist can be reported at once.
+pub fn from_str_recovering<T>(s: &str) -> (Vec<T>, Vec<LineError>)
+where
+    T: DeserializeOwned,
+{
+    let (items, warnings) = recover(s, false);
+    let errors = warnings
+        .into_iter()
+        .filter_map(|warning| match warning.kind {
+            WarningKind::Skipped(error) => Some(LineError {
+                line: warning.line,
+                error,
+            }),
+            WarningKind::Repaired { .. } => None,
+        })
+        .collect();
+
+    (items, errors)
+}
+
+/// How forgiving to be of playlists that don't follow the spec
+#[derive(Clone, Debug)]
+pub struct ParseOptions {
+    /// Fail on the first line that doesn't parse. When false, malformed
+    /// lines are repaired where possible and skipped otherwise, each
+    /// leaving a warning behind.
+    pub strict: bool,
+}
+
+impl ParseOptions {
+    pub fn lenient() -> Self {
+        Self { strict: false }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self { strict: true }
+    }
+}
+
+/// The lines of a playlist, along with anything that had to be fixed up or
+/// dropped to parse it
+#[derive(Debug)]
+pub struct Parsed<T> {
+    pub lines: Vec<T>,
+    pub warnings: Vec<ParseWarning>,
+}
+
+#[derive(Debug)]
+pub struct ParseWarning {
+    /// 1-based line number in the input
+    pub line: usize,
+    pub kind: WarningKind,
+}
+
+#[derive(Debug)]
+pub enum WarningKind {
+    /// The line only parsed after being rewritten
+    Repaired { original: String, repaired: String },
+    /// The line could not be parsed at all and was left out
+    Skipped(Error),
+}
+
+impl Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            WarningKind::Repaired { original, repaired } => write!(
+                f,
+                "line {}: read {:?} as {:?}",
+                self.line, original, repaired
+            ),
+            WarningKind::Skipped(error) => write!(f, "line {}: skipped: {}", self.line, error),
+        }
+    }
+}
+
+/// Parse a playlist according to `options`. In lenient mode this only fails
+/// if nothing can be made of the input at all; see [`ParseWarning`] for what
+/// was done to the lines that didn't parse as written.
+pub fn from_str_with_options<T>(s: &str, options: &ParseOptions) -> Result<Parsed<T>>
+where
+    T: DeserializeOwned,
+{
+    if options.strict {
+        return Ok(Parsed {
+            lines: from_str(s)?,
+            warnings: Vec::new(),
+        });
+    }
+
+    let (lines, warnings) = recover(s, true);
+    if lines.is_empty() && !warnings.is_empty() {
+        return Err(Error::Syntax);
+    }
+
+    Ok(Parsed { lines, warnings })
+}
+
+/// Parse each line on its own, noting every line that didn't parse. If
+/// `try_repair` is set, those lines get a second chance after [`repair`] has
+/// rewritten them.
+fn recover<T>(s: &str, try_repair: bool) -> (Vec<T>, Vec<ParseWarning>)
+where
+    T: DeserializeOwned,
+{
+    let mut lines = Vec::new();
+    let mut warnings = Vec::new();
+
+    for (index, line) in s.split_inclusive('\n').enumerate() {
+        let error = match parse_line(line) {
+            Ok(parsed) => {
+                lines.extend(parsed);
+                continue;
+            }
+            Err(error) => error,
+        };
+
+        let repaired = try_repair
+            .then(|| repair(line))
+            .and_then(|repaired| Some((parse_line(&repaired).ok()?, repaired)));
+        let kind = match repaired {
+            Some((parsed, repaired)) => {
+                lines.extend(parsed);
+                WarningKind::Repaired {
+                    original: line.trim_end_matches(['\r', '\n']).to_owned(),
+                    repaired: repaired.trim_end().to_owned(),
+                }
+            }
+            None => WarningKind::Skipped(error),
+        };
+        warnings.push(ParseWarning {
+            line: index + 1,
+            kind,
+        });
+    }
+
+    (lines, warnings)
+}
+
+/// Rewrite the common ways a line bends the spec: trailing whitespace,
+/// lowercase tag and attribute names, decimals such as `5.` or `.5`, and
+/// integers too large to represent, which saturate at `u64::MAX`
+fn repair(line: &str) -> String {
+    let line = line.trim_end();
+    let mut repaired = String::with_capacity(line.len() + 1);
+
+    let is_tag = line
+        .get(..4)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("#EXT"));
+    match line.split_once(':') {
+        Some((name, args)) if is_tag => {
+            repaired.push_str(&name.to_ascii_uppercase());
+            repaired.push(':');
+            if name.eq_ignore_ascii_case("#EXTINF") {
+                let (duration, title) = args.split_once(',').unwrap_or((args, ""));
+                repaired.push_str(&repair_decimal(duration.trim()));
+                repaired.push(',');
+                repaired.push_str(title);
+            } else {
+                repair_attributes(args, &mut repaired);
+            }
+        }
+        None if is_tag => repaired.push_str(&line.to_ascii_uppercase()),
+        _ => repaired.push_str(line),
+    }
+
+    repaired.push('\n');
+    repaired
+}
+
+fn repair_attributes(args: &str, output: &mut String) {
+    // Split on the commas that are outside of quoted strings
+    let mut attrs = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    for (i, c) in args.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                attrs.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    attrs.push(&args[start..]);
+
+    for (i, attr) in attrs.into_iter().enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        match attr.split_once('=') {
+            Some((name, value)) => {
+                let value = value.trim();
+                output.push_str(&name.trim().to_ascii_uppercase());
+                output.push('=');
+                if value.starts_with('"') {
+                    output.push_str(value);
+                } else if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) {
+                    output.push_str(&repair_integer(value));
+                } else {
+                    output.push_str(&repair_decimal(value));
+                }
+            }
+            None => output.push_str(attr.trim()),
+        }
+    }
+}
+
+fn repair_integer(value: &str) -> String {
+    match value.parse::<u64>() {
+        Ok(i) => i.to_string(),
+        Err(_) => u64::MAX.to_string(),
+    }
+}
+
+fn repair_decimal(value: &str) -> String {
+    if !value.contains('.') {
+        return value.to_owned();
+    }
+    match value.parse::<f64>() {
+        Ok(f) if f.is_finite() && f.fract() == 0.0 => format!("{:.1}", f),
+        Ok(f) if f.is_finite() => f.to_string(),
+        _ => value.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::manifest::{Line, Tag};
+
+    #[test]
+    fn reports_every_bad_line() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-MEDIA-SEQUENCE:first\n",
+            "#EXTINF:6.0,\n",
+            "a.ts\n",
+            "\n",
+            "#EXT-X-KEY:METHOD=ROT13\n",
+            "#EXTINF:6.0,\n",
+            "b.ts",
+        );
+        let (lines, errors) = from_str_recovering::<Line>(input);
+
+        assert_eq!(5, lines.len());
+        assert!(matches!(lines[4], Line::Uri(ref uri) if uri == "b.ts"));
+        assert!(!lines
+            .iter()
+            .any(|l| matches!(l, Line::Tag(Tag::MediaSequence(_)))));
+
+        let numbers: Vec<_> = errors.iter().map(|e| e.line).collect();
+        assert_eq!(vec![2, 6], numbers);
+    }
+
+    #[test]
+    fn lenient_mode_repairs_or_skips_bad_lines() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-TARGETDURATION:6  \n",
+            "#EXT-X-KEY:method=AES-128,uri=\"k.bin\"\n",
+            "#EXTINF:5.,\n",
+            "a.ts\n",
+            "#EXT-X-MEDIA-SEQUENCE:first\n",
+            "#EXTINF:06.0,\n",
+            "b.ts\n",
+        );
+
+        assert!(from_str_with_options::<Line>(input, &ParseOptions::default()).is_err());
+
+        let parsed = from_str_with_options::<Line>(input, &ParseOptions::lenient()).unwrap();
+        assert_eq!(7, parsed.lines.len());
+        assert!(matches!(parsed.lines[1], Line::Tag(Tag::Targetduration(6))));
+        assert!(
+            matches!(parsed.lines[2], Line::Tag(Tag::Key(ref k)) if k.uri.as_deref() == Some("k.bin"))
+        );
+        assert!(matches!(parsed.lines[3], Line::Tag(Tag::Inf(d, _)) if d == 5.0));
+        assert!(matches!(parsed.lines[5], Line::Tag(Tag::Inf(d, _)) if d == 6.0));
+
+        let warnings: Vec<_> = parsed
+            .warnings
+            .iter()
+            .map(|w| (w.line, matches!(w.kind, WarningKind::Skipped(_))))
+            .collect();
+        assert_eq!(
+            vec![(2, false), (3, false), (4, false), (6, true), (7, false)],
+            warnings
+        );
+    }
+
+    #[test]
+    fn saturates_oversized_integers_in_lenient_mode() {
+        let input = "#EXT-X-STREAM-INF:BANDWIDTH=184467440737095516151\nlow.m3u8\n";
+
+        assert!(crate::from_str::<Vec<Line>>(input).is_err());
+        assert!(from_str_with_options::<Line>(input, &ParseOptions::default()).is_err());
+
+        let parsed = from_str_with_options::<Line>(input, &ParseOptions::lenient()).unwrap();
+        assert!(matches!(
+            &parsed.lines[0],
+            Line::Tag(Tag::StreamInf(attrs)) if attrs.bandwidth == u64::MAX
+        ));
+        assert_eq!(1, parsed.warnings.len());
+    }
+
+    #[test]
+    fn skips_non_ascii_lines_in_lenient_mode() {
+        let input = "#EXTM3U\n#é\n#EXTINF:6.0,\ncafé.ts\n\u{2603}\u{2603} snow\n";
+
+        let parsed = from_str_with_options::<Line>(input, &ParseOptions::lenient()).unwrap();
+        assert!(matches!(parsed.lines[2], Line::Uri(ref uri) if uri == "café.ts"));
+        let skipped: Vec<_> = parsed.warnings.iter().map(|w| w.line).collect();
+        assert_eq!(vec![5], skipped);
+    }
+}
@@ -0,0 +1,610 @@
+use crate::error::{Error, Result};
+use crate::manifest::Tag;
+use serde::ser::{self, Impossible, Serialize};
+
+/// Write a playlist, or a single line of one, back out in M3U8 syntax. Each
+/// line of a playlist is terminated with a newline.
+pub fn to_string<T>(value: &T) -> Result<String>
+where
+    T: Serialize + ?Sized,
+{
+    value.serialize(LineSerializer)
+}
+
+/// Write a single tag, without a line ending
+pub(crate) fn tag_to_string(tag: &Tag) -> Result<String> {
+    tag.serialize(TagSerializer)
+}
+
+macro_rules! reject {
+    ($($method:ident($($arg:ty),*) -> $ret:ty;)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ret> {
+                Err(unsupported(stringify!($method)))
+            }
+        )*
+    };
+}
+
+fn unsupported(method: &str) -> Error {
+    let what = method.trim_start_matches("serialize_").replace('_', " ");
+    Error::Message(format!("a {} can't be written here", what))
+}
+
+/// Playlist tags are `#EXT-X-` prefixed apart from the two defined by the
+/// original M3U format
+fn tag_name(variant: &str) -> String {
+    match variant {
+        "M3U" | "INF" => format!("#EXT{}", variant),
+        _ => format!("#EXT-X-{}", variant),
+    }
+}
+
+/// Decimal floats always get a fractional part so they read back as floats
+fn format_float(f: f64) -> String {
+    let s = f.to_string();
+    if s.contains('.') {
+        s
+    } else {
+        format!("{}.0", s)
+    }
+}
+
+/// A manifest line, or a sequence of them
+struct LineSerializer;
+
+impl ser::Serializer for LineSerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Lines;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Lines> {
+        Ok(Lines {
+            output: String::with_capacity(len.unwrap_or_default() * 32),
+        })
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<String>
+    where
+        T: Serialize + ?Sized,
+    {
+        let text = || value.serialize(ValueSerializer { quote: false });
+        match variant {
+            "Tag" => value.serialize(TagSerializer),
+            "Uri" => Ok(text()?.unwrap_or_default()),
+            "Comment" => Ok(format!("#{}", text()?.unwrap_or_default())),
+            _ => Err(Error::Message(format!("unknown line type {}", variant))),
+        }
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<String>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<String>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    reject! {
+        serialize_bool(bool) -> String;
+        serialize_i8(i8) -> String;
+        serialize_i16(i16) -> String;
+        serialize_i32(i32) -> String;
+        serialize_i64(i64) -> String;
+        serialize_u8(u8) -> String;
+        serialize_u16(u16) -> String;
+        serialize_u32(u32) -> String;
+        serialize_u64(u64) -> String;
+        serialize_f32(f32) -> String;
+        serialize_f64(f64) -> String;
+        serialize_char(char) -> String;
+        serialize_str(&str) -> String;
+        serialize_bytes(&[u8]) -> String;
+        serialize_none() -> String;
+        serialize_unit() -> String;
+        serialize_unit_struct(&'static str) -> String;
+        serialize_unit_variant(&'static str, u32, &'static str) -> String;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+struct Lines {
+    output: String,
+}
+
+impl ser::SerializeSeq for Lines {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_element<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.output.push_str(&value.serialize(LineSerializer)?);
+        self.output.push('\n');
+        Ok(())
+    }
+
+    fn end(self) -> Result<String> {
+        Ok(self.output)
+    }
+}
+
+/// A tag, named by its enum variant
+struct TagSerializer;
+
+impl ser::Serializer for TagSerializer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = TagTuple;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = Impossible<String, Error>;
+    type SerializeStructVariant = TagStruct;
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<String> {
+        Ok(tag_name(variant))
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<String>
+    where
+        T: Serialize + ?Sized,
+    {
+        match value.serialize(ValueSerializer { quote: false })? {
+            Some(args) => Ok(format!("{}:{}", tag_name(variant), args)),
+            None => Ok(tag_name(variant)),
+        }
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<TagTuple> {
+        Ok(TagTuple {
+            name: tag_name(variant),
+            args: Vec::with_capacity(len),
+        })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<TagStruct> {
+        Ok(TagStruct {
+            variant,
+            fields: Vec::with_capacity(2),
+        })
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<String>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<String>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    reject! {
+        serialize_bool(bool) -> String;
+        serialize_i8(i8) -> String;
+        serialize_i16(i16) -> String;
+        serialize_i32(i32) -> String;
+        serialize_i64(i64) -> String;
+        serialize_u8(u8) -> String;
+        serialize_u16(u16) -> String;
+        serialize_u32(u32) -> String;
+        serialize_u64(u64) -> String;
+        serialize_f32(f32) -> String;
+        serialize_f64(f64) -> String;
+        serialize_char(char) -> String;
+        serialize_str(&str) -> String;
+        serialize_bytes(&[u8]) -> String;
+        serialize_none() -> String;
+        serialize_unit() -> String;
+        serialize_unit_struct(&'static str) -> String;
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_map(Option<usize>) -> Self::SerializeMap;
+        serialize_struct(&'static str, usize) -> Self::SerializeStruct;
+    }
+}
+
+/// A tag with comma separated arguments, such as EXTINF's duration and title
+struct TagTuple {
+    name: String,
+    args: Vec<String>,
+}
+
+impl ser::SerializeTupleVariant for TagTuple {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let arg = value.serialize(ValueSerializer { quote: false })?;
+        self.args.push(arg.unwrap_or_default());
+        Ok(())
+    }
+
+    fn end(self) -> Result<String> {
+        // EXTINF needs its comma even without a title
+        let args = match self.args.as_slice() {
+            [arg] => format!("{},", arg),
+            args => args.join(","),
+        };
+        Ok(format!("{}:{}", self.name, args))
+    }
+}
+
+/// Either an unknown tag, written back with its original name and
+/// arguments, or a tag whose first field holds its arguments and whose
+/// second holds the URI line that follows it
+struct TagStruct {
+    variant: &'static str,
+    fields: Vec<(&'static str, Option<String>)>,
+}
+
+impl TagStruct {
+    fn field(&self, key: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, v)| v.as_deref())
+    }
+}
+
+impl ser::SerializeStructVariant for TagStruct {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let value = value.serialize(ValueSerializer { quote: false })?;
+        self.fields.push((key, value));
+        Ok(())
+    }
+
+    fn end(self) -> Result<String> {
+        if self.variant.eq_ignore_ascii_case("unknown") {
+            let name = self.field("name").ok_or(Error::Syntax)?;
+            return Ok(match self.field("raw_args") {
+                Some(args) => format!("#{}:{}", name, args),
+                None => format!("#{}", name),
+            });
+        }
+
+        let mut fields = self.fields.into_iter().map(|(_, v)| v);
+        let mut line = tag_name(self.variant);
+        if let Some(args) = fields.next().flatten() {
+            line.push(':');
+            line.push_str(&args);
+        }
+        if let Some(uri) = fields.next().flatten() {
+            line.push('\n');
+            line.push_str(&uri);
+        }
+        Ok(line)
+    }
+}
+
+/// A tag argument or attribute value. `None` for absent optional values.
+struct ValueSerializer {
+    quote: bool,
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Option<String>;
+    type Error = Error;
+    type SerializeSeq = Impossible<Option<String>, Error>;
+    type SerializeTuple = Impossible<Option<String>, Error>;
+    type SerializeTupleStruct = Impossible<Option<String>, Error>;
+    type SerializeTupleVariant = Impossible<Option<String>, Error>;
+    type SerializeMap = AttributeList;
+    type SerializeStruct = AttributeList;
+    type SerializeStructVariant = Impossible<Option<String>, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Option<String>> {
+        Ok(Some(if v { "YES" } else { "NO" }.to_owned()))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Option<String>> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Option<String>> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Option<String>> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Option<String>> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Option<String>> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Option<String>> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Option<String>> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Option<String>> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Option<String>> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Option<String>> {
+        Ok(Some(format_float(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Option<String>> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Option<String>> {
+        if self.quote {
+            Ok(Some(format!("\"{}\"", v)))
+        } else {
+            Ok(Some(v.to_owned()))
+        }
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Option<String>> {
+        Ok(Some(format!("0x{}", hex::encode_upper(v))))
+    }
+
+    fn serialize_none(self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn serialize_some<T>(self, value: &T) -> Result<Option<String>>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Option<String>> {
+        Ok(Some(variant.to_owned()))
+    }
+
+    fn serialize_newtype_struct<T>(self, _name: &'static str, value: &T) -> Result<Option<String>>
+    where
+        T: Serialize + ?Sized,
+    {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Option<String>>
+    where
+        T: Serialize + ?Sized,
+    {
+        Err(unsupported("serialize_newtype_variant"))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<AttributeList> {
+        Ok(AttributeList {
+            attrs: Vec::with_capacity(len),
+            key: None,
+        })
+    }
+
+    /// Maps are written as attribute lists too, which is also how flattened
+    /// fields such as client attributes arrive
+    fn serialize_map(self, len: Option<usize>) -> Result<AttributeList> {
+        Ok(AttributeList {
+            attrs: Vec::with_capacity(len.unwrap_or_default()),
+            key: None,
+        })
+    }
+
+    reject! {
+        serialize_seq(Option<usize>) -> Self::SerializeSeq;
+        serialize_tuple(usize) -> Self::SerializeTuple;
+        serialize_tuple_struct(&'static str, usize) -> Self::SerializeTupleStruct;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Self::SerializeTupleVariant;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Self::SerializeStructVariant;
+    }
+}
+
+/// The `NAME=value` attributes of a tag
+struct AttributeList {
+    attrs: Vec<String>,
+    /// The name of a map entry waiting on its value
+    key: Option<String>,
+}
+
+impl AttributeList {
+    fn push<T>(&mut self, key: &str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        // Resolutions are modelled as strings but written bare
+        let quote = key != "RESOLUTION";
+        let value = match value.serialize(ValueSerializer { quote })? {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        // CLOSED-CAPTIONS is a quoted group ID, or the bare keyword NONE
+        if key == "CLOSED-CAPTIONS" && value == "\"NONE\"" {
+            self.attrs.push(format!("{}=NONE", key));
+        } else {
+            self.attrs.push(format!("{}={}", key, value));
+        }
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for AttributeList {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Option<String>> {
+        Ok(Some(self.attrs.join(",")))
+    }
+}
+
+impl ser::SerializeMap for AttributeList {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_key<T>(&mut self, key: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        self.key = key.serialize(ValueSerializer { quote: false })?;
+        Ok(())
+    }
+
+    fn serialize_value<T>(&mut self, value: &T) -> Result<()>
+    where
+        T: Serialize + ?Sized,
+    {
+        let key = self.key.take().ok_or(Error::Syntax)?;
+        self.push(&key, value)
+    }
+
+    fn end(self) -> Result<Option<String>> {
+        Ok(Some(self.attrs.join(",")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::manifest::Line;
+
+    #[test]
+    fn round_trips_playlists() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-VERSION:6\n",
+            "#EXT-X-INDEPENDENT-SEGMENTS\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aac\",NAME=\"English\",DEFAULT=YES\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1280000,RESOLUTION=1920x1080,FRAME-RATE=29.97,CLOSED-CAPTIONS=NONE\n",
+            "hd.m3u8\n",
+            "#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x0123456789ABCDEF0123456789ABCDEF\n",
+            "#EXT-X-PLAYLIST-TYPE:VOD\n",
+            "#EXT-X-BYTERANGE:1024@0\n",
+            "#EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"\n",
+            "#EXTINF:6.006,Title\n",
+            "#EXTINF:6.0,\n",
+            "seg1.ts\n",
+            "#EXT-X-VENDOR-CUE:ID=\"ad-1\",DURATION=30.50,SIZE=1920x1080\n",
+            "#EXT-X-TWITCH-PREFETCH:https://example.com/seg2.ts\n",
+            "#EXT-X-ENDLIST\n",
+        );
+
+        let lines: Vec<Line> = crate::from_str(input).unwrap();
+        assert_eq!(to_string(&lines).unwrap(), input);
+
+        let text: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+        assert_eq!(text, input);
+    }
+
+    #[test]
+    fn keeps_the_prefix_of_unknown_tags() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXTGRP:News\n",
+            "#EXTALB:Live at the Apollo\n",
+            "#EXTVLCOPT:network-caching=1000\n",
+            "#EXT-X-VENDOR-FLAG\n",
+            "#EXTINF:6.0,\n",
+            "seg1.ts\n",
+        );
+
+        let lines: Vec<Line> = crate::from_str(input).unwrap();
+        assert_eq!(to_string(&lines).unwrap(), input);
+    }
+}
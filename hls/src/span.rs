@@ -0,0 +1,148 @@
+use crate::error::{Error, Result};
+use crate::models::{Line, TagArgs};
+use crate::parser::playlist_line;
+use nom::Finish;
+use std::ops::Range;
+
+/// Byte offsets of a piece of manifest text
+pub type Span = Range<usize>;
+
+/// Where each part of a manifest line sits in the source text, for editors
+/// and validators that need to point at it
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineSpan {
+    /// The whole line, without its line ending
+    pub line: Span,
+    pub kind: SpanKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpanKind {
+    Tag {
+        /// The tag itself, e.g. `#EXT-X-KEY`
+        name: Span,
+        /// Everything after the `:`, if the tag has arguments
+        args: Option<Span>,
+        attributes: Vec<AttributeSpan>,
+    },
+    Uri,
+    Comment,
+}
+
+/// An attribute of an attribute list, e.g. `METHOD=AES-128`
+#[derive(Clone, Debug, PartialEq)]
+pub struct AttributeSpan {
+    pub name: Span,
+    /// The value as written, including any quotes
+    pub value: Span,
+}
+
+/// Find the span of every non-blank line of `input`, and of the tags and
+/// attributes on them
+pub fn line_spans(input: &str) -> Result<Vec<LineSpan>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+
+    for (number, text) in input.split_inclusive('\n').enumerate() {
+        let line_start = start;
+        start += text.len();
+
+        // The last line of a file may have no line ending
+        let owned;
+        let text = if text.ends_with('\n') {
+            text
+        } else {
+            owned = format!("{}\n", text);
+            &owned
+        };
+
+        let (_, line) = playlist_line(text)
+            .finish()
+            .map_err(|e| Error::Parse(format!("line {}: {:?}", number + 1, e.code)))?;
+        let Some(line) = line else {
+            continue;
+        };
+
+        let at = |part: &str| {
+            let offset = part.as_ptr() as usize - text.as_ptr() as usize;
+            line_start + offset..line_start + offset + part.len()
+        };
+        let kind = match line {
+            Line::Tag {
+                name,
+                args,
+                raw_args,
+                ..
+            } => SpanKind::Tag {
+                name: line_start..at(name).end,
+                args: raw_args.map(at),
+                attributes: match (args, raw_args) {
+                    (Some(TagArgs::Attributes(attrs)), Some(raw)) => {
+                        let end = at(raw).end;
+                        let names: Vec<_> = attrs.iter().map(|attr| at(attr.name)).collect();
+                        names
+                            .iter()
+                            .enumerate()
+                            .map(|(i, name)| AttributeSpan {
+                                name: name.clone(),
+                                // Values run up to the comma before the next
+                                // attribute's name
+                                value: name.end + 1..names.get(i + 1).map_or(end, |n| n.start - 1),
+                            })
+                            .collect()
+                    }
+                    _ => Vec::new(),
+                },
+            },
+            Line::Uri(_) => SpanKind::Uri,
+            Line::Comment(_) => SpanKind::Comment,
+        };
+
+        let line_end = line_start + text.trim_end_matches(['\r', '\n']).len();
+        spans.push(LineSpan {
+            line: line_start..line_end,
+            kind,
+        });
+    }
+
+    Ok(spans)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn locates_tags_and_attributes() {
+        let input = "#EXTM3U\n\n#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\r\na.ts";
+        let spans = line_spans(input).unwrap();
+        assert_eq!(spans.len(), 3);
+
+        let key = &spans[1];
+        assert_eq!(
+            &input[key.line.clone()],
+            "#EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\""
+        );
+        let SpanKind::Tag {
+            name,
+            args,
+            attributes,
+        } = &key.kind
+        else {
+            panic!("expected a tag");
+        };
+        assert_eq!(&input[name.clone()], "#EXT-X-KEY");
+        assert_eq!(
+            &input[args.clone().unwrap()],
+            "METHOD=AES-128,URI=\"key.bin\""
+        );
+        let text: Vec<_> = attributes
+            .iter()
+            .map(|a| (&input[a.name.clone()], &input[a.value.clone()]))
+            .collect();
+        assert_eq!(text, [("METHOD", "AES-128"), ("URI", "\"key.bin\"")]);
+
+        assert_eq!(spans[2].kind, SpanKind::Uri);
+        assert_eq!(&input[spans[2].line.clone()], "a.ts");
+    }
+}
@@ -0,0 +1,599 @@
+//! Checks for playlists that parse but break rules of the HLS spec. Parsed
+//! lines are checked with [`validate`], which can point at the line with each
+//! problem, and typed playlists with their `validate` methods. Both run the
+//! same checks.
+
+use crate::manifest::{
+    DateRange, EncryptionMethod, IFrameStreamInfAttributes, InstreamId, KeyAttributes, Line,
+    MediaAttributes, MediaType, StreamInfAttributes, Tag,
+};
+use crate::playlist::{MasterPlaylist, MediaPlaylist};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+
+/// How serious a [`Problem`] is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Allowed, but likely to cause trouble for some clients
+    Warning,
+    /// Breaks a MUST or MUST NOT of the spec
+    Error,
+}
+
+/// A spec violation found in a playlist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub severity: Severity,
+    /// Index into the parsed lines of the line with the problem, when
+    /// checking lines with [`validate`]. Blank and comment lines aren't
+    /// parsed, so this is not a line number of the source text.
+    pub index: Option<usize>,
+    pub message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(index) = self.index {
+            write!(f, "entry {}: ", index)?;
+        }
+        write!(f, "{:?}: {}", self.severity, self.message)
+    }
+}
+
+/// Whether a segment duration breaks the rule that EXTINF durations, rounded
+/// to the nearest integer, may not exceed the target duration
+pub fn exceeds_target_duration(duration: f64, target_duration: u64) -> bool {
+    duration.round() > target_duration as f64
+}
+
+/// Check a parsed playlist, returning every problem found
+pub fn validate(lines: &[Line]) -> Vec<Problem> {
+    let mut problems = Problems::default();
+    let mut version = 1;
+    let mut media = MediaChecks::default();
+    for line in lines {
+        match line {
+            Line::Tag(Tag::Version(v)) => version = *v,
+            Line::Tag(Tag::Targetduration(t)) => media.target_duration = Some(*t),
+            Line::Tag(Tag::IFramesOnly) => media.iframes_only = true,
+            Line::Tag(Tag::PartInf(_)) => media.part_inf = true,
+            Line::Tag(Tag::ServerControl(control)) => {
+                media.can_skip = control.can_skip_until.is_some()
+            }
+            _ => {}
+        }
+    }
+    media.version = version;
+    let renditions = lines.iter().filter_map(|line| match line {
+        Line::Tag(Tag::Media(media)) => Some(media),
+        _ => None,
+    });
+    let mut master = MasterChecks::new(version, renditions);
+
+    let mut sequence = 0;
+    for (index, line) in lines.iter().enumerate() {
+        problems.index = Some(index);
+        let Line::Tag(tag) = line else {
+            if let Line::Uri(_) = line {
+                sequence += 1;
+            }
+            continue;
+        };
+
+        match tag {
+            Tag::MediaSequence(s) => sequence = *s,
+            Tag::IFramesOnly => media.iframes_only(&mut problems),
+            Tag::Inf(duration, _) => media.duration(sequence, *duration, &mut problems),
+            Tag::Byterange(_) => media.byterange(&mut problems),
+            Tag::Map(_) => media.map(&mut problems),
+            Tag::Key(key) => check_key(key, version, &mut problems),
+            Tag::Part(_) => media.part(&mut problems),
+            Tag::Skip(_) => media.skip(&mut problems),
+            Tag::Daterange(range) => check_daterange(range, &mut problems),
+            Tag::Media(rendition) => master.rendition(rendition, &mut problems),
+            Tag::StreamInf(attrs) => {
+                let uri = lines[index..].iter().find_map(|line| match line {
+                    Line::Uri(uri) => Some(uri.as_str()),
+                    _ => None,
+                });
+                master.variant(uri.unwrap_or_default(), attrs, &mut problems);
+            }
+            Tag::IFrameStreamInf(attrs) => master.iframe_variant(attrs, &mut problems),
+            _ => {}
+        }
+    }
+
+    problems.found.sort_by_key(|p| p.index);
+    problems.found
+}
+
+/// Check a media playlist against the spec
+pub fn check_media_playlist(playlist: &MediaPlaylist) -> Vec<Problem> {
+    let mut problems = Problems::default();
+    let version = playlist.version.unwrap_or(1);
+    let media = MediaChecks {
+        version,
+        target_duration: Some(playlist.target_duration),
+        iframes_only: playlist.iframes_only,
+        part_inf: playlist.part_target.is_some(),
+        can_skip: playlist.server_control.can_skip_until.is_some(),
+    };
+
+    if playlist.iframes_only {
+        media.iframes_only(&mut problems);
+    }
+    for segment in &playlist.segments {
+        media.duration(segment.sequence, segment.duration, &mut problems);
+        if segment.byterange.is_some() {
+            media.byterange(&mut problems);
+        }
+        if segment.map.is_some() {
+            media.map(&mut problems);
+        }
+        for key in &segment.keys {
+            check_key(key, version, &mut problems);
+        }
+        if !segment.parts.is_empty() {
+            media.part(&mut problems);
+        }
+    }
+    if !playlist.trailing_parts.is_empty() {
+        media.part(&mut problems);
+    }
+    if playlist.skipped_segments > 0 {
+        media.skip(&mut problems);
+    }
+    for range in &playlist.dateranges {
+        check_daterange(range, &mut problems);
+    }
+
+    problems.found
+}
+
+/// Check a master playlist against the spec
+pub fn check_master_playlist(playlist: &MasterPlaylist) -> Vec<Problem> {
+    let mut problems = Problems::default();
+    let version = playlist.version.unwrap_or(1);
+    let mut master = MasterChecks::new(version, playlist.renditions.iter());
+
+    for rendition in &playlist.renditions {
+        master.rendition(rendition, &mut problems);
+    }
+    for variant in &playlist.variants {
+        master.variant(&variant.uri, &variant.attrs, &mut problems);
+    }
+    for variant in &playlist.iframe_variants {
+        master.iframe_variant(variant, &mut problems);
+    }
+
+    problems.found
+}
+
+/// The problems found so far, each put down to the line being checked, if
+/// lines are being checked
+#[derive(Default)]
+struct Problems {
+    found: Vec<Problem>,
+    index: Option<usize>,
+    /// Features already reported as needing a newer version
+    too_new: HashSet<&'static str>,
+}
+
+impl Problems {
+    fn error(&mut self, message: String) {
+        self.push(Severity::Error, message);
+    }
+
+    fn warning(&mut self, message: String) {
+        self.push(Severity::Warning, message);
+    }
+
+    fn push(&mut self, severity: Severity, message: String) {
+        self.found.push(Problem {
+            severity,
+            index: self.index,
+            message,
+        });
+    }
+
+    /// Flag a feature used in a playlist whose EXT-X-VERSION is too old for
+    /// it, the first time it is used
+    fn require_version(&mut self, version: u64, required: u64, feature: &'static str) {
+        if version < required && self.too_new.insert(feature) {
+            self.error(format!(
+                "{} requires version {}, but the playlist is version {}",
+                feature, required, version
+            ));
+        }
+    }
+}
+
+/// Checks of the tags of a media playlist, against what the playlist as a
+/// whole declares
+#[derive(Default)]
+struct MediaChecks {
+    version: u64,
+    target_duration: Option<u64>,
+    iframes_only: bool,
+    /// Whether there is an EXT-X-PART-INF
+    part_inf: bool,
+    /// Whether EXT-X-SERVER-CONTROL has CAN-SKIP-UNTIL
+    can_skip: bool,
+}
+
+impl MediaChecks {
+    fn iframes_only(&self, problems: &mut Problems) {
+        problems.require_version(self.version, 4, "EXT-X-I-FRAMES-ONLY");
+    }
+
+    fn duration(&self, sequence: u64, duration: f64, problems: &mut Problems) {
+        if let Some(target) = self.target_duration {
+            if exceeds_target_duration(duration, target) {
+                problems.error(format!(
+                    "segment {} has duration {}, more than the target duration {}",
+                    sequence, duration, target
+                ));
+            }
+        }
+        if duration.fract() != 0.0 {
+            problems.require_version(self.version, 3, "a decimal EXTINF duration");
+        }
+    }
+
+    fn byterange(&self, problems: &mut Problems) {
+        problems.require_version(self.version, 4, "EXT-X-BYTERANGE");
+    }
+
+    fn map(&self, problems: &mut Problems) {
+        let required = if self.iframes_only { 5 } else { 6 };
+        problems.require_version(self.version, required, "EXT-X-MAP");
+    }
+
+    fn part(&self, problems: &mut Problems) {
+        if !self.part_inf {
+            problems.error("EXT-X-PART without an EXT-X-PART-INF".to_owned());
+        }
+    }
+
+    fn skip(&self, problems: &mut Problems) {
+        if !self.can_skip {
+            problems.error("EXT-X-SKIP in a playlist without CAN-SKIP-UNTIL".to_owned());
+        }
+    }
+}
+
+fn check_key(key: &KeyAttributes, version: u64, problems: &mut Problems) {
+    if key.iv.is_some() {
+        problems.require_version(version, 2, "the IV attribute of EXT-X-KEY");
+    }
+    if key.keyformat.is_some() || key.keyformatversions.is_some() {
+        problems.require_version(version, 5, "KEYFORMAT and KEYFORMATVERSIONS");
+    }
+    if key.method == EncryptionMethod::SampleAes {
+        problems.require_version(version, 5, "METHOD=SAMPLE-AES");
+    }
+}
+
+fn check_daterange(range: &DateRange, problems: &mut Problems) {
+    if range.end_on_next == Some(true) {
+        if range.duration.is_some() || range.end_date.is_some() {
+            problems.error(format!(
+                "daterange '{}' has END-ON-NEXT along with DURATION or END-DATE",
+                range.id
+            ));
+        }
+        if range.class.is_none() {
+            problems.error(format!(
+                "daterange '{}' has END-ON-NEXT but no CLASS",
+                range.id
+            ));
+        }
+    }
+}
+
+/// Checks of the renditions and variants of a master playlist, including
+/// the references between them
+struct MasterChecks<'a> {
+    version: u64,
+    /// Every rendition group, by media type
+    groups: HashSet<(MediaType, &'a str)>,
+    /// The groups and languages seen with a DEFAULT=YES rendition
+    defaults: HashSet<(MediaType, &'a str, Option<&'a str>)>,
+    /// The URI of the first variant seen with each bandwidth, with I-frame
+    /// variants kept apart
+    bandwidths: HashMap<(bool, u64), &'a str>,
+}
+
+impl<'a> MasterChecks<'a> {
+    fn new(version: u64, renditions: impl Iterator<Item = &'a MediaAttributes>) -> Self {
+        Self {
+            version,
+            groups: renditions
+                .map(|r| (r.media_type, r.group_id.as_str()))
+                .collect(),
+            defaults: HashSet::new(),
+            bandwidths: HashMap::new(),
+        }
+    }
+
+    fn rendition(&mut self, rendition: &'a MediaAttributes, problems: &mut Problems) {
+        let closed_captions = rendition.media_type == MediaType::ClosedCaptions;
+        match (closed_captions, rendition.instream_id) {
+            (true, None) => problems.error(format!(
+                "CLOSED-CAPTIONS rendition '{}' is missing INSTREAM-ID",
+                rendition.name
+            )),
+            (false, Some(_)) => problems.error(format!(
+                "rendition '{}' has INSTREAM-ID but isn't CLOSED-CAPTIONS",
+                rendition.name
+            )),
+            (true, Some(InstreamId::Service(_))) => {
+                problems.require_version(self.version, 7, "an INSTREAM-ID of SERVICEn")
+            }
+            _ => {}
+        }
+        if closed_captions && rendition.uri.is_some() {
+            problems.error(format!(
+                "CLOSED-CAPTIONS rendition '{}' must not have a URI",
+                rendition.name
+            ));
+        }
+
+        if rendition.default == Some(true) {
+            let key = (
+                rendition.media_type,
+                rendition.group_id.as_str(),
+                rendition.language.as_deref(),
+            );
+            if !self.defaults.insert(key) {
+                problems.error(format!(
+                    "group '{}' has more than one DEFAULT=YES rendition for language {}",
+                    rendition.group_id,
+                    rendition.language.as_deref().unwrap_or("(none)")
+                ));
+            }
+        }
+    }
+
+    fn variant(&mut self, uri: &'a str, attrs: &'a StreamInfAttributes, problems: &mut Problems) {
+        self.bandwidth(
+            uri,
+            false,
+            attrs.bandwidth,
+            attrs.average_bandwidth,
+            problems,
+        );
+        if attrs.codecs.is_none() {
+            problems.warning(format!("variant {} has no CODECS", uri));
+        }
+
+        let references = [
+            (MediaType::Audio, &attrs.audio),
+            (MediaType::Video, &attrs.video),
+            (MediaType::Subtitles, &attrs.subtitles),
+            (MediaType::ClosedCaptions, &attrs.closed_captions),
+        ];
+        for (media_type, group) in references {
+            self.reference(uri, media_type, group.as_deref(), problems);
+        }
+    }
+
+    fn iframe_variant(&mut self, attrs: &'a IFrameStreamInfAttributes, problems: &mut Problems) {
+        let uri = attrs.uri.as_str();
+        self.bandwidth(
+            uri,
+            true,
+            attrs.bandwidth,
+            attrs.average_bandwidth,
+            problems,
+        );
+        self.reference(uri, MediaType::Video, attrs.video.as_deref(), problems);
+    }
+
+    fn bandwidth(
+        &mut self,
+        uri: &'a str,
+        is_iframe: bool,
+        bandwidth: u64,
+        average_bandwidth: Option<u64>,
+        problems: &mut Problems,
+    ) {
+        if bandwidth == 0 {
+            problems.error(format!("variant {} has a BANDWIDTH of 0", uri));
+        }
+        if average_bandwidth.is_some_and(|average| average > bandwidth) {
+            problems.error(format!(
+                "variant {} has an AVERAGE-BANDWIDTH greater than its BANDWIDTH",
+                uri
+            ));
+        }
+        if let Some(other) = self.bandwidths.insert((is_iframe, bandwidth), uri) {
+            problems.warning(format!(
+                "variant {} has the same BANDWIDTH as {}",
+                uri, other
+            ));
+        }
+    }
+
+    fn reference(
+        &self,
+        uri: &str,
+        media_type: MediaType,
+        group: Option<&str>,
+        problems: &mut Problems,
+    ) {
+        match group {
+            // CLOSED-CAPTIONS=NONE explicitly opts out of captions
+            Some("NONE") if media_type == MediaType::ClosedCaptions => {}
+            Some(group) if !self.groups.contains(&(media_type, group)) => problems.error(format!(
+                "variant {} references undefined {:?} group '{}'",
+                uri, media_type, group
+            )),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn checks_instream_id_against_media_type() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-VERSION:7\n",
+            "#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"English\",INSTREAM-ID=\"SERVICE12\"\n",
+            "#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"Spanish\"\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",INSTREAM-ID=\"CC1\"\n",
+        );
+        let lines: Vec<Line> = crate::from_str(input).unwrap();
+
+        assert!(matches!(
+            &lines[2],
+            Line::Tag(Tag::Media(m)) if m.instream_id == Some(InstreamId::Service(12))
+        ));
+        let indices: Vec<_> = validate(&lines).iter().map(|p| p.index).collect();
+        assert_eq!(vec![Some(3), Some(4)], indices);
+
+        let input = "#EXTM3U\n#EXT-X-MEDIA:TYPE=CLOSED-CAPTIONS,GROUP-ID=\"cc\",NAME=\"x\",INSTREAM-ID=\"CC5\"\n";
+        assert!(crate::from_str::<Vec<Line>>(input).is_err());
+    }
+
+    #[test]
+    fn flags_segments_longer_than_target_duration() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-VERSION:3\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXTINF:6.499,\n",
+            "a.ts\n",
+            "#EXTINF:6.5,\n",
+            "b.ts\n",
+        );
+        let lines: Vec<Line> = crate::from_str(input).unwrap();
+
+        let indices: Vec<_> = validate(&lines).iter().map(|p| p.index).collect();
+        assert_eq!(vec![Some(5)], indices);
+    }
+
+    #[test]
+    fn cross_references_master_playlists() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"A\",LANGUAGE=\"en\",DEFAULT=YES\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"B\",LANGUAGE=\"en\",DEFAULT=YES\n",
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"C\",LANGUAGE=\"fr\",DEFAULT=YES\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1000,CODECS=\"avc1.4d401f\",AUDIO=\"aud\"\n",
+            "low.m3u8\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1000,AVERAGE-BANDWIDTH=2000,CODECS=\"avc1.4d401f\",SUBTITLES=\"subs\"\n",
+            "high.m3u8\n",
+        );
+        let lines: Vec<Line> = crate::from_str(input).unwrap();
+
+        let problems = validate(&lines);
+        let found: Vec<_> = problems.iter().map(|p| (p.index, p.severity)).collect();
+        assert_eq!(
+            vec![
+                (Some(2), Severity::Error),
+                (Some(6), Severity::Error),
+                (Some(6), Severity::Warning),
+                (Some(6), Severity::Error),
+            ],
+            found,
+            "{:?}",
+            problems
+        );
+        assert_eq!(
+            "variant high.m3u8 has the same BANDWIDTH as low.m3u8",
+            problems[2].message
+        );
+
+        // The typed playlist finds the same problems
+        let playlist = MasterPlaylist::parse(input).unwrap();
+        let mut typed: Vec<_> = check_master_playlist(&playlist)
+            .into_iter()
+            .map(|p| p.message)
+            .collect();
+        let mut messages: Vec<_> = problems.into_iter().map(|p| p.message).collect();
+        typed.sort();
+        messages.sort();
+        assert_eq!(messages, typed);
+    }
+
+    #[test]
+    fn checks_typed_playlists() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-VERSION:3\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXT-X-DATERANGE:ID=\"ad\",START-DATE=\"2020-01-01T00:00:00Z\",DURATION=5.0,END-ON-NEXT=YES\n",
+            "#EXT-X-MAP:URI=\"init.mp4\"\n",
+            "#EXTINF:7.0,\n",
+            "a.ts\n",
+        );
+        let playlist = MediaPlaylist::parse(input).unwrap();
+
+        let mut messages: Vec<_> = playlist
+            .validate()
+            .into_iter()
+            .map(|v| {
+                assert_eq!(Severity::Error, v.severity);
+                v.message
+            })
+            .collect();
+        messages.sort();
+        assert_eq!(4, messages.len(), "{:?}", messages);
+        assert!(messages[0].starts_with("EXT-X-MAP requires version 6"));
+        assert!(messages[1].contains("END-ON-NEXT along with DURATION"));
+        assert!(messages[2].contains("END-ON-NEXT but no CLASS"));
+        assert!(messages[3].starts_with("segment 0 has duration 7"));
+
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-STREAM-INF:BANDWIDTH=1000,AUDIO=\"aud\"\n",
+            "low.m3u8\n",
+        );
+        let playlist = MasterPlaylist::parse(input).unwrap();
+        let severities: Vec<_> = playlist.validate().iter().map(|v| v.severity).collect();
+        assert_eq!(vec![Severity::Warning, Severity::Error], severities);
+    }
+
+    #[test]
+    fn labels_problems_with_their_entry() {
+        let input = "#EXTM3U\n# comment\n\n#EXT-X-TARGETDURATION:6\n#EXTINF:9.0,\na.ts\n";
+        let lines: Vec<Line> = crate::from_str(input).unwrap();
+
+        let problems = validate(&lines);
+        assert_eq!(1, problems.len());
+        assert!(matches!(
+            lines[problems[0].index.unwrap()],
+            Line::Tag(Tag::Inf(..))
+        ));
+        assert!(problems[0].to_string().starts_with("entry 2: Error: "));
+    }
+
+    #[test]
+    fn reports_each_version_requirement_once() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-VERSION:2\n",
+            "#EXT-X-TARGETDURATION:6\n",
+            "#EXTINF:5.5,\n",
+            "a.ts\n",
+            "#EXT-X-BYTERANGE:100@0\n",
+            "#EXTINF:5.5,\n",
+            "b.ts\n",
+            "#EXT-X-BYTERANGE:100\n",
+            "#EXTINF:5.5,\n",
+            "b.ts\n",
+        );
+        let lines: Vec<Line> = crate::from_str(input).unwrap();
+
+        let indices: Vec<_> = validate(&lines).iter().map(|p| p.index).collect();
+        assert_eq!(vec![Some(3), Some(5)], indices);
+
+        let playlist = MediaPlaylist::parse(input).unwrap();
+        assert_eq!(2, playlist.validate().len());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::de::from_str;
+use crate::error::{Error, Result};
+use crate::manifest::{Line, Tag};
+use std::collections::HashMap;
+
+/// Where EXT-X-DEFINE takes the values of IMPORT and QUERYPARAM variables
+/// from
+#[derive(Debug, Default)]
+pub struct DefineContext<'a> {
+    /// Variables defined by the master playlist, for IMPORT
+    pub imports: HashMap<String, String>,
+    /// Query string of the playlist's URL, for QUERYPARAM. Values are used
+    /// as they appear, without percent-decoding.
+    pub query: Option<&'a str>,
+}
+
+/// Resolve EXT-X-DEFINE variables, replacing each `{$name}` in URI lines and
+/// quoted attribute values with its value. Returns the substituted playlist
+/// along with the variables it defined, which a master playlist's media
+/// playlists can IMPORT.
+pub fn substitute_variables(
+    input: &str,
+    context: &DefineContext,
+) -> Result<(String, HashMap<String, String>)> {
+    let mut variables = HashMap::new();
+    let mut output = String::with_capacity(input.len());
+
+    for line in input.split_inclusive('\n') {
+        if line.starts_with("#EXT-X-DEFINE:") {
+            let (name, value) = define(line, context)?;
+            variables.insert(name, value);
+            output.push_str(line);
+        } else if line.starts_with("#EXT") {
+            substitute_quoted(line, &variables, &mut output)?;
+        } else if line.starts_with('#') {
+            output.push_str(line);
+        } else {
+            substitute(line, &variables, &mut output)?;
+        }
+    }
+
+    Ok((output, variables))
+}
+
+fn define(line: &str, context: &DefineContext) -> Result<(String, String)> {
+    let line = format!("{}\n", line.trim_end());
+    let attrs = match from_str::<Vec<Line>>(&line)?.pop() {
+        Some(Line::Tag(Tag::Define(attrs))) => attrs,
+        _ => return Err(Error::Syntax),
+    };
+
+    let missing = |name: &str| Error::Message(format!("variable {} is not available", name));
+    match (attrs.name, attrs.value, attrs.import, attrs.queryparam) {
+        (Some(name), Some(value), None, None) => Ok((name, value)),
+        (None, None, Some(name), None) => {
+            let value = context.imports.get(&name).ok_or_else(|| missing(&name))?;
+            Ok((name.clone(), value.clone()))
+        }
+        (None, None, None, Some(name)) => {
+            let value = context
+                .query
+                .unwrap_or_default()
+                .split('&')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_owned())
+                .ok_or_else(|| missing(&name))?;
+            Ok((name, value))
+        }
+        _ => Err(Error::Message(format!(
+            "invalid EXT-X-DEFINE: {}",
+            line.trim_end()
+        ))),
+    }
+}
+
+/// Substitute only inside the quoted strings of a tag line
+fn substitute_quoted(
+    line: &str,
+    variables: &HashMap<String, String>,
+    output: &mut String,
+) -> Result<()> {
+    for (i, part) in line.split('"').enumerate() {
+        if i > 0 {
+            output.push('"');
+        }
+        if i % 2 == 1 {
+            substitute(part, variables, output)?;
+        } else {
+            output.push_str(part);
+        }
+    }
+    Ok(())
+}
+
+fn substitute(text: &str, variables: &HashMap<String, String>, output: &mut String) -> Result<()> {
+    let mut rest = text;
+    while let Some(start) = rest.find("{$") {
+        output.push_str(&rest[..start]);
+        let end = rest[start..].find('}').ok_or(Error::Syntax)?;
+        let name = &rest[start + 2..start + end];
+        let value = variables
+            .get(name)
+            .ok_or_else(|| Error::Message(format!("undefined variable {}", name)))?;
+        output.push_str(value);
+        rest = &rest[start + end + 1..];
+    }
+    output.push_str(rest);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn substitutes_defined_variables() {
+        let input = concat!(
+            "#EXTM3U\n",
+            "#EXT-X-DEFINE:NAME=\"cdn\",VALUE=\"https://cdn.example.com\"\n",
+            "#EXT-X-DEFINE:IMPORT=\"session\"\n",
+            "#EXT-X-DEFINE:QUERYPARAM=\"token\"\n",
+            "#EXT-X-MAP:URI=\"{$cdn}/init.mp4?s={$session}\"\n",
+            "#EXTINF:6.0,\n",
+            "{$cdn}/seg1.m4s?token={$token}\n",
+        );
+        let context = DefineContext {
+            imports: HashMap::from([("session".to_owned(), "abc".to_owned())]),
+            query: Some("a=1&token=xyz"),
+        };
+
+        let (output, variables) = substitute_variables(input, &context).unwrap();
+        assert!(output.contains("#EXT-X-MAP:URI=\"https://cdn.example.com/init.mp4?s=abc\"\n"));
+        assert!(output.ends_with("https://cdn.example.com/seg1.m4s?token=xyz\n"));
+        assert_eq!(variables.len(), 3);
+
+        let undefined = "#EXTINF:6.0,\n{$nope}.ts\n";
+        assert!(substitute_variables(undefined, &DefineContext::default()).is_err());
+    }
+}